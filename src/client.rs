@@ -0,0 +1,260 @@
+//! Instruction builders and PDA derivations for off-chain integrators.
+//! Only compiled with the `client` feature.
+
+use borsh::BorshSerialize;
+use solana_program::{
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+    system_program,
+};
+
+use crate::instruction::{EscrowInstruction, EscrowOptions};
+
+pub fn counter_pda(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"counter"], program_id)
+}
+
+/// Escrow created through the global counter
+pub fn escrow_pda(program_id: &Pubkey, escrow_id: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"escrow", &escrow_id.to_le_bytes()], program_id)
+}
+
+/// Escrow keyed by its creator, as made by `CreateEscrowV2` and `CreateEscrowWithNonce`
+pub fn creator_escrow_pda(program_id: &Pubkey, creator: &Pubkey, escrow_id: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[b"escrow", creator.as_ref(), &escrow_id.to_le_bytes()],
+        program_id,
+    )
+}
+
+pub fn creator_stats_pda(program_id: &Pubkey, creator: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"creator", creator.as_ref()], program_id)
+}
+
+pub fn beneficiary_index_pda(program_id: &Pubkey, beneficiary: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"beneficiary", beneficiary.as_ref()], program_id)
+}
+
+pub fn wsol_pda(program_id: &Pubkey, escrow: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"wsol", escrow.as_ref()], program_id)
+}
+
+pub fn idempotency_marker_pda(
+    program_id: &Pubkey,
+    creator: &Pubkey,
+    key: &[u8; 16],
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"idempotency", creator.as_ref(), key], program_id)
+}
+
+fn build(
+    program_id: &Pubkey,
+    instruction: &EscrowInstruction,
+    accounts: Vec<AccountMeta>,
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data: instruction.try_to_vec().expect("instruction serializes"),
+    }
+}
+
+pub fn initialize_ix(program_id: &Pubkey, authority: &Pubkey) -> Instruction {
+    build(
+        program_id,
+        &EscrowInstruction::Initialize,
+        vec![
+            AccountMeta::new(*authority, true),
+            AccountMeta::new(counter_pda(program_id).0, false),
+            AccountMeta::new_readonly(system_program::ID, false),
+        ],
+    )
+}
+
+/// `CreateEscrow` for an unwrapped escrow. `escrow_id` is the id the counter
+/// hands out next, one past its current `count`. The idempotency marker is
+/// appended when `options` carries a key
+#[allow(clippy::too_many_arguments)]
+pub fn create_escrow_ix(
+    program_id: &Pubkey,
+    creator: &Pubkey,
+    escrow_id: u64,
+    amount: u64,
+    beneficiary: &Pubkey,
+    approver1: &Pubkey,
+    approver2: &Pubkey,
+    approver3: Option<Pubkey>,
+    description: String,
+    options: EscrowOptions,
+) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new(*creator, true),
+        AccountMeta::new(escrow_pda(program_id, escrow_id).0, false),
+        AccountMeta::new(counter_pda(program_id).0, false),
+        AccountMeta::new_readonly(system_program::ID, false),
+        AccountMeta::new(creator_stats_pda(program_id, creator).0, false),
+        AccountMeta::new(beneficiary_index_pda(program_id, beneficiary).0, false),
+    ];
+    if let Some(key) = &options.idempotency_key {
+        accounts.push(AccountMeta::new(idempotency_marker_pda(program_id, creator, key).0, false));
+    }
+    build(
+        program_id,
+        &EscrowInstruction::CreateEscrow {
+            amount,
+            beneficiary: *beneficiary,
+            approver1: *approver1,
+            approver2: *approver2,
+            approver3,
+            description,
+            options,
+        },
+        accounts,
+    )
+}
+
+/// `ApproveRelease` for an unwrapped escrow
+pub fn approve_release_ix(
+    program_id: &Pubkey,
+    approver: &Pubkey,
+    escrow: &Pubkey,
+    beneficiary: &Pubkey,
+    creator: &Pubkey,
+) -> Instruction {
+    build(
+        program_id,
+        &EscrowInstruction::ApproveRelease,
+        vec![
+            AccountMeta::new_readonly(*approver, true),
+            AccountMeta::new(*escrow, false),
+            AccountMeta::new(*beneficiary, false),
+            AccountMeta::new_readonly(system_program::ID, false),
+            AccountMeta::new(creator_stats_pda(program_id, creator).0, false),
+        ],
+    )
+}
+
+/// `FinalizeRelease` for an unwrapped escrow, with its executor when it has one
+pub fn finalize_release_ix(
+    program_id: &Pubkey,
+    escrow: &Pubkey,
+    beneficiary: &Pubkey,
+    creator: &Pubkey,
+    executor: Option<Pubkey>,
+) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new(*escrow, false),
+        AccountMeta::new(*beneficiary, false),
+        AccountMeta::new(creator_stats_pda(program_id, creator).0, false),
+    ];
+    if let Some(executor) = executor {
+        accounts.push(AccountMeta::new_readonly(executor, true));
+    }
+    build(program_id, &EscrowInstruction::FinalizeRelease, accounts)
+}
+
+/// `CancelEscrow` for an unwrapped escrow refunding to the creator
+pub fn cancel_escrow_ix(program_id: &Pubkey, creator: &Pubkey, escrow: &Pubkey) -> Instruction {
+    build(
+        program_id,
+        &EscrowInstruction::CancelEscrow,
+        vec![
+            AccountMeta::new(*creator, true),
+            AccountMeta::new(*escrow, false),
+            AccountMeta::new_readonly(system_program::ID, false),
+            AccountMeta::new(creator_stats_pda(program_id, creator).0, false),
+        ],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn create_escrow_ix_round_trips() {
+        let program_id = Pubkey::new_unique();
+        let creator = Pubkey::new_unique();
+        let beneficiary = Pubkey::new_unique();
+        let (approver1, approver2) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let ix = create_escrow_ix(
+            &program_id,
+            &creator,
+            4,
+            1_000,
+            &beneficiary,
+            &approver1,
+            &approver2,
+            None,
+            "desc".to_string(),
+            EscrowOptions::default(),
+        );
+
+        assert_eq!(ix.program_id, program_id);
+        assert_eq!(
+            EscrowInstruction::unpack(&ix.data).unwrap(),
+            EscrowInstruction::CreateEscrow {
+                amount: 1_000,
+                beneficiary,
+                approver1,
+                approver2,
+                approver3: None,
+                description: "desc".to_string(),
+                options: EscrowOptions::default(),
+            }
+        );
+        let tag = EscrowInstruction::discriminator(&ix.data).unwrap();
+        assert_eq!(ix.accounts.len(), EscrowInstruction::min_accounts(tag));
+        assert_eq!(ix.accounts[1].pubkey, escrow_pda(&program_id, 4).0);
+        assert!(ix.accounts[0].is_signer);
+    }
+
+    #[test]
+    fn release_and_cancel_ixs_round_trip() {
+        let program_id = Pubkey::new_unique();
+        let creator = Pubkey::new_unique();
+        let approver = Pubkey::new_unique();
+        let escrow = Pubkey::new_unique();
+        let beneficiary = Pubkey::new_unique();
+        let cases = [
+            (initialize_ix(&program_id, &creator), EscrowInstruction::Initialize),
+            (
+                approve_release_ix(&program_id, &approver, &escrow, &beneficiary, &creator),
+                EscrowInstruction::ApproveRelease,
+            ),
+            (
+                finalize_release_ix(&program_id, &escrow, &beneficiary, &creator, None),
+                EscrowInstruction::FinalizeRelease,
+            ),
+            (cancel_escrow_ix(&program_id, &creator, &escrow), EscrowInstruction::CancelEscrow),
+        ];
+        for (ix, expected) in cases {
+            assert_eq!(EscrowInstruction::unpack(&ix.data).unwrap(), expected);
+            let tag = EscrowInstruction::discriminator(&ix.data).unwrap();
+            assert!(ix.accounts.len() >= EscrowInstruction::min_accounts(tag));
+        }
+    }
+
+    #[test]
+    fn create_escrow_ix_appends_idempotency_marker() {
+        let program_id = Pubkey::new_unique();
+        let creator = Pubkey::new_unique();
+        let key = [7; 16];
+        let ix = create_escrow_ix(
+            &program_id,
+            &creator,
+            1,
+            1_000,
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            None,
+            String::new(),
+            EscrowOptions { idempotency_key: Some(key), ..EscrowOptions::default() },
+        );
+
+        assert_eq!(ix.accounts.len(), 7);
+        assert_eq!(ix.accounts[6].pubkey, idempotency_marker_pda(&program_id, &creator, &key).0);
+        assert!(ix.accounts[6].is_writable);
+    }
+}
@@ -35,6 +35,126 @@ pub enum EscrowError {
 
     #[error("Invalid counter account")]
     InvalidCounterAccount,
+
+    #[error("Already initialized")]
+    AlreadyInitialized,
+
+    #[error("Note limit exceeded")]
+    NoteLimitExceeded,
+
+    #[error("Escrow declined by beneficiary")]
+    EscrowDeclined,
+
+    #[error("Too many active escrows for creator")]
+    TooManyEscrows,
+
+    #[error("Invalid creator stats account")]
+    InvalidCreatorStatsAccount,
+
+    #[error("Invalid approver weight")]
+    InvalidWeight,
+
+    #[error("Invalid refund account")]
+    InvalidRefundAccount,
+
+    #[error("Escrow is frozen")]
+    EscrowFrozen,
+
+    #[error("Invalid approver")]
+    InvalidApprover,
+
+    #[error("Invalid beneficiary")]
+    InvalidBeneficiary,
+
+    #[error("Approval limit reached")]
+    ApprovalLimitReached,
+
+    #[error("Invalid wrapped SOL account")]
+    InvalidWrappedSolAccount,
+
+    #[error("Not supported for wrapped SOL escrows")]
+    UnsupportedForWrappedSol,
+
+    #[error("Invalid beneficiary index account")]
+    InvalidBeneficiaryIndexAccount,
+
+    #[error("Escrow id counter exhausted")]
+    CounterExhausted,
+
+    #[error("Escrow not releasable yet")]
+    EscrowNotReleasable,
+
+    #[error("Escrow too young to release")]
+    EscrowTooYoung,
+
+    #[error("Invalid delegate")]
+    InvalidDelegate,
+
+    #[error("Invalid approver categories")]
+    InvalidCategories,
+
+    #[error("Beneficiary account not found")]
+    BeneficiaryNotFound,
+
+    #[error("Escrows have different terms")]
+    IncompatibleEscrows,
+
+    #[error("Proof limit exceeded")]
+    ProofLimitExceeded,
+
+    #[error("Mint mismatch")]
+    MintMismatch,
+
+    #[error("Invalid cancel penalty")]
+    InvalidCancelPenalty,
+
+    #[error("Invalid approval threshold")]
+    InvalidThreshold,
+
+    #[error("Escrow cannot be cancelled yet")]
+    CancelLocked,
+
+    #[error("Nothing to release")]
+    NothingToRelease,
+
+    #[error("Escrow still holds funds")]
+    EscrowStillFunded,
+
+    #[error("Arbiter overlaps another escrow role")]
+    RoleConflict,
+
+    #[error("Unexpected extra accounts")]
+    UnexpectedAccounts,
+
+    #[error("Approval out of order")]
+    OutOfOrderApproval,
+
+    #[error("Forfeited funds must go to the incinerator")]
+    InvalidIncinerator,
+
+    #[error("Deadline in the past or beyond the allowed horizon")]
+    InvalidDeadline,
+
+    #[error("Escrow id counter holds an impossible count")]
+    CounterCorrupt,
+
+    #[error("Approver must wait more slots before approving again")]
+    ApprovalRateLimited,
+
+    #[error("Fee treasury must be the counter authority")]
+    InvalidTreasury,
+
+    #[error("Invalid token vault or token account")]
+    InvalidTokenAccount,
+
+    #[error("Not supported for SPL token escrows")]
+    UnsupportedForTokenEscrow,
+
+    #[error("Price feed is not a trading Pyth price account")]
+    InvalidPriceFeed,
+
+    #[error("Oracle price is too old")]
+    StalePrice,
 }
 
 impl From<EscrowError> for ProgramError {
@@ -1,6 +1,67 @@
 use borsh::{BorshDeserialize, BorshSerialize};
 use solana_program::{program_error::ProgramError, pubkey::Pubkey};
 
+use crate::error::EscrowError;
+
+/// Optional escrow terms supplied at creation
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq, Default)]
+pub struct EscrowOptions {
+    /// Voting power per approver slot, defaults to 1 each
+    pub weights: Option<[u16; 3]>,
+    /// Refund destination on cancellation, defaults to the creator
+    pub refund_to: Option<Pubkey>,
+    /// Third party allowed to freeze the escrow
+    pub arbiter: Option<Pubkey>,
+    /// Hold the escrowed SOL as WSOL in a token account owned by the escrow PDA
+    pub wrap_sol: bool,
+    /// Seconds between reaching quorum and the funds becoming releasable, 0 releases immediately
+    pub release_delay_secs: i64,
+    /// Minimum seconds between creation and release, 0 allows same-block release
+    pub min_age_secs: i64,
+    /// Seconds an approval counts toward quorum before it must be re-submitted, 0 never expires
+    pub approval_ttl_secs: i64,
+    /// Category tag (0-7) per approver slot
+    pub categories: [u8; 3],
+    /// Bitmask of categories that must each be represented among the approvals
+    pub required_categories: u8,
+    /// Refuse to release to a beneficiary account that does not exist yet
+    pub require_existing_beneficiary: bool,
+    /// Share of the amount, in basis points, paid to the beneficiary on a consent cancel
+    pub cancel_penalty_bps: u16,
+    /// Store approvals sorted by approver rather than in arrival order
+    pub sort_approvals: bool,
+    /// Seconds after creation during which the creator cannot cancel
+    pub cancel_lock_secs: i64,
+    /// Hold release until the beneficiary acknowledges the escrow
+    pub require_ack: bool,
+    /// Approvers beyond the three slots, at most `Escrow::MAX_EXTRA_APPROVERS`
+    pub extra_approvers: Vec<Pubkey>,
+    /// Approvals required for release, 0 for the default rule
+    pub threshold: u8,
+    /// Settlement agent who must sign the release, leaving it to `FinalizeRelease`
+    pub executor: Option<Pubkey>,
+    /// Seconds after creation from which keepers may refund the escrow, 0 never.
+    /// At most the counter's `max_expiry_secs`
+    pub expiry_secs: i64,
+    /// Parent agreement identifier shared by bundled escrows
+    pub group_id: Option<[u8; 32]>,
+    /// Approvals must arrive in approver1, approver2, approver3, extra approvers order
+    pub approval_order: bool,
+    /// Client-chosen key that, with the creator, makes a retried create return the
+    /// escrow id it already created instead of creating another
+    pub idempotency_key: Option<[u8; 16]>,
+    /// Slots each approver must wait between approvals, 0 for no limit
+    pub approval_rate_limit_slots: u64,
+    /// Escrow `amount` base units of this SPL mint instead of lamports, held in a
+    /// token vault owned by the escrow PDA. Not combinable with `wrap_sol`
+    pub token_mint: Option<Pubkey>,
+    /// Release the lamport value of this many US cents at the release-time price,
+    /// capped at `amount`, refunding the rest to the creator. Lamport escrows only
+    pub target_usd: Option<u64>,
+    /// Pyth SOL/USD price account the release is priced by, required with `target_usd`
+    pub price_feed: Option<Pubkey>,
+}
+
 #[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
 pub enum EscrowInstruction {
     /// Initialize the escrow counter
@@ -16,6 +77,16 @@ pub enum EscrowInstruction {
     /// 1. `[writable]` Escrow account (PDA)
     /// 2. `[writable]` Counter account (PDA)
     /// 3. `[]` System program
+    /// 4. `[writable]` Creator stats account (PDA)
+    /// 5. `[writable]` Beneficiary index account (PDA)
+    /// 6. `[writable]` WSOL account (PDA), only with `wrap_sol`
+    /// 7. `[]` Native mint, only with `wrap_sol`
+    /// 8. `[]` Token program, only with `wrap_sol`
+    /// 6-9. Creator token account, token vault (PDA), mint and token program in
+    ///    place of the WSOL accounts, only with `token_mint`
+    /// 6, 9 or 10. `[writable]` Idempotency marker (PDA), last, only with `idempotency_key`
+    ///
+    /// Returns the new escrow id as little-endian `u64` return data
     CreateEscrow {
         amount: u64,
         beneficiary: Pubkey,
@@ -23,14 +94,25 @@ pub enum EscrowInstruction {
         approver2: Pubkey,
         approver3: Option<Pubkey>,
         description: String,
+        options: EscrowOptions,
     },
 
     /// Approve release of funds
     /// Accounts expected:
-    /// 0. `[signer]` Approver account
+    /// 0. `[signer]` Approver account, or its delegate
     /// 1. `[writable]` Escrow account (PDA)
     /// 2. `[writable]` Beneficiary account
     /// 3. `[]` System program
+    /// 4. `[writable]` Creator stats account (PDA)
+    /// 5. `[writable]` WSOL account (PDA), only for wrapped escrows
+    /// 6. `[]` Token program, only for wrapped escrows
+    /// 5-8. Token vault (PDA), mint, token program and payee token account in
+    ///    place of the WSOL accounts, only for SPL token escrows
+    /// 5-6. `[]` Price feed and `[writable]` creator account in place of the WSOL
+    ///    accounts, only for escrows with a `target_usd`
+    ///
+    /// Escrows with an executor are never paid out here, only by `FinalizeRelease`.
+    /// Accounts past those expected fail with `UnexpectedAccounts`
     ApproveRelease,
 
     /// Cancel escrow
@@ -38,12 +120,519 @@ pub enum EscrowInstruction {
     /// 0. `[writable, signer]` Creator account
     /// 1. `[writable]` Escrow account (PDA)
     /// 2. `[]` System program
+    /// 3. `[writable]` Creator stats account (PDA)
+    /// 4. `[writable]` Refund account, only when the escrow has `refund_to` set
+    /// 5. `[writable]` WSOL account (PDA), only for wrapped escrows
+    /// 6. `[]` Token program, only for wrapped escrows
+    /// 5-8. Token vault (PDA), mint, token program and payee token account in
+    ///    place of the WSOL accounts, only for SPL token escrows
+    /// Returns a Borsh-encoded `CancellationReceipt` as return data
     CancelEscrow,
+
+    /// Append a status note to an active escrow
+    /// Accounts expected:
+    /// 0. `[signer]` Creator account
+    /// 1. `[writable]` Escrow account (PDA)
+    AppendNote { note: String },
+
+    /// Decline the escrow, allowing the creator to reclaim funds
+    /// Accounts expected:
+    /// 0. `[signer]` Beneficiary account
+    /// 1. `[writable]` Escrow account (PDA)
+    DeclineEscrow,
+
+    /// Update counter configuration
+    /// Accounts expected:
+    /// 0. `[signer]` Authority account
+    /// 1. `[writable]` Counter account (PDA)
+    UpdateConfig {
+        max_active_escrows: Option<u32>,
+        max_description_len: Option<u16>,
+        strict_description: Option<bool>,
+        /// Longest `expiry_secs` new escrows may set, must be positive
+        max_expiry_secs: Option<i64>,
+        /// Treasury share of expiry refunds, at most `EscrowCounter::MAX_EXPIRY_FEE_BPS`
+        expiry_fee_bps: Option<u16>,
+        /// Highest escrow id the counter may reach, 0 for none; not below the current count
+        max_escrow_id: Option<u64>,
+    },
+
+    /// Consent to cancel; refunds the creator once the creator and every
+    /// approver who already approved have consented
+    /// Accounts expected:
+    /// 0. `[signer]` Creator or approving approver account
+    /// 1. `[writable]` Escrow account (PDA)
+    /// 2. `[writable]` Refund account (creator or `refund_to`)
+    /// 3. `[writable]` Creator stats account (PDA)
+    /// 4..4+`extra_consenters`. `[signer]` Further consenting accounts
+    /// 4+`extra_consenters`. `[writable]` Beneficiary account, only with a cancel penalty
+    /// 5+`extra_consenters`. `[writable]` WSOL account (PDA), only for wrapped escrows
+    /// 6+`extra_consenters`. `[]` Token program, only for wrapped escrows
+    ///
+    /// SPL token escrows take the token vault (PDA), mint, token program and refund
+    /// token account in place of the WSOL accounts, then the beneficiary's token
+    /// account with a cancel penalty.
+    /// The beneficiary and WSOL accounts are only read by the consent that cancels,
+    /// which returns a Borsh-encoded `CancellationReceipt` as return data
+    ConsentCancel { extra_consenters: u8 },
+
+    /// Log the serialized size and rent of an escrow with the given shape, 2 to
+    /// `Escrow::MAX_APPROVERS` approvers, and of the allocation its creation makes
+    /// Accounts expected:
+    /// 0. `[]` Counter account (PDA), optional. The allocation follows its
+    ///    `max_description_len`, or `Escrow::MAX_DESCRIPTION_LEN` without it
+    EstimateSize { description_len: u16, num_approvers: u8 },
+
+    /// Carve `amount` off an active escrow into a new escrow with the same terms
+    /// Accounts expected:
+    /// 0. `[writable, signer]` Creator account
+    /// 1. `[writable]` Escrow account (PDA)
+    /// 2. `[writable]` New escrow account (PDA)
+    /// 3. `[writable]` Counter account (PDA)
+    /// 4. `[]` System program
+    /// 5. `[writable]` Creator stats account (PDA)
+    /// 6. `[writable]` Beneficiary index account (PDA)
+    SplitEscrow { amount: u64 },
+
+    /// Freeze the escrow, blocking approvals and releases
+    /// Accounts expected:
+    /// 0. `[signer]` Arbiter account
+    /// 1. `[writable]` Escrow account (PDA)
+    FreezeEscrow,
+
+    /// Lift a freeze placed by the arbiter
+    /// Accounts expected:
+    /// 0. `[signer]` Arbiter account
+    /// 1. `[writable]` Escrow account (PDA)
+    UnfreezeEscrow,
+
+    /// Create a new escrow keyed on the creator and a per-creator nonce
+    /// Accounts expected:
+    /// 0. `[writable, signer]` Creator account
+    /// 1. `[writable]` Escrow account (PDA of creator + nonce)
+    /// 2. `[]` Counter account (PDA)
+    /// 3. `[]` System program
+    /// 4. `[writable]` Creator stats account (PDA)
+    /// 5. `[writable]` Beneficiary index account (PDA)
+    /// 6. `[writable]` WSOL account (PDA), only with `wrap_sol`
+    /// 7. `[]` Native mint, only with `wrap_sol`
+    /// 8. `[]` Token program, only with `wrap_sol`
+    /// 6-9. Creator token account, token vault (PDA), mint and token program in
+    ///    place of the WSOL accounts, only with `token_mint`
+    /// 6, 9 or 10. `[writable]` Idempotency marker (PDA), last, only with `idempotency_key`
+    ///
+    /// Returns the per-creator nonce as little-endian `u64` return data
+    CreateEscrowV2 {
+        amount: u64,
+        beneficiary: Pubkey,
+        approver1: Pubkey,
+        approver2: Pubkey,
+        approver3: Option<Pubkey>,
+        description: String,
+        options: EscrowOptions,
+    },
+
+    /// Change the beneficiary before any approvals
+    /// Accounts expected:
+    /// 0. `[signer]` Creator account
+    /// 1. `[writable]` Escrow account (PDA)
+    ChangeBeneficiary { new_beneficiary: Pubkey },
+
+    /// Log the escrows recorded in a beneficiary's index
+    /// Accounts expected:
+    /// 0. `[]` Beneficiary index account (PDA)
+    GetBeneficiaryIndex,
+
+    /// Log whether an approval from `approver` would release the escrow
+    /// Accounts expected:
+    /// 0. `[]` Escrow account (PDA)
+    PreviewApproval { approver: Pubkey },
+
+    /// Swap the approver in `slot` (0-2), even after approvals, dropping any
+    /// approval or cancel consent from the replaced approver
+    /// Accounts expected:
+    /// 0. `[signer]` Creator account
+    /// 1. `[signer]` Arbiter account
+    /// 2. `[writable]` Escrow account (PDA)
+    ReplaceApprover { slot: u8, new_approver: Pubkey },
+
+    /// Reallocate the escrow account to its current content plus `headroom` bytes,
+    /// with the creator funding extra rent or receiving the surplus on shrink
+    /// Accounts expected:
+    /// 0. `[writable, signer]` Creator account
+    /// 1. `[writable]` Escrow account (PDA)
+    /// 2. `[]` System program
+    ResizeEscrow { headroom: u32 },
+
+    /// Pay out an escrow whose release delay and minimum age have elapsed since quorum
+    /// Accounts expected:
+    /// 0. `[writable]` Escrow account (PDA)
+    /// 1. `[writable]` Beneficiary account
+    /// 2. `[writable]` Creator stats account (PDA)
+    /// 3. `[signer]` Executor account, only for escrows with an executor
+    /// 4. `[writable]` WSOL account (PDA), only for wrapped escrows
+    /// 5. `[]` Token program, only for wrapped escrows
+    /// 4-7. Token vault (PDA), mint, token program and payee token account in
+    ///    place of the WSOL accounts, only for SPL token escrows
+    /// 4-5. `[]` Price feed and `[writable]` creator account in place of the WSOL
+    ///    accounts, only for escrows with a `target_usd`
+    FinalizeRelease,
+
+    /// Emit an `EscrowSummary` as program data for each escrow account passed,
+    /// skipping accounts that are not escrows
+    /// Accounts expected:
+    /// 0..N. `[]` Escrow accounts (PDA), at most `MAX_SUMMARIES`
+    SummarizeEscrows,
+
+    /// Emit a `ReleaseRequestedEvent` nudging approvers, without changing state
+    /// Accounts expected:
+    /// 0. `[signer]` Creator account
+    /// 1. `[]` Escrow account (PDA)
+    RequestRelease,
+
+    /// Create a new escrow funded with `amount` that copies the beneficiary,
+    /// approvers and terms of an existing escrow, with fresh approvals and timestamps
+    /// Accounts expected:
+    /// 0. `[writable, signer]` Creator account
+    /// 1. `[]` Source escrow account (PDA)
+    /// 2. `[writable]` New escrow account (PDA)
+    /// 3. `[writable]` Counter account (PDA)
+    /// 4. `[]` System program
+    /// 5. `[writable]` Creator stats account (PDA)
+    /// 6. `[writable]` Beneficiary index account (PDA)
+    CloneEscrow { amount: u64 },
+
+    /// Let `delegate` approve in place of the signing approver, replacing any
+    /// earlier delegate of that approver
+    /// Accounts expected:
+    /// 0. `[signer]` Approver account
+    /// 1. `[writable]` Escrow account (PDA)
+    DelegateApproval { delegate: Pubkey },
+
+    /// Return lamports sent to the escrow beyond its escrowed amount and rent to the creator
+    /// Accounts expected:
+    /// 0. `[writable, signer]` Creator account
+    /// 1. `[writable]` Escrow account (PDA)
+    SweepExcess,
+
+    /// Fold a second escrow with identical terms into the first, before either
+    /// has approvals, closing the second with its rent returned to the creator
+    /// Accounts expected:
+    /// 0. `[writable, signer]` Creator account
+    /// 1. `[writable]` Escrow account kept (PDA)
+    /// 2. `[writable]` Escrow account closed (PDA)
+    /// 3. `[writable]` Creator stats account (PDA)
+    MergeEscrows,
+
+    /// Record the hash of off-chain delivery evidence for approvers to check,
+    /// up to `Escrow::MAX_PROOFS` per escrow
+    /// Accounts expected:
+    /// 0. `[signer]` Beneficiary account
+    /// 1. `[writable]` Escrow account (PDA)
+    AttachProof { proof_hash: [u8; 32] },
+
+    /// Create a new escrow keyed on the creator and a client-chosen nonce, so
+    /// creates never write to the shared counter. Nonces share the seed space
+    /// of `CreateEscrowV2`, so clients should pick them at random
+    /// Accounts expected:
+    /// 0. `[writable, signer]` Creator account
+    /// 1. `[writable]` Escrow account (PDA of creator + nonce)
+    /// 2. `[]` Counter account (PDA)
+    /// 3. `[]` System program
+    /// 4. `[writable]` Creator stats account (PDA)
+    /// 5. `[writable]` Beneficiary index account (PDA)
+    /// 6. `[writable]` WSOL account (PDA), only with `wrap_sol`
+    /// 7. `[]` Native mint, only with `wrap_sol`
+    /// 8. `[]` Token program, only with `wrap_sol`
+    /// 6-9. Creator token account, token vault (PDA), mint and token program in
+    ///    place of the WSOL accounts, only with `token_mint`
+    /// 6, 9 or 10. `[writable]` Idempotency marker (PDA), last, only with `idempotency_key`
+    ///
+    /// Returns the nonce, which is also the escrow id, as little-endian `u64` return data
+    CreateEscrowWithNonce {
+        nonce: [u8; 8],
+        amount: u64,
+        beneficiary: Pubkey,
+        approver1: Pubkey,
+        approver2: Pubkey,
+        approver3: Option<Pubkey>,
+        description: String,
+        options: EscrowOptions,
+    },
+
+    /// Approve release of funds, storing `comment` with the approval for as
+    /// long as it stands. Accounts as for `ApproveRelease`
+    ApproveWithComment { comment: String },
+
+    /// Admin override for a stuck escrow: refund it to its creator regardless of
+    /// approvals, freezes or release terms, emitting an `EmergencyRecoveryEvent`
+    /// Accounts expected:
+    /// 0. `[signer]` Counter authority account
+    /// 1. `[]` Counter account (PDA)
+    /// 2. `[writable]` Escrow account (PDA)
+    /// 3. `[writable]` Creator account
+    /// 4. `[writable]` Creator stats account (PDA)
+    /// 5. `[writable]` WSOL account (PDA), only for wrapped escrows
+    /// 6. `[]` Token program, only for wrapped escrows
+    /// 5-8. Token vault (PDA), mint, token program and payee token account in
+    ///    place of the WSOL accounts, only for SPL token escrows
+    /// Returns a Borsh-encoded `CancellationReceipt` as return data
+    EmergencyRecover,
+
+    /// Emit a `ReleaseStatus` telling whether the escrow can be released now and
+    /// what still blocks it, without changing state
+    /// Accounts expected:
+    /// 0. `[]` Escrow account (PDA)
+    EscrowReleaseStatus,
+
+    /// Emit an `ApproverMatrix` with every approver slot and the effective quorum,
+    /// without changing state
+    /// Accounts expected:
+    /// 0. `[]` Escrow account (PDA)
+    ListApprovers,
+
+    /// Drain an escrow stuck behind terms it can no longer satisfy, such as one created
+    /// before a program upgrade or held by a release delay that will not pass. Releases
+    /// to the beneficiary if it meets the legacy approval count, otherwise refunds.
+    /// A pre-upgrade escrow (see `LegacyEscrow`) has no arbiter, so its beneficiary
+    /// signs in that place, its creator stats account is left untouched and a refund
+    /// goes to its creator
+    /// Accounts expected:
+    /// 0. `[signer]` Creator account
+    /// 1. `[signer]` Arbiter account, or the beneficiary of a pre-upgrade escrow
+    /// 2. `[writable]` Escrow account (PDA)
+    /// 3. `[writable]` Beneficiary account
+    /// 4. `[writable]` Refund account (creator or `refund_to`)
+    /// 5. `[writable]` Creator stats account (PDA)
+    /// 6. `[signer]` Executor account, only when releasing an escrow with an executor
+    /// 7. `[writable]` WSOL account (PDA), only for wrapped escrows
+    /// 8. `[]` Token program, only for wrapped escrows
+    /// 7-10. Token vault (PDA), mint, token program and payee token account in
+    ///    place of the WSOL accounts, only for SPL token escrows
+    /// 7-8. `[]` Price feed and `[writable]` creator account in place of the WSOL
+    ///    accounts, only when releasing an escrow with a `target_usd`
+    /// Returns a Borsh-encoded `CancellationReceipt` as return data when it refunds
+    ForceFinalize,
+
+    /// Set the approvals required for release, between 1 and the number of distinct
+    /// approvers, with the creator and every approver signing
+    /// Accounts expected:
+    /// 0. `[signer]` Creator account
+    /// 1. `[writable]` Escrow account (PDA)
+    /// 2..N. `[signer]` Every distinct approver, in any order
+    ChangeThreshold { new_threshold: u8 },
+
+    /// Accept the escrow terms as beneficiary, unblocking release on escrows
+    /// created with `require_ack`
+    /// Accounts expected:
+    /// 0. `[signer]` Beneficiary account
+    /// 1. `[writable]` Escrow account (PDA)
+    AcknowledgeEscrow,
+
+    /// Expose the counter, without changing state
+    /// Accounts expected:
+    /// 0. `[]` Counter account (PDA)
+    ///
+    /// Returns the Borsh-encoded `EscrowCounter` as return data, `count` first
+    GetCounter,
+
+    /// Close a program-owned escrow account that holds no record, such as a closed
+    /// escrow revived by a transfer in the same transaction, returning its lamports
+    /// to the creator. Only creator-scoped seeds bind such an account to the creator
+    /// Accounts expected:
+    /// 0. `[writable, signer]` Creator account
+    /// 1. `[writable]` Escrow account (PDA)
+    ReclaimFailedCreate { escrow_id: u64 },
+
+    /// Report whether an approval from `approver` would be accepted now
+    /// Accounts expected:
+    /// 0. `[]` Escrow account (PDA)
+    ///
+    /// Returns a Borsh-encoded `bool` as return data
+    CanApprove { approver: Pubkey },
+
+    /// Refund expired escrows to their refund recipients and close them, logging
+    /// and skipping any that are not expired, already completed, frozen, pending
+    /// release or hold funds outside the escrow account. The counter's `expiry_fee_bps` of each
+    /// refunded amount goes to the treasury and the rent to the creator. Anyone may call it.
+    /// Logs a Borsh-encoded `CancellationReceipt` via `sol_log_data` per refunded escrow
+    /// Accounts expected:
+    /// 0. `[]` Counter account (PDA)
+    /// 1. `[writable]` Treasury account, the counter authority
+    /// Then for each escrow, at most `MAX_SWEEPS` escrows:
+    /// 2+4n. `[writable]` Escrow account (PDA)
+    /// 3+4n. `[writable]` Creator account
+    /// 4+4n. `[writable]` Refund account (creator or `refund_to`)
+    /// 5+4n. `[writable]` Creator stats account (PDA)
+    SweepExpired,
+
+    /// Release a releasable escrow and fund a new escrow, created by the released
+    /// escrow's beneficiary, with its whole amount. Only escrows that wait for
+    /// `FinalizeRelease` (a release delay, a minimum age or an executor) can be chained,
+    /// and never SPL token or USD-pegged ones
+    /// Accounts expected:
+    /// 0. `[writable]` Released escrow account (PDA)
+    /// 1. `[writable]` Released escrow's creator stats account (PDA)
+    /// 2. `[writable, signer]` Released escrow's beneficiary, creator of the new escrow
+    /// 3. `[writable]` New escrow account (PDA)
+    /// 4. `[writable]` Counter account (PDA)
+    /// 5. `[]` System program
+    /// 6. `[writable]` Beneficiary's creator stats account (PDA)
+    /// 7. `[writable]` New beneficiary's index account (PDA)
+    /// 8. `[signer]` Executor account, only when the released escrow has an executor
+    /// 9. `[writable]` WSOL account (PDA), only when the released escrow is wrapped
+    /// 10. `[]` Token program, only when the released escrow is wrapped
+    ///
+    /// Returns the new escrow id as little-endian `u64` return data
+    ReleaseInto {
+        beneficiary: Pubkey,
+        approver1: Pubkey,
+        approver2: Pubkey,
+        approver3: Option<Pubkey>,
+        description: String,
+        options: EscrowOptions,
+    },
+
+    /// Derive the global escrow PDA for `escrow_id`, without reading any account
+    /// Accounts expected: none
+    ///
+    /// Returns the PDA followed by its bump seed, 33 bytes of return data
+    DeriveEscrowAddress { escrow_id: u64 },
+
+    /// Burn the escrowed lamports by sending them to the incinerator, on agreement
+    /// of the creator and beneficiary, and close the escrow. Rent returns to the creator
+    /// Accounts expected:
+    /// 0. `[writable, signer]` Creator account
+    /// 1. `[signer]` Beneficiary account
+    /// 2. `[writable]` Escrow account (PDA)
+    /// 3. `[writable]` Incinerator account
+    /// 4. `[writable]` Creator stats account (PDA)
+    ForfeitEscrow,
+
+    /// Classify each account passed as an active escrow, a terminal one or not an
+    /// escrow at all
+    /// Accounts expected:
+    /// 0..N. `[]` Escrow accounts (PDA), at most `MAX_SUMMARIES`
+    ///
+    /// Returns a Borsh-encoded `Vec<EscrowClass>` as return data, one per account
+    ClassifyEscrows,
+
+    /// Rewrite an active escrow from the three-slot pre-upgrade layout (see
+    /// `LegacyEscrow`) into the current one under default terms, keeping its
+    /// approvals, and count it among the creator's active escrows. The creator
+    /// funds the rent for the larger allocation
+    /// Accounts expected:
+    /// 0. `[writable, signer]` Creator account
+    /// 1. `[writable]` Escrow account (PDA)
+    /// 2. `[]` System program
+    /// 3. `[writable]` Creator stats account (PDA)
+    MigrateEscrow,
 }
 
 impl EscrowInstruction {
+    /// Escrow accounts accepted by a single `SummarizeEscrows`
+    pub const MAX_SUMMARIES: usize = 16;
+
+    /// Escrows refunded by a single `SweepExpired`
+    pub const MAX_SWEEPS: usize = 8;
+
+    /// Longest instruction data accepted, above the largest valid instruction
+    /// (a nonce-keyed create with a full description and every option set)
+    pub const MAX_DATA_LEN: usize = 1024;
+
+    /// Number of variants, i.e. one past the highest valid discriminator
+    pub const VARIANT_COUNT: u8 = 43;
+
+    /// Fixed accounts each instruction reads, indexed by discriminator
+    const MIN_ACCOUNTS: [usize; Self::VARIANT_COUNT as usize] = [
+        3, 6, 5, 4, 2, 2, 2, 4, 0, 7, 2, 2, 6, 2, 1, 1, 3, 3, 3, 0, 2, 7, 2, 2, 4, 2, 6, 5, 5, 1, 1,
+        6, 3, 2, 1, 2, 1, 6, 8, 0, 5, 0, 4,
+    ];
+
+    /// Peek the leading discriminator without decoding the payload
+    pub fn discriminator(input: &[u8]) -> Result<u8, ProgramError> {
+        match input.first() {
+            Some(&tag) if tag < Self::VARIANT_COUNT => Ok(tag),
+            _ => Err(EscrowError::InvalidInstruction.into()),
+        }
+    }
+
+    /// Minimum number of accounts the instruction with discriminator `tag` expects
+    pub fn min_accounts(tag: u8) -> usize {
+        Self::MIN_ACCOUNTS[tag as usize]
+    }
+
+    /// An unknown leading discriminator is `InvalidInstruction`, while a known
+    /// variant with a malformed payload is `InvalidInstructionData`
     pub fn unpack(input: &[u8]) -> Result<Self, ProgramError> {
+        Self::discriminator(input)?;
         Self::try_from_slice(input).map_err(|_| ProgramError::InvalidInstructionData)
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unpack_rejects_unknown_discriminator() {
+        assert_eq!(
+            EscrowInstruction::unpack(&[EscrowInstruction::VARIANT_COUNT]).unwrap_err(),
+            EscrowError::InvalidInstruction.into()
+        );
+        assert_eq!(
+            EscrowInstruction::unpack(&[]).unwrap_err(),
+            EscrowError::InvalidInstruction.into()
+        );
+    }
+
+    #[test]
+    fn unpack_rejects_truncated_payload() {
+        let data = EscrowInstruction::SplitEscrow { amount: 5 }.try_to_vec().unwrap();
+        assert_eq!(
+            EscrowInstruction::unpack(&data[..data.len() - 1]).unwrap_err(),
+            ProgramError::InvalidInstructionData
+        );
+    }
+
+    #[test]
+    fn largest_instruction_fits_max_data_len() {
+        let key = Some(Pubkey::new_unique());
+        let instruction = EscrowInstruction::CreateEscrowWithNonce {
+            nonce: [0; 8],
+            amount: u64::MAX,
+            beneficiary: Pubkey::new_unique(),
+            approver1: Pubkey::new_unique(),
+            approver2: Pubkey::new_unique(),
+            approver3: key,
+            description: "x".repeat(crate::state::Escrow::MAX_DESCRIPTION_LEN),
+            options: EscrowOptions {
+                weights: Some([1, 1, 1]),
+                refund_to: key,
+                arbiter: key,
+                executor: key,
+                group_id: Some([0xff; 32]),
+                extra_approvers: vec![
+                    Pubkey::new_unique();
+                    crate::state::Escrow::MAX_EXTRA_APPROVERS
+                ],
+                threshold: u8::MAX,
+                idempotency_key: Some([0xff; 16]),
+                token_mint: key,
+                target_usd: Some(u64::MAX),
+                price_feed: key,
+                ..EscrowOptions::default()
+            },
+        };
+        assert!(instruction.try_to_vec().unwrap().len() <= EscrowInstruction::MAX_DATA_LEN);
+    }
+
+    #[test]
+    fn last_variant_is_within_discriminator_range() {
+        let instruction = EscrowInstruction::MigrateEscrow;
+        let data = instruction.try_to_vec().unwrap();
+        assert_eq!(data[0], EscrowInstruction::VARIANT_COUNT - 1);
+        assert_eq!(EscrowInstruction::unpack(&data).unwrap(), instruction);
+    }
+}
+
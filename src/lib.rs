@@ -5,6 +5,24 @@ use solana_program::{
     pubkey::Pubkey,
 };
 
+/// `msg!` that compiles to nothing without the `verbose-logs` feature.
+///
+/// Gated: the `Instruction: <Name>` dispatch line, the success narration each
+/// state-changing handler prints, and `Escrow::log_summary`. Always logged:
+/// error diagnostics, the output of read-only instructions (counter, previews,
+/// release status, size estimates), sweep skip reasons, the admin recovery
+/// notice and every `sol_log_data` event and snapshot. Build with
+/// `--no-default-features` to save the compute units the gated lines cost.
+macro_rules! verbose_msg {
+    ($($arg:tt)*) => {
+        if cfg!(feature = "verbose-logs") {
+            solana_program::msg!($($arg)*);
+        }
+    };
+}
+
+#[cfg(feature = "client")]
+pub mod client;
 pub mod error;
 pub mod instruction;
 pub mod processor;
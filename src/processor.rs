@@ -3,34 +3,75 @@ use solana_program::{
     account_info::{next_account_info, AccountInfo},
     clock::Clock,
     entrypoint::ProgramResult,
+    incinerator,
+    instruction::Instruction,
+    log::sol_log_data,
     msg,
-    program::invoke_signed,
+    program::{invoke, invoke_signed, set_return_data},
+    program_pack::Pack,
     program_error::ProgramError,
     pubkey::Pubkey,
     rent::Rent,
-    system_instruction,
+    system_instruction, system_program,
     sysvar::Sysvar,
 };
 
 use crate::{
     error::EscrowError,
-    instruction::EscrowInstruction,
-    state::{Escrow, EscrowCounter},
+    instruction::{EscrowInstruction, EscrowOptions},
+    state::{
+        Approval, ApprovalComment, ApprovalEvent, BeneficiaryIndex, CancelReason,
+        CancellationReceipt, CreatorStats, Delegation, EmergencyRecoveryEvent, Escrow, EscrowClass,
+        EscrowCounter, IdempotencyMarker, LegacyEscrow, PriceFeed, ProofAttachedEvent, ReleaseEvent,
+        ReleaseRequestedEvent, SETTLEMENT_SNAPSHOT_TAG,
+    },
 };
 
 pub struct Processor;
 
+/// Where a new escrow's id, and with it its PDA seeds, comes from
+#[derive(Clone, Copy)]
+enum EscrowIdSource {
+    /// Next id on the global counter, seeded `["escrow", id]`
+    Counter,
+    /// Next nonce in the creator's stats, seeded `["escrow", creator, nonce]`
+    CreatorNonce,
+    /// Nonce chosen by the client, seeded `["escrow", creator, nonce]`
+    ClientNonce(u64),
+}
+
 impl Processor {
     pub fn process(
         program_id: &Pubkey,
         accounts: &[AccountInfo],
         instruction_data: &[u8],
     ) -> ProgramResult {
+        // Reject oversized data, unknown instructions and short account lists
+        // before paying for the full decode of a potentially large payload
+        if instruction_data.len() > EscrowInstruction::MAX_DATA_LEN {
+            msg!(
+                "Instruction data is {} bytes, above the {} byte limit",
+                instruction_data.len(),
+                EscrowInstruction::MAX_DATA_LEN
+            );
+            return Err(ProgramError::InvalidInstructionData);
+        }
+        let tag = EscrowInstruction::discriminator(instruction_data)?;
+        if accounts.len() < EscrowInstruction::min_accounts(tag) {
+            msg!(
+                "Instruction {} needs {} accounts, got {}",
+                tag,
+                EscrowInstruction::min_accounts(tag),
+                accounts.len()
+            );
+            return Err(ProgramError::NotEnoughAccountKeys);
+        }
+
         let instruction = EscrowInstruction::unpack(instruction_data)?;
 
         match instruction {
             EscrowInstruction::Initialize => {
-                msg!("Instruction: Initialize");
+                verbose_msg!("Instruction: Initialize");
                 Self::process_initialize(program_id, accounts)
             }
             EscrowInstruction::CreateEscrow {
@@ -40,27 +81,260 @@ impl Processor {
                 approver2,
                 approver3,
                 description,
+                options,
             } => {
-                msg!("Instruction: CreateEscrow");
+                verbose_msg!("Instruction: CreateEscrow");
                 Self::process_create_escrow(
                     program_id,
                     accounts,
+                    EscrowIdSource::Counter,
                     amount,
                     beneficiary,
                     approver1,
                     approver2,
                     approver3,
                     description,
+                    options,
                 )
             }
             EscrowInstruction::ApproveRelease => {
-                msg!("Instruction: ApproveRelease");
-                Self::process_approve_release(program_id, accounts)
+                verbose_msg!("Instruction: ApproveRelease");
+                Self::process_approve_release(program_id, accounts, None)
             }
             EscrowInstruction::CancelEscrow => {
-                msg!("Instruction: CancelEscrow");
+                verbose_msg!("Instruction: CancelEscrow");
                 Self::process_cancel_escrow(program_id, accounts)
             }
+            EscrowInstruction::AppendNote { note } => {
+                verbose_msg!("Instruction: AppendNote");
+                Self::process_append_note(accounts, note)
+            }
+            EscrowInstruction::DeclineEscrow => {
+                verbose_msg!("Instruction: DeclineEscrow");
+                Self::process_decline_escrow(accounts)
+            }
+            EscrowInstruction::UpdateConfig {
+                max_active_escrows,
+                max_description_len,
+                strict_description,
+                max_expiry_secs,
+                expiry_fee_bps,
+                max_escrow_id,
+            } => {
+                verbose_msg!("Instruction: UpdateConfig");
+                Self::process_update_config(
+                    program_id,
+                    accounts,
+                    max_active_escrows,
+                    max_description_len,
+                    strict_description,
+                    max_expiry_secs,
+                    expiry_fee_bps,
+                    max_escrow_id,
+                )
+            }
+            EscrowInstruction::ConsentCancel { extra_consenters } => {
+                verbose_msg!("Instruction: ConsentCancel");
+                Self::process_consent_cancel(program_id, accounts, extra_consenters)
+            }
+            EscrowInstruction::EstimateSize {
+                description_len,
+                num_approvers,
+            } => {
+                verbose_msg!("Instruction: EstimateSize");
+                Self::process_estimate_size(program_id, accounts, description_len, num_approvers)
+            }
+            EscrowInstruction::SplitEscrow { amount } => {
+                verbose_msg!("Instruction: SplitEscrow");
+                Self::process_split_escrow(program_id, accounts, amount)
+            }
+            EscrowInstruction::FreezeEscrow => {
+                verbose_msg!("Instruction: FreezeEscrow");
+                Self::process_set_frozen(accounts, true)
+            }
+            EscrowInstruction::UnfreezeEscrow => {
+                verbose_msg!("Instruction: UnfreezeEscrow");
+                Self::process_set_frozen(accounts, false)
+            }
+            EscrowInstruction::CreateEscrowV2 {
+                amount,
+                beneficiary,
+                approver1,
+                approver2,
+                approver3,
+                description,
+                options,
+            } => {
+                verbose_msg!("Instruction: CreateEscrowV2");
+                Self::process_create_escrow(
+                    program_id,
+                    accounts,
+                    EscrowIdSource::CreatorNonce,
+                    amount,
+                    beneficiary,
+                    approver1,
+                    approver2,
+                    approver3,
+                    description,
+                    options,
+                )
+            }
+            EscrowInstruction::CreateEscrowWithNonce {
+                nonce,
+                amount,
+                beneficiary,
+                approver1,
+                approver2,
+                approver3,
+                description,
+                options,
+            } => {
+                verbose_msg!("Instruction: CreateEscrowWithNonce");
+                Self::process_create_escrow(
+                    program_id,
+                    accounts,
+                    EscrowIdSource::ClientNonce(u64::from_le_bytes(nonce)),
+                    amount,
+                    beneficiary,
+                    approver1,
+                    approver2,
+                    approver3,
+                    description,
+                    options,
+                )
+            }
+            EscrowInstruction::ChangeBeneficiary { new_beneficiary } => {
+                verbose_msg!("Instruction: ChangeBeneficiary");
+                Self::process_change_beneficiary(accounts, new_beneficiary)
+            }
+            EscrowInstruction::GetBeneficiaryIndex => {
+                verbose_msg!("Instruction: GetBeneficiaryIndex");
+                Self::process_get_beneficiary_index(program_id, accounts)
+            }
+            EscrowInstruction::PreviewApproval { approver } => {
+                verbose_msg!("Instruction: PreviewApproval");
+                Self::process_preview_approval(accounts, approver)
+            }
+            EscrowInstruction::ReplaceApprover { slot, new_approver } => {
+                verbose_msg!("Instruction: ReplaceApprover");
+                Self::process_replace_approver(accounts, slot, new_approver)
+            }
+            EscrowInstruction::ResizeEscrow { headroom } => {
+                verbose_msg!("Instruction: ResizeEscrow");
+                Self::process_resize_escrow(program_id, accounts, headroom)
+            }
+            EscrowInstruction::FinalizeRelease => {
+                verbose_msg!("Instruction: FinalizeRelease");
+                Self::process_finalize_release(program_id, accounts)
+            }
+            EscrowInstruction::SummarizeEscrows => {
+                verbose_msg!("Instruction: SummarizeEscrows");
+                Self::process_summarize_escrows(program_id, accounts)
+            }
+            EscrowInstruction::RequestRelease => {
+                verbose_msg!("Instruction: RequestRelease");
+                Self::process_request_release(accounts)
+            }
+            EscrowInstruction::CloneEscrow { amount } => {
+                verbose_msg!("Instruction: CloneEscrow");
+                Self::process_clone_escrow(program_id, accounts, amount)
+            }
+            EscrowInstruction::DelegateApproval { delegate } => {
+                verbose_msg!("Instruction: DelegateApproval");
+                Self::process_delegate_approval(accounts, delegate)
+            }
+            EscrowInstruction::SweepExcess => {
+                verbose_msg!("Instruction: SweepExcess");
+                Self::process_sweep_excess(program_id, accounts)
+            }
+            EscrowInstruction::MergeEscrows => {
+                verbose_msg!("Instruction: MergeEscrows");
+                Self::process_merge_escrows(program_id, accounts)
+            }
+            EscrowInstruction::AttachProof { proof_hash } => {
+                verbose_msg!("Instruction: AttachProof");
+                Self::process_attach_proof(accounts, proof_hash)
+            }
+            EscrowInstruction::ApproveWithComment { comment } => {
+                verbose_msg!("Instruction: ApproveWithComment");
+                Self::process_approve_release(program_id, accounts, Some(comment))
+            }
+            EscrowInstruction::EmergencyRecover => {
+                verbose_msg!("Instruction: EmergencyRecover");
+                Self::process_emergency_recover(program_id, accounts)
+            }
+            EscrowInstruction::EscrowReleaseStatus => {
+                verbose_msg!("Instruction: EscrowReleaseStatus");
+                Self::process_escrow_release_status(program_id, accounts)
+            }
+            EscrowInstruction::ListApprovers => {
+                verbose_msg!("Instruction: ListApprovers");
+                Self::process_list_approvers(program_id, accounts)
+            }
+            EscrowInstruction::ForceFinalize => {
+                verbose_msg!("Instruction: ForceFinalize");
+                Self::process_force_finalize(program_id, accounts)
+            }
+            EscrowInstruction::ChangeThreshold { new_threshold } => {
+                verbose_msg!("Instruction: ChangeThreshold");
+                Self::process_change_threshold(accounts, new_threshold)
+            }
+            EscrowInstruction::AcknowledgeEscrow => {
+                verbose_msg!("Instruction: AcknowledgeEscrow");
+                Self::process_acknowledge_escrow(accounts)
+            }
+            EscrowInstruction::GetCounter => {
+                verbose_msg!("Instruction: GetCounter");
+                Self::process_get_counter(program_id, accounts)
+            }
+            EscrowInstruction::ReclaimFailedCreate { escrow_id } => {
+                verbose_msg!("Instruction: ReclaimFailedCreate");
+                Self::process_reclaim_failed_create(program_id, accounts, escrow_id)
+            }
+            EscrowInstruction::CanApprove { approver } => {
+                verbose_msg!("Instruction: CanApprove");
+                Self::process_can_approve(program_id, accounts, approver)
+            }
+            EscrowInstruction::ReleaseInto {
+                beneficiary,
+                approver1,
+                approver2,
+                approver3,
+                description,
+                options,
+            } => {
+                verbose_msg!("Instruction: ReleaseInto");
+                Self::process_release_into(
+                    program_id,
+                    accounts,
+                    beneficiary,
+                    approver1,
+                    approver2,
+                    approver3,
+                    description,
+                    options,
+                )
+            }
+            EscrowInstruction::SweepExpired => {
+                verbose_msg!("Instruction: SweepExpired");
+                Self::process_sweep_expired(program_id, accounts)
+            }
+            EscrowInstruction::DeriveEscrowAddress { escrow_id } => {
+                verbose_msg!("Instruction: DeriveEscrowAddress");
+                Self::process_derive_escrow_address(program_id, escrow_id)
+            }
+            EscrowInstruction::ForfeitEscrow => {
+                verbose_msg!("Instruction: ForfeitEscrow");
+                Self::process_forfeit_escrow(program_id, accounts)
+            }
+            EscrowInstruction::ClassifyEscrows => {
+                verbose_msg!("Instruction: ClassifyEscrows");
+                Self::process_classify_escrows(program_id, accounts)
+            }
+            EscrowInstruction::MigrateEscrow => {
+                verbose_msg!("Instruction: MigrateEscrow");
+                Self::process_migrate_escrow(program_id, accounts)
+            }
         }
     }
 
@@ -71,14 +345,26 @@ impl Processor {
         let system_program = next_account_info(account_info_iter)?;
 
         if !authority.is_signer {
+            msg!("Missing signature from {}", authority.key);
             return Err(ProgramError::MissingRequiredSignature);
         }
 
+        if !system_program::check_id(system_program.key) {
+            msg!("Expected the system program, got {}", system_program.key);
+            return Err(ProgramError::IncorrectProgramId);
+        }
+
         let (counter_pda, counter_bump) = Pubkey::find_program_address(&[b"counter"], program_id);
         if counter_pda != *counter_account.key {
+            msg!("Counter account {} does not match PDA {}", counter_account.key, counter_pda);
             return Err(EscrowError::InvalidCounterAccount.into());
         }
 
+        if counter_account.lamports() > 0 || !counter_account.data_is_empty() {
+            msg!("Counter {} is already initialized", counter_account.key);
+            return Err(EscrowError::AlreadyInitialized.into());
+        }
+
         let rent = Rent::get()?;
         let space = EscrowCounter::SIZE;
         let rent_lamports = rent.minimum_balance(space);
@@ -97,210 +383,4478 @@ impl Processor {
             &[&[b"counter".as_ref(), &[counter_bump]]],
         )?;
 
-        let counter = EscrowCounter { count: 0 };
+        let counter = EscrowCounter {
+            count: 0,
+            authority: *authority.key,
+            max_active_escrows: 0,
+            max_description_len: Escrow::MAX_DESCRIPTION_LEN as u16,
+            strict_description: false,
+            max_expiry_secs: EscrowCounter::DEFAULT_MAX_EXPIRY_SECS,
+            expiry_fee_bps: 0,
+            max_escrow_id: 0,
+        };
         counter.serialize(&mut &mut counter_account.data.borrow_mut()[..])?;
 
-        msg!("Counter initialized");
+        verbose_msg!("Counter initialized");
         Ok(())
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn process_create_escrow(
         program_id: &Pubkey,
         accounts: &[AccountInfo],
+        id_source: EscrowIdSource,
         amount: u64,
         beneficiary: Pubkey,
         approver1: Pubkey,
         approver2: Pubkey,
         approver3: Option<Pubkey>,
         description: String,
+        options: EscrowOptions,
     ) -> ProgramResult {
         if amount == 0 {
+            msg!("Escrow amount must be nonzero");
             return Err(EscrowError::InsufficientFunds.into());
         }
-        if description.len() > 200 {
+        if description.len() > Escrow::MAX_DESCRIPTION_LEN {
+            msg!(
+                "Description is {} bytes, above the {} byte limit",
+                description.len(),
+                Escrow::MAX_DESCRIPTION_LEN
+            );
+            return Err(ProgramError::InvalidInstructionData);
+        }
+        if options.wrap_sol && options.token_mint.is_some() {
+            msg!("An escrow holds either wrapped SOL or SPL tokens, not both");
+            return Err(ProgramError::InvalidInstructionData);
+        }
+        if options.target_usd.is_some() != options.price_feed.is_some()
+            || options.target_usd == Some(0)
+        {
+            msg!("A USD target needs a nonzero number of cents and a price feed");
+            return Err(ProgramError::InvalidInstructionData);
+        }
+        if options.target_usd.is_some() && (options.wrap_sol || options.token_mint.is_some()) {
+            msg!("Only escrows held in lamports can be pegged to USD");
+            return Err(ProgramError::InvalidInstructionData);
+        }
+        if options.weights.unwrap_or(Escrow::DEFAULT_WEIGHTS).contains(&0) {
+            msg!("Approver weights must be nonzero");
+            return Err(EscrowError::InvalidWeight.into());
+        }
+        if options.release_delay_secs < 0
+            || options.min_age_secs < 0
+            || options.approval_ttl_secs < 0
+            || options.cancel_lock_secs < 0
+        {
+            msg!("Escrow durations must not be negative");
             return Err(ProgramError::InvalidInstructionData);
         }
+        if options.cancel_penalty_bps > Escrow::MAX_BPS {
+            msg!(
+                "Cancel penalty of {} bps exceeds {}",
+                options.cancel_penalty_bps,
+                Escrow::MAX_BPS
+            );
+            return Err(EscrowError::InvalidCancelPenalty.into());
+        }
+        if options.categories.iter().any(|c| *c > 7) {
+            msg!("Approver categories must be 0-7, got {:?}", options.categories);
+            return Err(EscrowError::InvalidCategories.into());
+        }
+        // Every required category must be carried by at least one approver slot
+        let slots = if approver3.is_some() { 3 } else { 2 };
+        let available = options.categories[..slots].iter().fold(0u8, |mask, c| mask | (1 << c));
+        if options.required_categories & !available != 0 {
+            msg!(
+                "Required categories {:#010b} not carried by any approver",
+                options.required_categories
+            );
+            return Err(EscrowError::InvalidCategories.into());
+        }
+        let default_key = Pubkey::default();
+        if approver1 == default_key || approver2 == default_key || approver3 == Some(default_key) {
+            msg!("Approvers must not be the default key");
+            return Err(EscrowError::InvalidApprover.into());
+        }
+        if options.extra_approvers.len() > Escrow::MAX_EXTRA_APPROVERS {
+            msg!(
+                "{} extra approvers, at most {}",
+                options.extra_approvers.len(),
+                Escrow::MAX_EXTRA_APPROVERS
+            );
+            return Err(EscrowError::InvalidApprover.into());
+        }
+        // Extra approvers carry weight 1 each, so they must be distinct from every other approver
+        let slot_approvers = [Some(approver1), Some(approver2), approver3];
+        for (i, extra) in options.extra_approvers.iter().enumerate() {
+            if *extra == default_key
+                || slot_approvers.contains(&Some(*extra))
+                || options.extra_approvers[..i].contains(extra)
+            {
+                msg!("Extra approver {} is the default key or a duplicate", extra);
+                return Err(EscrowError::InvalidApprover.into());
+            }
+        }
 
         let account_info_iter = &mut accounts.iter();
         let creator = next_account_info(account_info_iter)?;
         let escrow_account = next_account_info(account_info_iter)?;
         let counter_account = next_account_info(account_info_iter)?;
         let system_program = next_account_info(account_info_iter)?;
+        let creator_stats_account = next_account_info(account_info_iter)?;
+        let beneficiary_index_account = next_account_info(account_info_iter)?;
 
         if !creator.is_signer {
+            msg!("Missing signature from {}", creator.key);
             return Err(ProgramError::MissingRequiredSignature);
         }
 
-        // Load and increment counter
-        let mut counter_data = counter_account.data.borrow_mut();
-        let mut counter_slice: &[u8] = &counter_data;
-        let mut counter = EscrowCounter::deserialize(&mut counter_slice)?;
-        let escrow_id = counter.count.checked_add(1).ok_or(EscrowError::AmountOverflow)?;
-        counter.count = escrow_id;
-        counter.serialize(&mut &mut counter_data[..])?;
-        drop(counter_data);
-
-        // Verify escrow account PDA
-        let escrow_id_bytes = escrow_id.to_le_bytes();
-        let escrow_seeds = &[b"escrow".as_ref(), escrow_id_bytes.as_ref()];
-        let (escrow_pda, escrow_bump) = Pubkey::find_program_address(escrow_seeds, program_id);
-        if escrow_pda != *escrow_account.key {
-            return Err(EscrowError::InvalidEscrowAccount.into());
+        if !system_program::check_id(system_program.key) {
+            msg!("Expected the system program, got {}", system_program.key);
+            return Err(ProgramError::IncorrectProgramId);
         }
 
-        // Create escrow account
-        let rent = Rent::get()?;
-        let space = Escrow::MAX_SIZE;
-        let rent_lamports = rent.minimum_balance(space);
+        Self::check_counter_account(program_id, counter_account)?;
 
-        let create_account_ix = system_instruction::create_account(
-            creator.key,
-            escrow_account.key,
-            rent_lamports,
-            space as u64,
-            program_id,
+        // A marker already at the key's PDA means this create ran before
+        let marker = match options.idempotency_key {
+            Some(key) => {
+                let marker_index = if options.wrap_sol {
+                    9
+                } else if options.token_mint.is_some() {
+                    10
+                } else {
+                    6
+                };
+                let marker_account =
+                    accounts.get(marker_index).ok_or(ProgramError::NotEnoughAccountKeys)?;
+                let (marker_pda, _marker_bump) = Pubkey::find_program_address(
+                    &[b"idempotency", creator.key.as_ref(), &key],
+                    program_id,
+                );
+                if marker_pda != *marker_account.key {
+                    msg!("Marker account {} does not match PDA {}", marker_account.key, marker_pda);
+                    return Err(ProgramError::InvalidSeeds);
+                }
+                if !marker_account.data_is_empty() {
+                    let data = marker_account.data.borrow();
+                    let mut data_slice: &[u8] = &data;
+                    let existing = IdempotencyMarker::deserialize(&mut data_slice)?;
+                    msg!("Escrow {} already created for this key", existing.escrow_id);
+                    set_return_data(&existing.escrow_id.to_le_bytes());
+                    return Ok(());
+                }
+                Some((marker_account, key))
+            }
+            None => None,
+        };
+
+        // The global path reserves an id on the counter, while the creator-scoped
+        // paths only read the config from it and take their id elsewhere
+        let creator_scoped = !matches!(id_source, EscrowIdSource::Counter);
+        let counter = if creator_scoped {
+            let counter_data = counter_account.data.borrow();
+            let mut counter_slice: &[u8] = &counter_data;
+            EscrowCounter::deserialize(&mut counter_slice)?
+        } else {
+            Self::reserve_escrow_id(counter_account)?
+        };
+
+        if !counter.accepts_description(&description) {
+            msg!("Description rejected by the counter config");
+            return Err(ProgramError::InvalidInstructionData);
+        }
+        if !counter.accepts_expiry(options.expiry_secs) {
+            msg!(
+                "Expiry {} must be 0 or 1-{} seconds",
+                options.expiry_secs,
+                counter.max_expiry_secs
+            );
+            return Err(EscrowError::InvalidDeadline.into());
+        }
+        let space = Escrow::size_with_description(
+            counter.max_description_len as usize,
+            options.extra_approvers.len(),
         );
 
-        invoke_signed(
-            &create_account_ix,
-            &[creator.clone(), escrow_account.clone(), system_program.clone()],
-            &[&[b"escrow".as_ref(), escrow_id_bytes.as_ref(), &[escrow_bump]]],
+        let mut creator_stats = Self::reserve_creator_slot(
+            program_id,
+            creator,
+            creator_stats_account,
+            system_program,
+            counter.max_active_escrows,
         )?;
 
-        // Transfer SOL to escrow
-        let transfer_ix = system_instruction::transfer(creator.key, escrow_account.key, amount);
-        solana_program::program::invoke(
-            &transfer_ix,
-            &[creator.clone(), escrow_account.clone(), system_program.clone()],
+        let escrow_id = match id_source {
+            EscrowIdSource::Counter => counter.count,
+            EscrowIdSource::CreatorNonce => {
+                creator_stats.nonce =
+                    creator_stats.nonce.checked_add(1).ok_or(EscrowError::AmountOverflow)?;
+                creator_stats.serialize(&mut &mut creator_stats_account.data.borrow_mut()[..])?;
+                creator_stats.nonce
+            }
+            // A reused nonce fails when the escrow account already exists
+            EscrowIdSource::ClientNonce(nonce) => nonce,
+        };
+
+        // Paying the escrow PDA from itself would complete without moving any funds
+        if beneficiary == *escrow_account.key {
+            msg!("Beneficiary {} is the escrow account itself", beneficiary);
+            return Err(EscrowError::InvalidBeneficiary.into());
+        }
+
+        let escrow_id_bytes = escrow_id.to_le_bytes();
+        let escrow_bump = if creator_scoped {
+            Self::create_escrow_account(
+                program_id,
+                creator,
+                escrow_account,
+                system_program,
+                space,
+                &[b"escrow", creator.key.as_ref(), &escrow_id_bytes],
+            )?
+        } else {
+            Self::create_escrow_account(
+                program_id,
+                creator,
+                escrow_account,
+                system_program,
+                space,
+                &[b"escrow", &escrow_id_bytes],
+            )?
+        };
+
+        Self::append_beneficiary_index(
+            program_id,
+            creator,
+            beneficiary_index_account,
+            system_program,
+            &beneficiary,
+            escrow_account.key,
         )?;
 
+        if options.wrap_sol {
+            let wsol_account = next_account_info(account_info_iter)?;
+            let native_mint = next_account_info(account_info_iter)?;
+            let token_program = next_account_info(account_info_iter)?;
+            Self::wrap_sol(
+                program_id,
+                creator,
+                escrow_account,
+                wsol_account,
+                native_mint,
+                token_program,
+                system_program,
+                amount,
+            )?;
+        } else if let Some(token_mint) = options.token_mint {
+            let source_account = next_account_info(account_info_iter)?;
+            let vault_account = next_account_info(account_info_iter)?;
+            let mint = next_account_info(account_info_iter)?;
+            let token_program = next_account_info(account_info_iter)?;
+            Self::deposit_tokens(
+                program_id,
+                creator,
+                source_account,
+                escrow_account,
+                vault_account,
+                mint,
+                token_program,
+                system_program,
+                &token_mint,
+                amount,
+            )?;
+        } else {
+            // Transfer SOL to escrow
+            let transfer_ix = system_instruction::transfer(creator.key, escrow_account.key, amount);
+            invoke(
+                &transfer_ix,
+                &[creator.clone(), escrow_account.clone(), system_program.clone()],
+            )?;
+        }
+
         // Create and save escrow data
         let clock = Clock::get()?;
         let escrow = Escrow {
-            id: escrow_id,
-            creator: *creator.key,
-            beneficiary,
-            amount,
-            approver1,
-            approver2,
-            approver3,
-            description: description.clone(),
-            approvals: Vec::new(),
-            is_completed: false,
-            created_at: clock.unix_timestamp,
-            completed_at: 0,
+            creator_scoped,
+            bump: escrow_bump,
+            ..Escrow::new(
+                escrow_id,
+                *creator.key,
+                beneficiary,
+                amount,
+                approver1,
+                approver2,
+                approver3,
+                description.clone(),
+                &options,
+                clock.unix_timestamp,
+            )
         };
+        if escrow.threshold as usize > escrow.total_approvers() {
+            msg!("Threshold {} exceeds {} approvers", escrow.threshold, escrow.total_approvers());
+            return Err(EscrowError::InvalidThreshold.into());
+        }
+        if escrow.has_role_conflict() {
+            msg!("Escrow {} would give the arbiter another role", escrow.id);
+            return Err(EscrowError::RoleConflict.into());
+        }
+
+        // Funding must have landed in full before the escrow is recorded
+        Self::check_escrow_funded(&escrow, escrow_account)?;
 
         escrow.serialize(&mut &mut escrow_account.data.borrow_mut()[..])?;
 
-        msg!("Escrow {} created with {} lamports", escrow_id, amount);
-        msg!("Beneficiary: {}", beneficiary);
+        if let Some((marker_account, key)) = marker {
+            Self::create_escrow_account(
+                program_id,
+                creator,
+                marker_account,
+                system_program,
+                IdempotencyMarker::SIZE,
+                &[b"idempotency", creator.key.as_ref(), &key],
+            )?;
+            let marker = IdempotencyMarker { escrow_id, escrow: *escrow_account.key };
+            marker.serialize(&mut &mut marker_account.data.borrow_mut()[..])?;
+        }
+
+        verbose_msg!("Escrow {} created with {} lamports", escrow_id, amount);
+        verbose_msg!("Beneficiary: {}", beneficiary);
+        escrow.log_summary();
+
+        // Expose the new id to clients without log parsing
+        set_return_data(&escrow_id.to_le_bytes());
         Ok(())
     }
 
-    fn process_approve_release(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    fn process_approve_release(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        comment: Option<String>,
+    ) -> ProgramResult {
+        if let Some(comment) = &comment {
+            if comment.is_empty() || comment.len() > ApprovalComment::MAX_COMMENT_LEN {
+                msg!(
+                    "Comment must be 1-{} bytes, got {}",
+                    ApprovalComment::MAX_COMMENT_LEN,
+                    comment.len()
+                );
+                return Err(ProgramError::InvalidInstructionData);
+            }
+        }
+
         let account_info_iter = &mut accounts.iter();
-        let approver = next_account_info(account_info_iter)?;
+        let signer = next_account_info(account_info_iter)?;
         let escrow_account = next_account_info(account_info_iter)?;
         let beneficiary = next_account_info(account_info_iter)?;
         let system_program = next_account_info(account_info_iter)?;
+        let creator_stats_account = next_account_info(account_info_iter)?;
 
-        if !approver.is_signer {
+        if !signer.is_signer {
+            msg!("Missing signature from {}", signer.key);
             return Err(ProgramError::MissingRequiredSignature);
         }
 
-        let mut data = escrow_account.data.borrow_mut();
-        let mut data_slice: &[u8] = &data;
-        let mut escrow = Escrow::deserialize(&mut data_slice)?;
+        // An escrow that was never created is empty or owned by someone else
+        if escrow_account.owner != program_id
+            || escrow_account.data_len() < Escrow::serialized_size(0, 2)
+        {
+            msg!("Account {} is not an escrow of this program", escrow_account.key);
+            return Err(EscrowError::InvalidEscrowAccount.into());
+        }
+
+        let mut escrow = {
+            let data = escrow_account.data.borrow();
+            let mut data_slice: &[u8] = &data;
+            Escrow::deserialize(&mut data_slice)?
+        };
+
+        if !escrow.is_initialized() {
+            msg!("Account {} holds no escrow", escrow_account.key);
+            return Err(EscrowError::InvalidEscrowAccount.into());
+        }
+
+        // Too few accounts fail as NotEnoughAccountKeys; trailing ones would be
+        // silently ignored, so they are more likely a misordered list
+        let expected_accounts = if escrow.wrapped_sol || escrow.target_usd.is_some() {
+            7
+        } else if escrow.token_mint.is_some() {
+            9
+        } else {
+            5
+        };
+        if accounts.len() > expected_accounts {
+            msg!("Expected at most {} accounts, got {}", expected_accounts, accounts.len());
+            return Err(EscrowError::UnexpectedAccounts.into());
+        }
 
         if escrow.is_completed {
+            msg!("Escrow {} is already completed", escrow.id);
             return Err(EscrowError::EscrowCompleted.into());
         }
 
-        if !escrow.is_approver(approver.key) {
-            return Err(EscrowError::Unauthorized.into());
+        if escrow.is_declined {
+            msg!("Escrow {} was declined", escrow.id);
+            return Err(EscrowError::EscrowDeclined.into());
+        }
+
+        if escrow.frozen {
+            msg!("Escrow {} is frozen", escrow.id);
+            return Err(EscrowError::EscrowFrozen.into());
         }
 
-        if escrow.has_approved(approver.key) {
+        // A delegate approves in place of, and is recorded as, its approver
+        let approver = match escrow.approver_for(signer.key) {
+            Some(approver) => approver,
+            None => {
+                msg!("Approver not authorized: {}", signer.key);
+                return Err(EscrowError::Unauthorized.into());
+            }
+        };
+
+        // Expired approvals no longer count and must be re-submitted
+        let clock = Clock::get()?;
+        let stored_approvals = escrow.approvals.len();
+        escrow.prune_expired_approvals(clock.unix_timestamp);
+        // A plain approval touches only the approvals vector, which can be rewritten in place
+        let mut approvals_only = escrow.approvals.len() == stored_approvals;
+
+        if escrow.has_approved(&approver) {
+            msg!("Approver {} already approved escrow {}", approver, escrow.id);
             return Err(EscrowError::AlreadyApproved.into());
         }
 
-        // Add approval
-        escrow.approvals.push(*approver.key);
+        if escrow.is_approval_rate_limited(&approver, clock.slot) {
+            msg!(
+                "Approver {} approved escrow {} within the last {} slots",
+                approver,
+                escrow.id,
+                escrow.approval_rate_limit_slots
+            );
+            return Err(EscrowError::ApprovalRateLimited.into());
+        }
 
-        msg!(
-            "Escrow {} approved by {} ({}/{} approvals)",
-            escrow.id,
-            approver.key,
-            escrow.approvals.len(),
-            escrow.required_approvals()
-        );
+        if !escrow.is_in_approval_order(&approver, clock.unix_timestamp) {
+            msg!("Approver {} must wait for earlier approvers on escrow {}", approver, escrow.id);
+            return Err(EscrowError::OutOfOrderApproval.into());
+        }
 
-        // Check if we can release
-        if escrow.can_be_released() {
-            let clock = Clock::get()?;
-            escrow.is_completed = true;
-            escrow.completed_at = clock.unix_timestamp;
+        if escrow.approvals.len() >= escrow.approval_limit() {
+            msg!("Escrow {} already holds {} approvals", escrow.id, escrow.approvals.len());
+            return Err(EscrowError::ApprovalLimitReached.into());
+        }
+
+        // Add approval
+        escrow.record_approval(Approval {
+            approver,
+            approved_at: clock.unix_timestamp,
+        });
+        // Kept apart from the approval, which may expire and be pruned within the window
+        if escrow.approval_rate_limit_slots > 0 {
+            escrow.record_approval_slot(approver, clock.slot);
+            approvals_only = false;
+        }
 
-            // Transfer funds from escrow to beneficiary
-            let escrow_id_bytes = escrow.id.to_le_bytes();
-            let escrow_pda_seeds = &[b"escrow".as_ref(), escrow_id_bytes.as_ref()];
-            let (_escrow_pda, bump) = Pubkey::find_program_address(escrow_pda_seeds, program_id);
-            let escrow_seeds = &[
-                b"escrow".as_ref(),
-                escrow_id_bytes.as_ref(),
-                &[bump],
-            ];
+        if let Some(comment) = comment {
+            let event = ApprovalEvent {
+                escrow_id: escrow.id,
+                approver,
+                approved_at: clock.unix_timestamp,
+                comment: comment.clone(),
+            };
+            sol_log_data(&[&event.try_to_vec()?]);
+            escrow.approval_comments.push(ApprovalComment { approver, comment });
+            approvals_only = false;
+        }
 
-            **escrow_account.try_borrow_mut_lamports()? -= escrow.amount;
-            **beneficiary.try_borrow_mut_lamports()? += escrow.amount;
+        verbose_msg!(
+            "Escrow {} approved by {} ({}/{} approval weight)",
+            escrow.id,
+            signer.key,
+            escrow.approved_weight(clock.unix_timestamp),
+            escrow.required_weight()
+        );
 
-            msg!("Escrow {} released to beneficiary", escrow.id);
-            msg!("Amount released: {} lamports", escrow.amount);
+        // Check if we can release, or defer to FinalizeRelease while the release
+        // delay or minimum age has not yet passed
+        if escrow.can_be_released(clock.unix_timestamp) && escrow.releasable_at == 0 {
+            let releasable_at = escrow
+                .release_unlocks_at(clock.unix_timestamp)
+                .ok_or(EscrowError::AmountOverflow)?;
+            // The executor signs the payout, so it is left to FinalizeRelease
+            if releasable_at <= clock.unix_timestamp && escrow.executor.is_none() {
+                return Self::release_to_beneficiary(
+                    program_id,
+                    &mut escrow,
+                    escrow_account,
+                    beneficiary,
+                    creator_stats_account,
+                    account_info_iter,
+                    clock.unix_timestamp,
+                );
+            }
+            escrow.releasable_at = releasable_at;
+            approvals_only = false;
+            verbose_msg!("Escrow {} releasable at {}", escrow.id, escrow.releasable_at);
         }
 
-        escrow.serialize(&mut &mut data[..])?;
+        if approvals_only {
+            let mut data = escrow_account.data.borrow_mut();
+            escrow.write_approvals_in_place(&mut data[..], stored_approvals)?;
+        } else {
+            escrow.serialize(&mut &mut escrow_account.data.borrow_mut()[..])?;
+        }
+        escrow.log_summary();
         Ok(())
     }
 
-    fn process_cancel_escrow(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    fn process_finalize_release(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
         let account_info_iter = &mut accounts.iter();
-        let creator = next_account_info(account_info_iter)?;
         let escrow_account = next_account_info(account_info_iter)?;
-        let system_program = next_account_info(account_info_iter)?;
+        let beneficiary = next_account_info(account_info_iter)?;
+        let creator_stats_account = next_account_info(account_info_iter)?;
 
-        if !creator.is_signer {
-            return Err(ProgramError::MissingRequiredSignature);
+        if escrow_account.owner != program_id {
+            msg!("Account {} is not owned by this program", escrow_account.key);
+            return Err(EscrowError::InvalidEscrowAccount.into());
         }
 
-        let mut data = escrow_account.data.borrow_mut();
-        let mut data_slice: &[u8] = &data;
-        let mut escrow = Escrow::deserialize(&mut data_slice)?;
+        let mut escrow = {
+            let data = escrow_account.data.borrow();
+            let mut data_slice: &[u8] = &data;
+            Escrow::deserialize(&mut data_slice)?
+        };
 
-        if escrow.creator != *creator.key {
-            return Err(EscrowError::Unauthorized.into());
-        }
+        let clock = Clock::get()?;
+        Self::check_finalizable(&escrow, beneficiary, clock.unix_timestamp)?;
+
+        Self::release_to_beneficiary(
+            program_id,
+            &mut escrow,
+            escrow_account,
+            beneficiary,
+            creator_stats_account,
+            account_info_iter,
+            clock.unix_timestamp,
+        )
+    }
 
+    /// The escrow reached quorum, its release time has come and nothing blocks
+    /// paying `beneficiary`
+    fn check_finalizable(escrow: &Escrow, beneficiary: &AccountInfo, now: i64) -> ProgramResult {
         if escrow.is_completed {
+            msg!("Escrow {} is already completed", escrow.id);
             return Err(EscrowError::EscrowCompleted.into());
         }
 
-        if !escrow.approvals.is_empty() {
-            return Err(EscrowError::CannotCancelAfterApprovals.into());
+        if escrow.is_declined {
+            msg!("Escrow {} was declined", escrow.id);
+            return Err(EscrowError::EscrowDeclined.into());
         }
 
-        let clock = Clock::get()?;
+        if escrow.frozen {
+            msg!("Escrow {} is frozen", escrow.id);
+            return Err(EscrowError::EscrowFrozen.into());
+        }
+
+        if escrow.beneficiary != *beneficiary.key {
+            msg!("Account {} is not the beneficiary of escrow {}", beneficiary.key, escrow.id);
+            return Err(EscrowError::InvalidBeneficiary.into());
+        }
+
+        if escrow.is_too_young(now) {
+            msg!("Escrow {} is younger than {} seconds", escrow.id, escrow.min_age_secs);
+            return Err(EscrowError::EscrowTooYoung.into());
+        }
+
+        if !escrow.is_releasable(now) {
+            msg!("Escrow {} is not releasable, releasable_at {}", escrow.id, escrow.releasable_at);
+            return Err(EscrowError::EscrowNotReleasable.into());
+        }
+
+        // Approvals replaced or expired during the delay no longer carry quorum
+        if !escrow.can_be_released(now) {
+            msg!("Escrow {} no longer has quorum", escrow.id);
+            return Err(EscrowError::EscrowNotReleasable.into());
+        }
+        Ok(())
+    }
+
+    /// Release an escrow to its beneficiary and have the beneficiary put the
+    /// whole amount straight into a new escrow, so the funds never sit free
+    #[allow(clippy::too_many_arguments)]
+    fn process_release_into(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        beneficiary: Pubkey,
+        approver1: Pubkey,
+        approver2: Pubkey,
+        approver3: Option<Pubkey>,
+        description: String,
+        options: EscrowOptions,
+    ) -> ProgramResult {
+        // The chained escrow is funded in lamports from the released amount, and
+        // its create accounts leave no room for an idempotency marker
+        if options.wrap_sol || options.token_mint.is_some() || options.idempotency_key.is_some() {
+            msg!("A chained escrow cannot hold wrapped SOL or tokens, or take an idempotency key");
+            return Err(ProgramError::InvalidInstructionData);
+        }
+
+        let (release_accounts, create_accounts) = accounts.split_at(2);
+        let escrow_account = &release_accounts[0];
+        let creator_stats_account = &release_accounts[1];
+        let released_beneficiary = &create_accounts[0];
+        let account_info_iter = &mut create_accounts[6..].iter();
+
+        if !released_beneficiary.is_signer {
+            msg!("Missing signature from {}", released_beneficiary.key);
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        if escrow_account.owner != program_id {
+            msg!("Account {} is not owned by this program", escrow_account.key);
+            return Err(EscrowError::InvalidEscrowAccount.into());
+        }
+
+        let mut escrow = {
+            let data = escrow_account.data.borrow();
+            let mut data_slice: &[u8] = &data;
+            Escrow::deserialize(&mut data_slice)?
+        };
+
+        // Only a payout of the whole amount in lamports can fund the chained escrow
+        if escrow.token_mint.is_some() {
+            msg!("Escrow {} holds SPL tokens", escrow.id);
+            return Err(EscrowError::UnsupportedForTokenEscrow.into());
+        }
+        if escrow.target_usd.is_some() {
+            msg!("Escrow {} is pegged to USD", escrow.id);
+            return Err(EscrowError::IncompatibleEscrows.into());
+        }
+
+        let clock = Clock::get()?;
+        Self::check_finalizable(&escrow, released_beneficiary, clock.unix_timestamp)?;
+
+        Self::release_to_beneficiary(
+            program_id,
+            &mut escrow,
+            escrow_account,
+            released_beneficiary,
+            creator_stats_account,
+            account_info_iter,
+            clock.unix_timestamp,
+        )?;
+
+        verbose_msg!("Escrow {} released into a new escrow", escrow.id);
+        Self::process_create_escrow(
+            program_id,
+            &create_accounts[..6],
+            EscrowIdSource::Counter,
+            escrow.amount,
+            beneficiary,
+            approver1,
+            approver2,
+            approver3,
+            description,
+            options,
+        )
+    }
+
+    /// Complete the escrow and pay out the beneficiary, taking the executor's
+    /// signature and then unwrapping WSOL or reading the token accounts from the
+    /// trailing accounts as needed.
+    /// Invariant: the completed escrow is serialized before any lamports move to
+    /// the beneficiary, so a payout can never happen without committed state.
+    fn release_to_beneficiary<'a, 'b: 'a, I: Iterator<Item = &'a AccountInfo<'b>>>(
+        program_id: &Pubkey,
+        escrow: &mut Escrow,
+        escrow_account: &AccountInfo<'b>,
+        beneficiary: &AccountInfo<'b>,
+        creator_stats_account: &AccountInfo<'b>,
+        account_info_iter: &mut I,
+        now: i64,
+    ) -> ProgramResult {
+        // Every release path pays through here, so the payee is checked here too.
+        // The escrow PDA itself would complete the escrow with the funds still inside
+        if *beneficiary.key != escrow.beneficiary {
+            msg!("Account {} is not the beneficiary of escrow {}", beneficiary.key, escrow.id);
+            return Err(EscrowError::InvalidBeneficiary.into());
+        }
+
+        // Completing an empty escrow would be a silent no-op transfer
+        if escrow.amount == 0 {
+            msg!("Escrow {} holds nothing to release", escrow.id);
+            return Err(EscrowError::NothingToRelease.into());
+        }
+
+        // An account with no lamports has never been created on-chain. Only meaningful
+        // after the key check above, or any funded account would pass
+        if escrow.require_existing_beneficiary && beneficiary.lamports() == 0 {
+            msg!("Beneficiary {} does not exist yet", beneficiary.key);
+            return Err(EscrowError::BeneficiaryNotFound.into());
+        }
+
+        if let Some(executor) = escrow.executor {
+            let executor_account = next_account_info(account_info_iter)?;
+            if *executor_account.key != executor || !executor_account.is_signer {
+                msg!("Executor {} must sign to release escrow {}", executor, escrow.id);
+                return Err(ProgramError::MissingRequiredSignature);
+            }
+        }
+
+        Self::log_settlement_snapshot(escrow)?;
+        // A USD target pays its lamport value at the current price, never more than
+        // remains, and hands the surplus back to the creator
+        let (payout, surplus_account) = match escrow.target_usd {
+            Some(target_usd) => {
+                let price_feed = next_account_info(account_info_iter)?;
+                let creator = next_account_info(account_info_iter)?;
+                if *creator.key != escrow.creator {
+                    msg!("Account {} is not the creator of escrow {}", creator.key, escrow.id);
+                    return Err(EscrowError::InvalidRefundAccount.into());
+                }
+                let price = Self::load_price_feed(escrow, price_feed, now)?;
+                let pegged = price.lamports_for_usd_cents(target_usd);
+                (pegged.min(escrow.remaining()), Some(creator))
+            }
+            None => (escrow.remaining(), None),
+        };
+        let surplus = escrow.remaining() - payout;
+        escrow.record_release(payout)?;
+        escrow.is_completed = true;
+        escrow.completed_at = now;
+
+        if escrow.wrapped_sol {
+            let wsol_account = next_account_info(account_info_iter)?;
+            let token_program = next_account_info(account_info_iter)?;
+            Self::unwrap_sol(program_id, escrow, escrow_account, wsol_account, token_program)?;
+        }
+        let token_accounts = Self::next_token_accounts(escrow, account_info_iter)?;
+
+        escrow.serialize(&mut &mut escrow_account.data.borrow_mut()[..])?;
+
+        // Transfer funds from escrow to beneficiary
+        Self::pay_out(program_id, escrow, escrow_account, beneficiary, token_accounts, payout)?;
+        if let Some(creator) = surplus_account {
+            Self::transfer_lamports(escrow_account, creator, surplus)?;
+        }
+
+        Self::release_creator_slot(program_id, &escrow.creator, creator_stats_account)?;
+
+        verbose_msg!("Escrow {} released to beneficiary", escrow.id);
+        verbose_msg!("Amount released: {} lamports, {} refunded", payout, surplus);
+        escrow.log_summary();
+
+        let event = ReleaseEvent {
+            escrow_id: escrow.id,
+            beneficiary: escrow.beneficiary,
+            amount: escrow.amount,
+            approvals: escrow.approvals.clone(),
+            completed_at: escrow.completed_at,
+            released: escrow.released,
+        };
+        sol_log_data(&[&event.try_to_vec()?]);
+        Ok(())
+    }
+
+    fn process_force_finalize(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let creator = next_account_info(account_info_iter)?;
+        let arbiter = next_account_info(account_info_iter)?;
+        let escrow_account = next_account_info(account_info_iter)?;
+        let beneficiary = next_account_info(account_info_iter)?;
+        let refund_account = next_account_info(account_info_iter)?;
+        let creator_stats_account = next_account_info(account_info_iter)?;
+
+        if !creator.is_signer || !arbiter.is_signer {
+            msg!("Missing signature from {} or {}", creator.key, arbiter.key);
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        if escrow_account.owner != program_id {
+            msg!("Account {} is not owned by this program", escrow_account.key);
+            return Err(EscrowError::InvalidEscrowAccount.into());
+        }
+
+        // Pre-upgrade escrows keep their original allocation and layout
+        if escrow_account.data_len() == LegacyEscrow::SIZE {
+            return Self::force_finalize_legacy(
+                creator,
+                arbiter,
+                escrow_account,
+                beneficiary,
+                refund_account,
+                Clock::get()?.unix_timestamp,
+            );
+        }
+
+        let mut escrow = {
+            let data = escrow_account.data.borrow();
+            let mut data_slice: &[u8] = &data;
+            Escrow::deserialize(&mut data_slice)?
+        };
+
+        if escrow.creator != *creator.key || escrow.arbiter != Some(*arbiter.key) {
+            msg!("Signers are not the creator and arbiter of escrow {}", escrow.id);
+            return Err(EscrowError::Unauthorized.into());
+        }
+
+        if escrow.is_completed {
+            msg!("Escrow {} is already completed", escrow.id);
+            return Err(EscrowError::EscrowCompleted.into());
+        }
+
+        if escrow.beneficiary != *beneficiary.key {
+            msg!("Account {} is not the beneficiary of escrow {}", beneficiary.key, escrow.id);
+            return Err(EscrowError::InvalidBeneficiary.into());
+        }
+
+        if *refund_account.key != escrow.refund_recipient() {
+            msg!(
+                "Refund account {} does not match {}",
+                refund_account.key,
+                escrow.refund_recipient()
+            );
+            return Err(EscrowError::InvalidRefundAccount.into());
+        }
+
+        // Legacy rules only: approvals are counted as they stand, ignoring
+        // weights, categories, expiry, freezes and release timing
+        let clock = Clock::get()?;
+        if escrow.meets_legacy_quorum() {
+            verbose_msg!("Escrow {} force-finalized under legacy rules: releasing", escrow.id);
+            return Self::release_to_beneficiary(
+                program_id,
+                &mut escrow,
+                escrow_account,
+                beneficiary,
+                creator_stats_account,
+                account_info_iter,
+                clock.unix_timestamp,
+            );
+        }
+
+        Self::log_settlement_snapshot(&escrow)?;
+        if escrow.wrapped_sol {
+            let wsol_account = next_account_info(account_info_iter)?;
+            let token_program = next_account_info(account_info_iter)?;
+            Self::unwrap_sol(program_id, &mut escrow, escrow_account, wsol_account, token_program)?;
+        }
+        let token_accounts = Self::next_token_accounts(&escrow, account_info_iter)?;
+
         escrow.is_completed = true;
         escrow.completed_at = clock.unix_timestamp;
+        escrow.serialize(&mut &mut escrow_account.data.borrow_mut()[..])?;
+
+        Self::pay_out(
+            program_id,
+            &escrow,
+            escrow_account,
+            refund_account,
+            token_accounts,
+            escrow.amount,
+        )?;
+
+        Self::release_creator_slot(program_id, &escrow.creator, creator_stats_account)?;
+        Self::set_cancellation_receipt(&escrow, CancelReason::Dispute, escrow.amount)?;
+
+        verbose_msg!(
+            "Escrow {} force-finalized under legacy rules: {} lamports refunded",
+            escrow.id,
+            escrow.amount
+        );
+        Ok(())
+    }
+
+    /// `ForceFinalize` for an escrow still in the pre-upgrade layout. Such escrows
+    /// have no arbiter, so the beneficiary co-signs in its place, and hold neither
+    /// creator stats nor tokens. The escrow is written back in its own layout
+    fn force_finalize_legacy(
+        creator: &AccountInfo,
+        arbiter: &AccountInfo,
+        escrow_account: &AccountInfo,
+        beneficiary: &AccountInfo,
+        refund_account: &AccountInfo,
+        now: i64,
+    ) -> ProgramResult {
+        let mut escrow = {
+            let data = escrow_account.data.borrow();
+            let mut data_slice: &[u8] = &data;
+            LegacyEscrow::deserialize(&mut data_slice)?
+        };
+
+        if escrow.creator != *creator.key || escrow.beneficiary != *arbiter.key {
+            msg!("Signers are not the creator and beneficiary of escrow {}", escrow.id);
+            return Err(EscrowError::Unauthorized.into());
+        }
+
+        if escrow.is_completed {
+            msg!("Escrow {} is already completed", escrow.id);
+            return Err(EscrowError::EscrowCompleted.into());
+        }
+
+        if escrow.beneficiary != *beneficiary.key {
+            msg!("Account {} is not the beneficiary of escrow {}", beneficiary.key, escrow.id);
+            return Err(EscrowError::InvalidBeneficiary.into());
+        }
+
+        if escrow.creator != *refund_account.key {
+            msg!("Refund account {} does not match {}", refund_account.key, escrow.creator);
+            return Err(EscrowError::InvalidRefundAccount.into());
+        }
+
+        let releasing = escrow.meets_legacy_quorum();
+        escrow.is_completed = true;
+        escrow.completed_at = now;
+        escrow.serialize(&mut &mut escrow_account.data.borrow_mut()[..])?;
+
+        if releasing {
+            Self::transfer_lamports(escrow_account, beneficiary, escrow.amount)?;
+            verbose_msg!("Pre-upgrade escrow {} force-finalized: releasing", escrow.id);
+            return Ok(());
+        }
+
+        Self::transfer_lamports(escrow_account, refund_account, escrow.amount)?;
+        let receipt = CancellationReceipt {
+            escrow_id: escrow.id,
+            reason: CancelReason::Dispute,
+            refunded: escrow.amount,
+        };
+        set_return_data(&receipt.try_to_vec()?);
+
+        verbose_msg!(
+            "Pre-upgrade escrow {} force-finalized: {} lamports refunded",
+            escrow.id,
+            escrow.amount
+        );
+        Ok(())
+    }
+
+    fn process_change_threshold(accounts: &[AccountInfo], new_threshold: u8) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let creator = next_account_info(account_info_iter)?;
+        let escrow_account = next_account_info(account_info_iter)?;
+        let signers: Vec<&AccountInfo> = account_info_iter.collect();
+
+        if !creator.is_signer || signers.iter().any(|s| !s.is_signer) {
+            msg!("Creator and every listed approver must sign");
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        let mut data = escrow_account.data.borrow_mut();
+        let mut data_slice: &[u8] = &data;
+        let mut escrow = Escrow::deserialize(&mut data_slice)?;
+
+        if escrow.creator != *creator.key {
+            msg!("{} is not the creator of escrow {}", creator.key, escrow.id);
+            return Err(EscrowError::Unauthorized.into());
+        }
+
+        // Unanimous: every distinct approver must be among the signers
+        if escrow.unique_approvers().iter().any(|a| !signers.iter().any(|s| s.key == a)) {
+            msg!("Every approver of escrow {} must sign", escrow.id);
+            return Err(EscrowError::Unauthorized.into());
+        }
+
+        if escrow.is_completed {
+            msg!("Escrow {} is already completed", escrow.id);
+            return Err(EscrowError::EscrowCompleted.into());
+        }
+
+        if escrow.is_declined {
+            msg!("Escrow {} was declined", escrow.id);
+            return Err(EscrowError::EscrowDeclined.into());
+        }
+
+        if new_threshold == 0 || new_threshold as usize > escrow.total_approvers() {
+            msg!("Threshold {} must be 1-{}", new_threshold, escrow.total_approvers());
+            return Err(EscrowError::InvalidThreshold.into());
+        }
+
+        escrow.threshold = new_threshold;
 
-        // Return funds to creator
-        **escrow_account.try_borrow_mut_lamports()? -= escrow.amount;
-        **creator.try_borrow_mut_lamports()? += escrow.amount;
+        // A pending release follows the new quorum either way
+        let now = Clock::get()?.unix_timestamp;
+        if !escrow.can_be_released(now) {
+            escrow.releasable_at = 0;
+        } else if escrow.releasable_at == 0 {
+            escrow.releasable_at = escrow.release_unlocks_at(now).ok_or(EscrowError::AmountOverflow)?;
+        }
 
         escrow.serialize(&mut &mut data[..])?;
 
-        msg!("Escrow {} cancelled, {} lamports refunded", escrow.id, escrow.amount);
+        verbose_msg!("Escrow {} approval threshold set to {}", escrow.id, new_threshold);
         Ok(())
     }
-}
 
+    fn process_acknowledge_escrow(accounts: &[AccountInfo]) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let beneficiary = next_account_info(account_info_iter)?;
+        let escrow_account = next_account_info(account_info_iter)?;
+
+        if !beneficiary.is_signer {
+            msg!("Missing signature from {}", beneficiary.key);
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        let mut data = escrow_account.data.borrow_mut();
+        let mut data_slice: &[u8] = &data;
+        let mut escrow = Escrow::deserialize(&mut data_slice)?;
+
+        if escrow.beneficiary != *beneficiary.key {
+            msg!("{} is not the beneficiary of escrow {}", beneficiary.key, escrow.id);
+            return Err(EscrowError::Unauthorized.into());
+        }
+
+        if escrow.is_completed {
+            msg!("Escrow {} is already completed", escrow.id);
+            return Err(EscrowError::EscrowCompleted.into());
+        }
+
+        if escrow.is_declined {
+            msg!("Escrow {} was declined", escrow.id);
+            return Err(EscrowError::EscrowDeclined.into());
+        }
+
+        if escrow.beneficiary_acknowledged {
+            msg!("Escrow {} was already acknowledged", escrow.id);
+            return Err(EscrowError::AlreadyApproved.into());
+        }
+
+        escrow.beneficiary_acknowledged = true;
+
+        // Quorum may already be in, in which case the release moves to FinalizeRelease
+        let now = Clock::get()?.unix_timestamp;
+        if escrow.can_be_released(now) && escrow.releasable_at == 0 {
+            escrow.releasable_at = escrow.release_unlocks_at(now).ok_or(EscrowError::AmountOverflow)?;
+            verbose_msg!("Escrow {} releasable at {}", escrow.id, escrow.releasable_at);
+        }
+
+        escrow.serialize(&mut &mut data[..])?;
+
+        verbose_msg!("Escrow {} acknowledged by beneficiary", escrow.id);
+        Ok(())
+    }
+
+    fn process_cancel_escrow(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let creator = next_account_info(account_info_iter)?;
+        let escrow_account = next_account_info(account_info_iter)?;
+        let system_program = next_account_info(account_info_iter)?;
+        let creator_stats_account = next_account_info(account_info_iter)?;
+
+        if !creator.is_signer {
+            msg!("Missing signature from {}", creator.key);
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        if escrow_account.owner != program_id {
+            msg!("Account {} is not owned by this program", escrow_account.key);
+            return Err(EscrowError::InvalidEscrowAccount.into());
+        }
+
+        let mut escrow = {
+            let data = escrow_account.data.borrow();
+            let mut data_slice: &[u8] = &data;
+            Escrow::deserialize(&mut data_slice)?
+        };
+
+        // Zeroed data decodes to an escrow nobody created
+        if !escrow.is_initialized() {
+            msg!("Account {} holds no escrow", escrow_account.key);
+            return Err(EscrowError::InvalidEscrowAccount.into());
+        }
+
+        escrow.check_cancel(creator.key)?;
+
+        let clock = Clock::get()?;
+        if escrow.is_cancel_locked(clock.unix_timestamp) {
+            msg!("Escrow {} cannot be cancelled yet", escrow.id);
+            return Err(EscrowError::CancelLocked.into());
+        }
+
+        let refund_account = match escrow.refund_to {
+            Some(_) => next_account_info(account_info_iter)?,
+            None => creator,
+        };
+        if *refund_account.key != escrow.refund_recipient() {
+            msg!(
+                "Refund account {} does not match {}",
+                refund_account.key,
+                escrow.refund_recipient()
+            );
+            return Err(EscrowError::InvalidRefundAccount.into());
+        }
+
+        Self::log_settlement_snapshot(&escrow)?;
+        if escrow.wrapped_sol {
+            let wsol_account = next_account_info(account_info_iter)?;
+            let token_program = next_account_info(account_info_iter)?;
+            Self::unwrap_sol(program_id, &mut escrow, escrow_account, wsol_account, token_program)?;
+        }
+        let token_accounts = Self::next_token_accounts(&escrow, account_info_iter)?;
+
+        // The refund must leave the escrow account rent exempt
+        Self::check_escrow_funded(&escrow, escrow_account)?;
+
+        escrow.is_completed = true;
+        escrow.completed_at = clock.unix_timestamp;
+
+        // Return funds to creator or the configured refund account. Checked, so an
+        // account drained earlier in the same transaction fails instead of panicking
+        Self::pay_out(
+            program_id,
+            &escrow,
+            escrow_account,
+            refund_account,
+            token_accounts,
+            escrow.amount,
+        )?;
+
+        Self::release_creator_slot(program_id, &escrow.creator, creator_stats_account)?;
+
+        escrow.serialize(&mut &mut escrow_account.data.borrow_mut()[..])?;
+
+        let reason = if escrow.is_declined {
+            CancelReason::Declined
+        } else {
+            CancelReason::Creator
+        };
+        Self::set_cancellation_receipt(&escrow, reason, escrow.amount)?;
+
+        verbose_msg!("Escrow {} cancelled, {} lamports refunded", escrow.id, escrow.amount);
+        escrow.log_summary();
+        Ok(())
+    }
+
+    fn process_append_note(accounts: &[AccountInfo], note: String) -> ProgramResult {
+        if note.is_empty() {
+            msg!("Note must not be empty");
+            return Err(ProgramError::InvalidInstructionData);
+        }
+
+        let account_info_iter = &mut accounts.iter();
+        let creator = next_account_info(account_info_iter)?;
+        let escrow_account = next_account_info(account_info_iter)?;
+
+        if !creator.is_signer {
+            msg!("Missing signature from {}", creator.key);
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        let mut data = escrow_account.data.borrow_mut();
+        let mut data_slice: &[u8] = &data;
+        let mut escrow = Escrow::deserialize(&mut data_slice)?;
+
+        if escrow.creator != *creator.key {
+            msg!("{} is not the creator of escrow {}", creator.key, escrow.id);
+            return Err(EscrowError::Unauthorized.into());
+        }
+
+        if escrow.is_completed {
+            msg!("Escrow {} is already completed", escrow.id);
+            return Err(EscrowError::EscrowCompleted.into());
+        }
+
+        // Notes are bounded so the escrow always fits in MAX_SIZE
+        if !escrow.can_append_note(&note) {
+            msg!("Note does not fit in escrow {}", escrow.id);
+            return Err(EscrowError::NoteLimitExceeded.into());
+        }
+
+        escrow.notes.push(note);
+        escrow.serialize(&mut &mut data[..])?;
+
+        verbose_msg!(
+            "Escrow {} note added ({}/{})",
+            escrow.id,
+            escrow.notes.len(),
+            Escrow::MAX_NOTES
+        );
+        Ok(())
+    }
+
+    fn process_decline_escrow(accounts: &[AccountInfo]) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let beneficiary = next_account_info(account_info_iter)?;
+        let escrow_account = next_account_info(account_info_iter)?;
+
+        if !beneficiary.is_signer {
+            msg!("Missing signature from {}", beneficiary.key);
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        let mut data = escrow_account.data.borrow_mut();
+        let mut data_slice: &[u8] = &data;
+        let mut escrow = Escrow::deserialize(&mut data_slice)?;
+
+        if escrow.beneficiary != *beneficiary.key {
+            msg!("{} is not the beneficiary of escrow {}", beneficiary.key, escrow.id);
+            return Err(EscrowError::Unauthorized.into());
+        }
+
+        if escrow.is_completed {
+            msg!("Escrow {} is already completed", escrow.id);
+            return Err(EscrowError::EscrowCompleted.into());
+        }
+
+        if escrow.is_declined {
+            msg!("Escrow {} was declined", escrow.id);
+            return Err(EscrowError::EscrowDeclined.into());
+        }
+
+        escrow.is_declined = true;
+        escrow.serialize(&mut &mut data[..])?;
+
+        verbose_msg!("Escrow {} declined by beneficiary", escrow.id);
+        Ok(())
+    }
+
+    fn process_update_config(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        max_active_escrows: Option<u32>,
+        max_description_len: Option<u16>,
+        strict_description: Option<bool>,
+        max_expiry_secs: Option<i64>,
+        expiry_fee_bps: Option<u16>,
+        max_escrow_id: Option<u64>,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let authority = next_account_info(account_info_iter)?;
+        let counter_account = next_account_info(account_info_iter)?;
+
+        if !authority.is_signer {
+            msg!("Missing signature from {}", authority.key);
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        Self::check_counter_account(program_id, counter_account)?;
+
+        let mut data = counter_account.data.borrow_mut();
+        let mut data_slice: &[u8] = &data;
+        let mut counter = EscrowCounter::deserialize(&mut data_slice)?;
+
+        if counter.authority != *authority.key {
+            msg!("{} is not the counter authority", authority.key);
+            return Err(EscrowError::Unauthorized.into());
+        }
+
+        if let Some(max_active_escrows) = max_active_escrows {
+            counter.max_active_escrows = max_active_escrows;
+            verbose_msg!("Max active escrows per creator set to {}", max_active_escrows);
+        }
+
+        if let Some(max_description_len) = max_description_len {
+            if max_description_len as usize > Escrow::MAX_DESCRIPTION_LEN {
+                msg!(
+                    "Description limit {} exceeds {}",
+                    max_description_len,
+                    Escrow::MAX_DESCRIPTION_LEN
+                );
+                return Err(ProgramError::InvalidInstructionData);
+            }
+            counter.max_description_len = max_description_len;
+            verbose_msg!("Max description length set to {}", max_description_len);
+        }
+
+        if let Some(strict_description) = strict_description {
+            counter.strict_description = strict_description;
+            verbose_msg!("Strict descriptions set to {}", strict_description);
+        }
+
+        if let Some(max_expiry_secs) = max_expiry_secs {
+            if max_expiry_secs <= 0 {
+                msg!("Expiry horizon {} must be positive", max_expiry_secs);
+                return Err(EscrowError::InvalidDeadline.into());
+            }
+            counter.max_expiry_secs = max_expiry_secs;
+            verbose_msg!("Max expiry set to {} seconds", max_expiry_secs);
+        }
+
+        if let Some(expiry_fee_bps) = expiry_fee_bps {
+            if expiry_fee_bps > EscrowCounter::MAX_EXPIRY_FEE_BPS {
+                msg!(
+                    "Expiry fee {} bps exceeds {}",
+                    expiry_fee_bps,
+                    EscrowCounter::MAX_EXPIRY_FEE_BPS
+                );
+                return Err(ProgramError::InvalidInstructionData);
+            }
+            counter.expiry_fee_bps = expiry_fee_bps;
+            verbose_msg!("Expiry fee set to {} bps", expiry_fee_bps);
+        }
+
+        if let Some(max_escrow_id) = max_escrow_id {
+            if max_escrow_id != 0 && max_escrow_id < counter.count {
+                msg!("Id ceiling {} is below the current count {}", max_escrow_id, counter.count);
+                return Err(ProgramError::InvalidInstructionData);
+            }
+            counter.max_escrow_id = max_escrow_id;
+            verbose_msg!("Max escrow id set to {}", max_escrow_id);
+        }
+
+        counter.serialize(&mut &mut data[..])?;
+        Ok(())
+    }
+
+    fn process_emergency_recover(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let authority = next_account_info(account_info_iter)?;
+        let counter_account = next_account_info(account_info_iter)?;
+        let escrow_account = next_account_info(account_info_iter)?;
+        let creator = next_account_info(account_info_iter)?;
+        let creator_stats_account = next_account_info(account_info_iter)?;
+
+        if !authority.is_signer {
+            msg!("Missing signature from {}", authority.key);
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        Self::check_counter_account(program_id, counter_account)?;
+
+        let counter = {
+            let data = counter_account.data.borrow();
+            let mut data_slice: &[u8] = &data;
+            EscrowCounter::deserialize(&mut data_slice)?
+        };
+
+        if counter.authority != *authority.key {
+            msg!("{} is not the counter authority", authority.key);
+            return Err(EscrowError::Unauthorized.into());
+        }
+
+        if escrow_account.owner != program_id {
+            msg!("Account {} is not owned by this program", escrow_account.key);
+            return Err(EscrowError::InvalidEscrowAccount.into());
+        }
+
+        let mut escrow = {
+            let data = escrow_account.data.borrow();
+            let mut data_slice: &[u8] = &data;
+            Escrow::deserialize(&mut data_slice)?
+        };
+
+        if !escrow.is_initialized() {
+            msg!("Account {} holds no escrow", escrow_account.key);
+            return Err(EscrowError::InvalidEscrowAccount.into());
+        }
+
+        if escrow.is_completed {
+            msg!("Escrow {} is already completed", escrow.id);
+            return Err(EscrowError::EscrowCompleted.into());
+        }
+
+        if escrow.creator != *creator.key {
+            msg!("Refund account {} is not the creator of escrow {}", creator.key, escrow.id);
+            return Err(EscrowError::InvalidRefundAccount.into());
+        }
+
+        Self::log_settlement_snapshot(&escrow)?;
+        if escrow.wrapped_sol {
+            let wsol_account = next_account_info(account_info_iter)?;
+            let token_program = next_account_info(account_info_iter)?;
+            Self::unwrap_sol(program_id, &mut escrow, escrow_account, wsol_account, token_program)?;
+        }
+        let token_accounts = Self::next_token_accounts(&escrow, account_info_iter)?;
+
+        Self::check_escrow_funded(&escrow, escrow_account)?;
+
+        let clock = Clock::get()?;
+        escrow.is_completed = true;
+        escrow.completed_at = clock.unix_timestamp;
+        escrow.serialize(&mut &mut escrow_account.data.borrow_mut()[..])?;
+
+        Self::pay_out(program_id, &escrow, escrow_account, creator, token_accounts, escrow.amount)?;
+
+        Self::release_creator_slot(program_id, &escrow.creator, creator_stats_account)?;
+
+        let event = EmergencyRecoveryEvent {
+            escrow_id: escrow.id,
+            authority: *authority.key,
+            creator: escrow.creator,
+            amount: escrow.amount,
+            approvals: escrow.approvals.len() as u8,
+            recovered_at: clock.unix_timestamp,
+        };
+        sol_log_data(&[&event.try_to_vec()?]);
+        Self::set_cancellation_receipt(&escrow, CancelReason::Emergency, escrow.amount)?;
+
+        msg!(
+            "ADMIN: escrow {} force-refunded {} lamports to creator by authority {}",
+            escrow.id,
+            escrow.amount,
+            authority.key
+        );
+        Ok(())
+    }
+
+    fn process_get_counter(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let counter_account = next_account_info(account_info_iter)?;
+
+        Self::check_counter_account(program_id, counter_account)?;
+
+        let data = counter_account.data.borrow();
+        let mut data_slice: &[u8] = &data;
+        let counter = EscrowCounter::deserialize(&mut data_slice)?;
+
+        msg!("Counter at {}, authority {}", counter.count, counter.authority);
+        set_return_data(&counter.try_to_vec()?);
+        Ok(())
+    }
+
+    fn process_derive_escrow_address(program_id: &Pubkey, escrow_id: u64) -> ProgramResult {
+        let (escrow_pda, bump) =
+            Pubkey::find_program_address(&[b"escrow", &escrow_id.to_le_bytes()], program_id);
+
+        msg!("Escrow {} at {}, bump {}", escrow_id, escrow_pda, bump);
+        set_return_data(&(escrow_pda, bump).try_to_vec()?);
+        Ok(())
+    }
+
+    fn process_forfeit_escrow(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let creator = next_account_info(account_info_iter)?;
+        let beneficiary = next_account_info(account_info_iter)?;
+        let escrow_account = next_account_info(account_info_iter)?;
+        let sink = next_account_info(account_info_iter)?;
+        let creator_stats_account = next_account_info(account_info_iter)?;
+
+        if !creator.is_signer || !beneficiary.is_signer {
+            msg!("Missing signature from {} or {}", creator.key, beneficiary.key);
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        if *sink.key != incinerator::ID {
+            msg!("Sink {} is not the incinerator", sink.key);
+            return Err(EscrowError::InvalidIncinerator.into());
+        }
+
+        if escrow_account.owner != program_id {
+            msg!("Account {} is not owned by this program", escrow_account.key);
+            return Err(EscrowError::InvalidEscrowAccount.into());
+        }
+
+        let escrow = {
+            let data = escrow_account.data.borrow();
+            let mut data_slice: &[u8] = &data;
+            Escrow::deserialize(&mut data_slice)?
+        };
+
+        if escrow.creator != *creator.key || escrow.beneficiary != *beneficiary.key {
+            msg!("Signers are not the creator and beneficiary of escrow {}", escrow.id);
+            return Err(EscrowError::Unauthorized.into());
+        }
+
+        if escrow.is_completed {
+            msg!("Escrow {} is already completed", escrow.id);
+            return Err(EscrowError::EscrowCompleted.into());
+        }
+
+        // A frozen escrow is awaiting the arbiter, whom the parties cannot bypass
+        if escrow.frozen {
+            msg!("Escrow {} is frozen", escrow.id);
+            return Err(EscrowError::EscrowFrozen.into());
+        }
+
+        if escrow.wrapped_sol {
+            msg!("Escrow {} holds wrapped SOL", escrow.id);
+            return Err(EscrowError::UnsupportedForWrappedSol.into());
+        }
+        if escrow.token_mint.is_some() {
+            msg!("Escrow {} holds SPL tokens", escrow.id);
+            return Err(EscrowError::UnsupportedForTokenEscrow.into());
+        }
+
+        Self::check_escrow_funded(&escrow, escrow_account)?;
+        Self::log_settlement_snapshot(&escrow)?;
+        Self::release_creator_slot(program_id, &escrow.creator, creator_stats_account)?;
+
+        // Burn the amount, return the rent and zero the data so the account is dropped
+        Self::transfer_lamports(escrow_account, sink, escrow.amount)?;
+        Self::transfer_lamports(escrow_account, creator, escrow_account.lamports())?;
+        escrow_account.data.borrow_mut().fill(0);
+
+        verbose_msg!("Escrow {} forfeited, {} lamports burned", escrow.id, escrow.amount);
+        Ok(())
+    }
+
+    fn process_reclaim_failed_create(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        escrow_id: u64,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let creator = next_account_info(account_info_iter)?;
+        let escrow_account = next_account_info(account_info_iter)?;
+
+        if !creator.is_signer {
+            msg!("Missing signature from {}", creator.key);
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        if escrow_account.owner != program_id {
+            msg!("Account {} is not owned by this program", escrow_account.key);
+            return Err(EscrowError::InvalidEscrowAccount.into());
+        }
+
+        // A closed escrow revived by a transfer later in the same transaction keeps
+        // this program as owner but holds no record; anything else is closed by its
+        // own instructions
+        if escrow_account.data.borrow().iter().any(|b| *b != 0) {
+            msg!("Account {} still holds an escrow record", escrow_account.key);
+            return Err(EscrowError::InvalidEscrowAccount.into());
+        }
+
+        // Nothing recorded who paid for the account, so only seeds naming the
+        // creator prove it; accounts at the global seeds stay put
+        let (escrow_pda, _escrow_bump) = Pubkey::find_program_address(
+            &[b"escrow", creator.key.as_ref(), &escrow_id.to_le_bytes()],
+            program_id,
+        );
+        if escrow_pda != *escrow_account.key {
+            msg!("Account {} is not the creator's escrow PDA {}", escrow_account.key, escrow_pda);
+            return Err(EscrowError::Unauthorized.into());
+        }
+
+        // Zero lamports and data so the runtime drops the account after this transaction
+        let lamports = escrow_account.lamports();
+        Self::transfer_lamports(escrow_account, creator, lamports)?;
+        escrow_account.data.borrow_mut().fill(0);
+
+        verbose_msg!("Escrow {} account reclaimed, {} lamports returned", escrow_id, lamports);
+        Ok(())
+    }
+
+    fn process_split_escrow(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        amount: u64,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let creator = next_account_info(account_info_iter)?;
+        let escrow_account = next_account_info(account_info_iter)?;
+        let new_escrow_account = next_account_info(account_info_iter)?;
+        let counter_account = next_account_info(account_info_iter)?;
+        let system_program = next_account_info(account_info_iter)?;
+        let creator_stats_account = next_account_info(account_info_iter)?;
+        let beneficiary_index_account = next_account_info(account_info_iter)?;
+
+        if !creator.is_signer {
+            msg!("Missing signature from {}", creator.key);
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        if !system_program::check_id(system_program.key) {
+            msg!("Expected the system program, got {}", system_program.key);
+            return Err(ProgramError::IncorrectProgramId);
+        }
+
+        Self::check_counter_account(program_id, counter_account)?;
+
+        let mut escrow = {
+            let data = escrow_account.data.borrow();
+            let mut data_slice: &[u8] = &data;
+            Escrow::deserialize(&mut data_slice)?
+        };
+
+        if escrow.creator != *creator.key {
+            msg!("{} is not the creator of escrow {}", creator.key, escrow.id);
+            return Err(EscrowError::Unauthorized.into());
+        }
+
+        if escrow.is_completed {
+            msg!("Escrow {} is already completed", escrow.id);
+            return Err(EscrowError::EscrowCompleted.into());
+        }
+
+        if escrow.is_declined {
+            msg!("Escrow {} was declined", escrow.id);
+            return Err(EscrowError::EscrowDeclined.into());
+        }
+
+        if escrow.frozen {
+            msg!("Escrow {} is frozen", escrow.id);
+            return Err(EscrowError::EscrowFrozen.into());
+        }
+
+        // The split-off escrow starts without approvals, so splitting an approved
+        // escrow would let the creator cancel most of it
+        if !escrow.approvals.is_empty() {
+            msg!("Escrow {} already has {} approvals", escrow.id, escrow.approvals.len());
+            return Err(EscrowError::CannotCancelAfterApprovals.into());
+        }
+
+        if escrow.wrapped_sol {
+            msg!("Escrow {} holds wrapped SOL", escrow.id);
+            return Err(EscrowError::UnsupportedForWrappedSol.into());
+        }
+        if escrow.token_mint.is_some() {
+            msg!("Escrow {} holds SPL tokens", escrow.id);
+            return Err(EscrowError::UnsupportedForTokenEscrow.into());
+        }
+        // Each half would owe the whole USD target
+        if escrow.target_usd.is_some() {
+            msg!("Escrow {} is pegged to USD", escrow.id);
+            return Err(EscrowError::IncompatibleEscrows.into());
+        }
+
+        // Both halves must keep a nonzero amount
+        if amount == 0 || amount >= escrow.amount {
+            msg!("Split amount {} must be below {}", amount, escrow.amount);
+            return Err(EscrowError::InsufficientFunds.into());
+        }
+
+        let counter = Self::reserve_escrow_id(counter_account)?;
+        let new_escrow_id = counter.count;
+
+        Self::reserve_creator_slot(
+            program_id,
+            creator,
+            creator_stats_account,
+            system_program,
+            counter.max_active_escrows,
+        )?;
+
+        // Fit the copied description even if the configured limit was lowered since
+        let description_len = escrow.description.len().max(counter.max_description_len as usize);
+        let new_escrow_bump = Self::create_escrow_account(
+            program_id,
+            creator,
+            new_escrow_account,
+            system_program,
+            Escrow::size_with_description(description_len, escrow.extra_approvers.len()),
+            &[b"escrow", &new_escrow_id.to_le_bytes()],
+        )?;
+
+        Self::append_beneficiary_index(
+            program_id,
+            creator,
+            beneficiary_index_account,
+            system_program,
+            &escrow.beneficiary,
+            new_escrow_account.key,
+        )?;
+
+        // Move the carved-off amount between the two program-owned accounts
+        Self::transfer_lamports(escrow_account, new_escrow_account, amount)?;
+
+        let clock = Clock::get()?;
+        let new_escrow = Escrow {
+            id: new_escrow_id,
+            amount,
+            approvals: Vec::new(),
+            is_completed: false,
+            created_at: clock.unix_timestamp,
+            completed_at: 0,
+            notes: Vec::new(),
+            is_declined: false,
+            cancel_approvals: Vec::new(),
+            creator_scoped: false,
+            releasable_at: 0,
+            bump: new_escrow_bump,
+            proofs: Vec::new(),
+            approval_comments: Vec::new(),
+            beneficiary_acknowledged: false,
+            released: 0,
+            approval_slots: Vec::new(),
+            ..escrow.clone()
+        };
+        new_escrow.serialize(&mut &mut new_escrow_account.data.borrow_mut()[..])?;
+
+        escrow.amount = escrow.amount.checked_sub(amount).ok_or(EscrowError::InsufficientFunds)?;
+        escrow.serialize(&mut &mut escrow_account.data.borrow_mut()[..])?;
+
+        verbose_msg!(
+            "Escrow {} split: {} lamports moved to escrow {}, {} lamports remain",
+            escrow.id,
+            amount,
+            new_escrow_id,
+            escrow.amount
+        );
+        Ok(())
+    }
+
+    fn process_clone_escrow(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        amount: u64,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let creator = next_account_info(account_info_iter)?;
+        let source_account = next_account_info(account_info_iter)?;
+        let new_escrow_account = next_account_info(account_info_iter)?;
+        let counter_account = next_account_info(account_info_iter)?;
+        let system_program = next_account_info(account_info_iter)?;
+        let creator_stats_account = next_account_info(account_info_iter)?;
+        let beneficiary_index_account = next_account_info(account_info_iter)?;
+
+        if !creator.is_signer {
+            msg!("Missing signature from {}", creator.key);
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        if !system_program::check_id(system_program.key) {
+            msg!("Expected the system program, got {}", system_program.key);
+            return Err(ProgramError::IncorrectProgramId);
+        }
+
+        Self::check_counter_account(program_id, counter_account)?;
+
+        if amount == 0 {
+            msg!("Clone amount must be nonzero");
+            return Err(EscrowError::InsufficientFunds.into());
+        }
+
+        if source_account.owner != program_id {
+            msg!("Account {} is not owned by this program", source_account.key);
+            return Err(EscrowError::InvalidEscrowAccount.into());
+        }
+
+        // The source is only read, so any past escrow can serve as a template
+        let source = {
+            let data = source_account.data.borrow();
+            let mut data_slice: &[u8] = &data;
+            Escrow::deserialize(&mut data_slice)?
+        };
+
+        if source.creator != *creator.key {
+            msg!("{} is not the creator of escrow {}", creator.key, source.id);
+            return Err(EscrowError::Unauthorized.into());
+        }
+
+        let counter = Self::reserve_escrow_id(counter_account)?;
+        let new_escrow_id = counter.count;
+
+        Self::reserve_creator_slot(
+            program_id,
+            creator,
+            creator_stats_account,
+            system_program,
+            counter.max_active_escrows,
+        )?;
+
+        // Fit the copied description even if the configured limit was lowered since
+        let description_len = source.description.len().max(counter.max_description_len as usize);
+        let new_escrow_bump = Self::create_escrow_account(
+            program_id,
+            creator,
+            new_escrow_account,
+            system_program,
+            Escrow::size_with_description(description_len, source.extra_approvers.len()),
+            &[b"escrow", &new_escrow_id.to_le_bytes()],
+        )?;
+
+        Self::append_beneficiary_index(
+            program_id,
+            creator,
+            beneficiary_index_account,
+            system_program,
+            &source.beneficiary,
+            new_escrow_account.key,
+        )?;
+
+        let transfer_ix = system_instruction::transfer(creator.key, new_escrow_account.key, amount);
+        invoke(
+            &transfer_ix,
+            &[creator.clone(), new_escrow_account.clone(), system_program.clone()],
+        )?;
+
+        let clock = Clock::get()?;
+        let new_escrow = Escrow {
+            id: new_escrow_id,
+            amount,
+            approvals: Vec::new(),
+            is_completed: false,
+            created_at: clock.unix_timestamp,
+            completed_at: 0,
+            notes: Vec::new(),
+            is_declined: false,
+            cancel_approvals: Vec::new(),
+            frozen: false,
+            creator_scoped: false,
+            wrapped_sol: false,
+            token_mint: None,
+            releasable_at: 0,
+            bump: new_escrow_bump,
+            proofs: Vec::new(),
+            approval_comments: Vec::new(),
+            beneficiary_acknowledged: false,
+            delegations: Vec::new(),
+            released: 0,
+            approval_slots: Vec::new(),
+            expires_at: source.expiry_for_copy_at(clock.unix_timestamp),
+            ..source.clone()
+        };
+        new_escrow.serialize(&mut &mut new_escrow_account.data.borrow_mut()[..])?;
+
+        verbose_msg!(
+            "Escrow {} cloned from escrow {} with {} lamports",
+            new_escrow_id,
+            source.id,
+            amount
+        );
+        Ok(())
+    }
+
+    fn process_delegate_approval(accounts: &[AccountInfo], delegate: Pubkey) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let approver = next_account_info(account_info_iter)?;
+        let escrow_account = next_account_info(account_info_iter)?;
+
+        if !approver.is_signer {
+            msg!("Missing signature from {}", approver.key);
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        let mut data = escrow_account.data.borrow_mut();
+        let mut data_slice: &[u8] = &data;
+        let mut escrow = Escrow::deserialize(&mut data_slice)?;
+
+        // Only an approver may delegate, so a delegate can never pass its role on
+        if !escrow.is_approver(approver.key) {
+            msg!("{} is not an approver of escrow {}", approver.key, escrow.id);
+            return Err(EscrowError::Unauthorized.into());
+        }
+
+        if escrow.is_completed {
+            msg!("Escrow {} is already completed", escrow.id);
+            return Err(EscrowError::EscrowCompleted.into());
+        }
+
+        escrow.delegations.retain(|d| d.approver != *approver.key);
+
+        // A delegate may stand in for only one approver and never be one itself
+        if delegate == Pubkey::default()
+            || escrow.is_approver(&delegate)
+            || escrow.is_delegate(&delegate)
+        {
+            msg!("Delegate {} is the default key, an approver or already a delegate", delegate);
+            return Err(EscrowError::InvalidDelegate.into());
+        }
+
+        escrow.delegations.push(Delegation {
+            approver: *approver.key,
+            delegate,
+        });
+        escrow.serialize(&mut &mut data[..])?;
+
+        verbose_msg!("Escrow {} approver {} delegated to {}", escrow.id, approver.key, delegate);
+        Ok(())
+    }
+
+    fn process_sweep_excess(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let creator = next_account_info(account_info_iter)?;
+        let escrow_account = next_account_info(account_info_iter)?;
+
+        if !creator.is_signer {
+            msg!("Missing signature from {}", creator.key);
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        if escrow_account.owner != program_id {
+            msg!("Account {} is not owned by this program", escrow_account.key);
+            return Err(EscrowError::InvalidEscrowAccount.into());
+        }
+
+        let escrow = {
+            let data = escrow_account.data.borrow();
+            let mut data_slice: &[u8] = &data;
+            Escrow::deserialize(&mut data_slice)?
+        };
+
+        if escrow.creator != *creator.key {
+            msg!("{} is not the creator of escrow {}", creator.key, escrow.id);
+            return Err(EscrowError::Unauthorized.into());
+        }
+
+        // Rent follows the current allocation, which ResizeEscrow may have changed
+        let rent = Rent::get()?;
+        let reserved = escrow
+            .held_lamports()
+            .checked_add(rent.minimum_balance(escrow_account.data_len()))
+            .ok_or(EscrowError::AmountOverflow)?;
+        let excess = escrow_account.lamports().saturating_sub(reserved);
+
+        if excess > 0 {
+            Self::transfer_lamports(escrow_account, creator, excess)?;
+        }
+
+        verbose_msg!("Escrow {} swept {} excess lamports", escrow.id, excess);
+        Ok(())
+    }
+
+    fn process_merge_escrows(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let creator = next_account_info(account_info_iter)?;
+        let escrow_account = next_account_info(account_info_iter)?;
+        let merged_account = next_account_info(account_info_iter)?;
+        let creator_stats_account = next_account_info(account_info_iter)?;
+
+        if !creator.is_signer {
+            msg!("Missing signature from {}", creator.key);
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        if escrow_account.owner != program_id
+            || merged_account.owner != program_id
+            || escrow_account.key == merged_account.key
+        {
+            msg!("Merge needs two distinct escrow accounts of this program");
+            return Err(EscrowError::InvalidEscrowAccount.into());
+        }
+
+        let mut escrow = {
+            let data = escrow_account.data.borrow();
+            let mut data_slice: &[u8] = &data;
+            Escrow::deserialize(&mut data_slice)?
+        };
+        let merged = {
+            let data = merged_account.data.borrow();
+            let mut data_slice: &[u8] = &data;
+            Escrow::deserialize(&mut data_slice)?
+        };
+
+        if escrow.creator != *creator.key {
+            msg!("{} is not the creator of escrow {}", creator.key, escrow.id);
+            return Err(EscrowError::Unauthorized.into());
+        }
+
+        for e in [&escrow, &merged] {
+            if e.is_completed {
+                msg!("Escrow {} is already completed", e.id);
+                return Err(EscrowError::EscrowCompleted.into());
+            }
+            if e.is_declined {
+                msg!("Escrow {} was declined", e.id);
+                return Err(EscrowError::EscrowDeclined.into());
+            }
+            if e.frozen {
+                msg!("Escrow {} is frozen", e.id);
+                return Err(EscrowError::EscrowFrozen.into());
+            }
+            if e.wrapped_sol {
+                msg!("Escrow {} holds wrapped SOL", e.id);
+                return Err(EscrowError::UnsupportedForWrappedSol.into());
+            }
+            if e.token_mint.is_some() {
+                msg!("Escrow {} holds SPL tokens", e.id);
+                return Err(EscrowError::UnsupportedForTokenEscrow.into());
+            }
+            // The combined escrow would owe the USD target once, not twice
+            if e.target_usd.is_some() {
+                msg!("Escrow {} is pegged to USD", e.id);
+                return Err(EscrowError::IncompatibleEscrows.into());
+            }
+            // Wiping approvals would let the creator cancel an approved escrow
+            if !e.approvals.is_empty() {
+                msg!("Escrow {} already has {} approvals", e.id, e.approvals.len());
+                return Err(EscrowError::AlreadyApproved.into());
+            }
+        }
+
+        if !escrow.has_same_terms(&merged) {
+            msg!("Escrows {} and {} have different terms", escrow.id, merged.id);
+            return Err(EscrowError::IncompatibleEscrows.into());
+        }
+
+        escrow.amount = escrow
+            .amount
+            .checked_add(merged.amount)
+            .ok_or(EscrowError::AmountOverflow)?;
+        // The combined escrow starts with no cancel consents either
+        escrow.cancel_approvals.clear();
+        escrow.serialize(&mut &mut escrow_account.data.borrow_mut()[..])?;
+
+        // Close the merged escrow: its amount joins the kept escrow, rent goes to the creator
+        Self::transfer_lamports(merged_account, escrow_account, merged.amount)?;
+        Self::transfer_lamports(merged_account, creator, merged_account.lamports())?;
+        merged_account.realloc(0, false)?;
+
+        Self::release_creator_slot(program_id, &merged.creator, creator_stats_account)?;
+
+        verbose_msg!(
+            "Escrow {} merged into escrow {}, now {} lamports",
+            merged.id,
+            escrow.id,
+            escrow.amount
+        );
+        Ok(())
+    }
+
+    fn process_attach_proof(accounts: &[AccountInfo], proof_hash: [u8; 32]) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let beneficiary = next_account_info(account_info_iter)?;
+        let escrow_account = next_account_info(account_info_iter)?;
+
+        if !beneficiary.is_signer {
+            msg!("Missing signature from {}", beneficiary.key);
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        let mut data = escrow_account.data.borrow_mut();
+        let mut data_slice: &[u8] = &data;
+        let mut escrow = Escrow::deserialize(&mut data_slice)?;
+
+        if escrow.beneficiary != *beneficiary.key {
+            msg!("{} is not the beneficiary of escrow {}", beneficiary.key, escrow.id);
+            return Err(EscrowError::Unauthorized.into());
+        }
+
+        if escrow.is_completed {
+            msg!("Escrow {} is already completed", escrow.id);
+            return Err(EscrowError::EscrowCompleted.into());
+        }
+
+        if escrow.is_declined {
+            msg!("Escrow {} was declined", escrow.id);
+            return Err(EscrowError::EscrowDeclined.into());
+        }
+
+        // Proofs are bounded so the escrow always fits in MAX_SIZE
+        if escrow.proofs.len() >= Escrow::MAX_PROOFS {
+            msg!("Escrow {} already holds {} proofs", escrow.id, escrow.proofs.len());
+            return Err(EscrowError::ProofLimitExceeded.into());
+        }
+
+        escrow.proofs.push(proof_hash);
+        escrow.serialize(&mut &mut data[..])?;
+
+        let event = ProofAttachedEvent {
+            escrow_id: escrow.id,
+            proof_hash,
+            proofs: escrow.proofs.len() as u8,
+        };
+        sol_log_data(&[&event.try_to_vec()?]);
+
+        verbose_msg!(
+            "Escrow {} proof attached ({}/{})",
+            escrow.id,
+            escrow.proofs.len(),
+            Escrow::MAX_PROOFS
+        );
+        Ok(())
+    }
+
+    fn process_set_frozen(accounts: &[AccountInfo], frozen: bool) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let arbiter = next_account_info(account_info_iter)?;
+        let escrow_account = next_account_info(account_info_iter)?;
+
+        if !arbiter.is_signer {
+            msg!("Missing signature from {}", arbiter.key);
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        let mut data = escrow_account.data.borrow_mut();
+        let mut data_slice: &[u8] = &data;
+        let mut escrow = Escrow::deserialize(&mut data_slice)?;
+
+        if !escrow.is_arbiter(arbiter.key) {
+            msg!("{} is not the arbiter of escrow {}", arbiter.key, escrow.id);
+            return Err(EscrowError::Unauthorized.into());
+        }
+
+        if escrow.is_completed {
+            msg!("Escrow {} is already completed", escrow.id);
+            return Err(EscrowError::EscrowCompleted.into());
+        }
+
+        escrow.frozen = frozen;
+        escrow.serialize(&mut &mut data[..])?;
+
+        if frozen {
+            verbose_msg!("Escrow {} frozen by arbiter", escrow.id);
+        } else {
+            verbose_msg!("Escrow {} unfrozen by arbiter", escrow.id);
+        }
+        Ok(())
+    }
+
+    fn process_change_beneficiary(accounts: &[AccountInfo], new_beneficiary: Pubkey) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let creator = next_account_info(account_info_iter)?;
+        let escrow_account = next_account_info(account_info_iter)?;
+
+        if !creator.is_signer {
+            msg!("Missing signature from {}", creator.key);
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        let mut data = escrow_account.data.borrow_mut();
+        let mut data_slice: &[u8] = &data;
+        let mut escrow = Escrow::deserialize(&mut data_slice)?;
+
+        if escrow.creator != *creator.key {
+            msg!("{} is not the creator of escrow {}", creator.key, escrow.id);
+            return Err(EscrowError::Unauthorized.into());
+        }
+
+        if escrow.is_completed {
+            msg!("Escrow {} is already completed", escrow.id);
+            return Err(EscrowError::EscrowCompleted.into());
+        }
+
+        if escrow.is_declined {
+            msg!("Escrow {} was declined", escrow.id);
+            return Err(EscrowError::EscrowDeclined.into());
+        }
+
+        if !escrow.approvals.is_empty() {
+            msg!("Escrow {} already has {} approvals", escrow.id, escrow.approvals.len());
+            return Err(EscrowError::AlreadyApproved.into());
+        }
+
+        if new_beneficiary == Pubkey::default()
+            || new_beneficiary == escrow.creator
+            || new_beneficiary == *escrow_account.key
+        {
+            msg!("Beneficiary {} is the default key, the creator or the escrow", new_beneficiary);
+            return Err(EscrowError::InvalidBeneficiary.into());
+        }
+
+        escrow.beneficiary = new_beneficiary;
+        if escrow.has_role_conflict() {
+            msg!("Escrow {} would give the arbiter another role", escrow.id);
+            return Err(EscrowError::RoleConflict.into());
+        }
+        // The new beneficiary has not accepted anything yet
+        escrow.beneficiary_acknowledged = false;
+        escrow.serialize(&mut &mut data[..])?;
+
+        verbose_msg!("Escrow {} beneficiary changed to {}", escrow.id, new_beneficiary);
+        Ok(())
+    }
+
+    fn process_replace_approver(
+        accounts: &[AccountInfo],
+        slot: u8,
+        new_approver: Pubkey,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let creator = next_account_info(account_info_iter)?;
+        let arbiter = next_account_info(account_info_iter)?;
+        let escrow_account = next_account_info(account_info_iter)?;
+
+        if !creator.is_signer || !arbiter.is_signer {
+            msg!("Missing signature from {} or {}", creator.key, arbiter.key);
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        let mut data = escrow_account.data.borrow_mut();
+        let mut data_slice: &[u8] = &data;
+        let mut escrow = Escrow::deserialize(&mut data_slice)?;
+
+        if escrow.creator != *creator.key || !escrow.is_arbiter(arbiter.key) {
+            msg!("Signers are not the creator and arbiter of escrow {}", escrow.id);
+            return Err(EscrowError::Unauthorized.into());
+        }
+
+        if escrow.is_completed {
+            msg!("Escrow {} is already completed", escrow.id);
+            return Err(EscrowError::EscrowCompleted.into());
+        }
+
+        if escrow.is_declined {
+            msg!("Escrow {} was declined", escrow.id);
+            return Err(EscrowError::EscrowDeclined.into());
+        }
+
+        let old_approver = escrow.approver_in_slot(slot).ok_or(EscrowError::InvalidApprover)?;
+
+        // The new approver must not already hold any slot or act as a delegate,
+        // so the set stays unique
+        if new_approver == Pubkey::default()
+            || escrow.is_approver(&new_approver)
+            || escrow.is_delegate(&new_approver)
+        {
+            msg!("Approver {} is the default key, an approver or a delegate", new_approver);
+            return Err(EscrowError::InvalidApprover.into());
+        }
+
+        match slot {
+            0 => escrow.approver1 = new_approver,
+            1 => escrow.approver2 = new_approver,
+            _ => escrow.approver3 = Some(new_approver),
+        }
+        if escrow.has_role_conflict() {
+            msg!("Escrow {} would give the arbiter another role", escrow.id);
+            return Err(EscrowError::RoleConflict.into());
+        }
+
+        // Only drop the old approver's approval and consent if no other slot still holds it
+        if !escrow.is_approver(&old_approver) {
+            escrow.approvals.retain(|a| a.approver != old_approver);
+            escrow.approval_comments.retain(|c| c.approver != old_approver);
+            escrow.cancel_approvals.retain(|a| *a != old_approver);
+            escrow.delegations.retain(|d| d.approver != old_approver);
+            escrow.approval_slots.retain(|s| s.approver != old_approver);
+        }
+
+        // A pending release that lost its quorum waits for a fresh one
+        if !escrow.can_be_released(Clock::get()?.unix_timestamp) {
+            escrow.releasable_at = 0;
+        }
+
+        escrow.serialize(&mut &mut data[..])?;
+
+        verbose_msg!(
+            "Escrow {} approver slot {} replaced: {} -> {}",
+            escrow.id,
+            slot,
+            old_approver,
+            new_approver
+        );
+        Ok(())
+    }
+
+    fn process_resize_escrow(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        headroom: u32,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let creator = next_account_info(account_info_iter)?;
+        let escrow_account = next_account_info(account_info_iter)?;
+        let system_program = next_account_info(account_info_iter)?;
+
+        if !creator.is_signer {
+            msg!("Missing signature from {}", creator.key);
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        if !system_program::check_id(system_program.key) {
+            msg!("Expected the system program, got {}", system_program.key);
+            return Err(ProgramError::IncorrectProgramId);
+        }
+
+        if escrow_account.owner != program_id {
+            msg!("Account {} is not owned by this program", escrow_account.key);
+            return Err(EscrowError::InvalidEscrowAccount.into());
+        }
+
+        // The legacy allocation is what marks a pre-upgrade escrow, so none is
+        // resized into or out of it
+        let old_len = escrow_account.data_len();
+        if old_len == LegacyEscrow::SIZE {
+            msg!("Escrow account {} is in the pre-upgrade layout", escrow_account.key);
+            return Err(EscrowError::InvalidEscrowAccount.into());
+        }
+
+        let escrow = {
+            let data = escrow_account.data.borrow();
+            let mut data_slice: &[u8] = &data;
+            Escrow::deserialize(&mut data_slice)?
+        };
+
+        if escrow.creator != *creator.key {
+            msg!("{} is not the creator of escrow {}", creator.key, escrow.id);
+            return Err(EscrowError::Unauthorized.into());
+        }
+
+        // Sized from the serialized content, so a shrink can never truncate the escrow
+        let content_len = escrow.current_size();
+        let new_len = content_len
+            .checked_add(headroom as usize)
+            .ok_or(EscrowError::AmountOverflow)?;
+        if new_len == LegacyEscrow::SIZE {
+            msg!("{} bytes is reserved for pre-upgrade escrows", new_len);
+            return Err(ProgramError::InvalidInstructionData);
+        }
+
+        // The escrow also holds the escrowed lamports, so only the rent delta moves
+        let rent = Rent::get()?;
+        let old_rent = rent.minimum_balance(old_len);
+        let new_rent = rent.minimum_balance(new_len);
+        if let Some(top_up) = new_rent.checked_sub(old_rent).filter(|l| *l > 0) {
+            let transfer_ix = system_instruction::transfer(creator.key, escrow_account.key, top_up);
+            invoke(
+                &transfer_ix,
+                &[creator.clone(), escrow_account.clone(), system_program.clone()],
+            )?;
+        } else if let Some(refund) = old_rent.checked_sub(new_rent).filter(|l| *l > 0) {
+            Self::transfer_lamports(escrow_account, creator, refund)?;
+        }
+
+        escrow_account.realloc(new_len, false)?;
+
+        verbose_msg!("Escrow {} resized: {} -> {} bytes", escrow.id, old_len, new_len);
+        Ok(())
+    }
+
+    fn process_get_beneficiary_index(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let beneficiary_index_account = next_account_info(account_info_iter)?;
+
+        if beneficiary_index_account.owner != program_id {
+            msg!("Account {} is not owned by this program", beneficiary_index_account.key);
+            return Err(EscrowError::InvalidBeneficiaryIndexAccount.into());
+        }
+
+        let data = beneficiary_index_account.data.borrow();
+        let mut data_slice: &[u8] = &data;
+        let index = BeneficiaryIndex::deserialize(&mut data_slice)?;
+
+        msg!("Beneficiary {} has {} escrows", index.beneficiary, index.escrows.len());
+        for escrow in &index.escrows {
+            msg!("Escrow: {}", escrow);
+        }
+        Ok(())
+    }
+
+    fn process_preview_approval(accounts: &[AccountInfo], approver: Pubkey) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let escrow_account = next_account_info(account_info_iter)?;
+
+        let data = escrow_account.data.borrow();
+        let mut data_slice: &[u8] = &data;
+        let escrow = Escrow::deserialize(&mut data_slice)?;
+        let now = Clock::get()?.unix_timestamp;
+
+        if escrow.is_completed || escrow.is_declined || escrow.frozen {
+            msg!("Preview: escrow {} is not accepting approvals", escrow.id);
+        } else if !escrow.is_approver(&approver) {
+            msg!("Preview: {} is not an approver of escrow {}", approver, escrow.id);
+        } else if escrow.has_live_approval(&approver, now) {
+            msg!("Preview: {} already approved escrow {}, no change", approver, escrow.id);
+        } else {
+            msg!(
+                "Preview: approval by {} would bring escrow {} to {}/{} approval weight",
+                approver,
+                escrow.id,
+                escrow.approved_weight(now).saturating_add(escrow.approver_weight(&approver)),
+                escrow.required_weight()
+            );
+        }
+
+        if escrow.would_release_with(&approver, now) {
+            msg!("Preview: would release");
+        } else {
+            msg!("Preview: would not release");
+        }
+        Ok(())
+    }
+
+    fn process_can_approve(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        approver: Pubkey,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let escrow_account = next_account_info(account_info_iter)?;
+
+        if escrow_account.owner != program_id {
+            msg!("Account {} is not owned by this program", escrow_account.key);
+            return Err(EscrowError::InvalidEscrowAccount.into());
+        }
+
+        let data = escrow_account.data.borrow();
+        let mut data_slice: &[u8] = &data;
+        let escrow = Escrow::deserialize(&mut data_slice)?;
+        let can_approve = escrow.can_approve(&approver, Clock::get()?.unix_timestamp);
+
+        msg!("Escrow {}: {} can approve {}", escrow.id, approver, can_approve);
+        set_return_data(&can_approve.try_to_vec()?);
+        Ok(())
+    }
+
+    fn process_escrow_release_status(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let escrow_account = next_account_info(account_info_iter)?;
+
+        if escrow_account.owner != program_id {
+            msg!("Account {} is not owned by this program", escrow_account.key);
+            return Err(EscrowError::InvalidEscrowAccount.into());
+        }
+
+        let data = escrow_account.data.borrow();
+        let mut data_slice: &[u8] = &data;
+        let escrow = Escrow::deserialize(&mut data_slice)?;
+        let status = escrow.release_status(Clock::get()?.unix_timestamp);
+
+        msg!(
+            "Escrow {}: releasable now {}, {} approval weight remaining, min age blocks {}, delay blocks {}",
+            escrow.id,
+            status.releasable_now,
+            status.remaining_weight,
+            status.blocked_by_min_age,
+            status.blocked_by_delay
+        );
+        sol_log_data(&[&status.try_to_vec()?]);
+        Ok(())
+    }
+
+    fn process_list_approvers(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let escrow_account = next_account_info(account_info_iter)?;
+
+        if escrow_account.owner != program_id {
+            msg!("Account {} is not owned by this program", escrow_account.key);
+            return Err(EscrowError::InvalidEscrowAccount.into());
+        }
+
+        let data = escrow_account.data.borrow();
+        let mut data_slice: &[u8] = &data;
+        let escrow = Escrow::deserialize(&mut data_slice)?;
+        let matrix = escrow.approver_matrix(Clock::get()?.unix_timestamp);
+
+        for entry in &matrix.approvers {
+            msg!(
+                "Escrow {} approver {}: approved {}, weight {}, category {}",
+                escrow.id,
+                entry.approver,
+                entry.approved,
+                entry.weight,
+                entry.category
+            );
+        }
+        msg!(
+            "Escrow {} quorum: {}/{} approval weight",
+            escrow.id,
+            matrix.approved_weight,
+            matrix.required_weight
+        );
+        sol_log_data(&[&matrix.try_to_vec()?]);
+        Ok(())
+    }
+
+    fn process_summarize_escrows(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+        if accounts.len() > EscrowInstruction::MAX_SUMMARIES {
+            msg!(
+                "At most {} escrows per summary, got {}",
+                EscrowInstruction::MAX_SUMMARIES,
+                accounts.len()
+            );
+            return Err(ProgramError::InvalidInstructionData);
+        }
+
+        for escrow_account in accounts {
+            if escrow_account.owner != program_id {
+                msg!("Skipping {}: not owned by the program", escrow_account.key);
+                continue;
+            }
+
+            let data = escrow_account.data.borrow();
+            let mut data_slice: &[u8] = &data;
+            match Escrow::deserialize(&mut data_slice) {
+                Ok(escrow) => sol_log_data(&[&escrow.summary().try_to_vec()?]),
+                Err(_) => msg!("Skipping {}: not an escrow account", escrow_account.key),
+            }
+        }
+        Ok(())
+    }
+
+    fn process_classify_escrows(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+        if accounts.len() > EscrowInstruction::MAX_SUMMARIES {
+            msg!(
+                "At most {} escrows per classification, got {}",
+                EscrowInstruction::MAX_SUMMARIES,
+                accounts.len()
+            );
+            return Err(ProgramError::InvalidInstructionData);
+        }
+
+        let classes = accounts
+            .iter()
+            .map(|escrow_account| {
+                if escrow_account.owner != program_id {
+                    return EscrowClass::NotEscrow;
+                }
+                let data = escrow_account.data.borrow();
+                let mut data_slice: &[u8] = &data;
+                match Escrow::deserialize(&mut data_slice) {
+                    Ok(escrow) if escrow.is_active() => EscrowClass::Active,
+                    Ok(_) => EscrowClass::Terminal,
+                    Err(_) => EscrowClass::NotEscrow,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let active = classes.iter().filter(|c| **c == EscrowClass::Active).count();
+        msg!("Classified {} accounts, {} active escrows", classes.len(), active);
+        set_return_data(&classes.try_to_vec()?);
+        Ok(())
+    }
+
+    fn process_migrate_escrow(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let creator = next_account_info(account_info_iter)?;
+        let escrow_account = next_account_info(account_info_iter)?;
+        let system_program = next_account_info(account_info_iter)?;
+        let creator_stats_account = next_account_info(account_info_iter)?;
+
+        if !creator.is_signer {
+            msg!("Missing signature from {}", creator.key);
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        if !system_program::check_id(system_program.key) {
+            msg!("Expected the system program, got {}", system_program.key);
+            return Err(ProgramError::IncorrectProgramId);
+        }
+
+        if escrow_account.owner != program_id || escrow_account.data_len() != LegacyEscrow::SIZE {
+            msg!("Account {} is not a pre-upgrade escrow", escrow_account.key);
+            return Err(EscrowError::InvalidEscrowAccount.into());
+        }
+
+        let legacy = {
+            let data = escrow_account.data.borrow();
+            let mut data_slice: &[u8] = &data;
+            LegacyEscrow::deserialize(&mut data_slice)?
+        };
+
+        if legacy.creator != *creator.key {
+            msg!("{} is not the creator of escrow {}", creator.key, legacy.id);
+            return Err(EscrowError::Unauthorized.into());
+        }
+
+        // A settled escrow would be counted as active without ever being released
+        if legacy.is_completed {
+            msg!("Escrow {} is already completed", legacy.id);
+            return Err(EscrowError::EscrowCompleted.into());
+        }
+
+        // Pre-upgrade escrows all live at the global PDA
+        let (escrow_pda, bump) =
+            Pubkey::find_program_address(&[b"escrow", &legacy.id.to_le_bytes()], program_id);
+        if escrow_pda != *escrow_account.key {
+            msg!("Escrow account {} does not match PDA {}", escrow_account.key, escrow_pda);
+            return Err(EscrowError::InvalidEscrowAccount.into());
+        }
+
+        let now = Clock::get()?.unix_timestamp;
+        let mut escrow = legacy.into_escrow();
+        escrow.bump = bump;
+        // Quorum reached before the upgrade without paying out is released by FinalizeRelease
+        if escrow.can_be_released(now) {
+            escrow.releasable_at =
+                escrow.release_unlocks_at(now).ok_or(EscrowError::AmountOverflow)?;
+        }
+
+        // The escrowed lamports stay put, so only the rent delta moves
+        let new_len = Escrow::size_with_description(Escrow::MAX_DESCRIPTION_LEN, 0);
+        let rent = Rent::get()?;
+        let top_up = rent
+            .minimum_balance(new_len)
+            .saturating_sub(rent.minimum_balance(LegacyEscrow::SIZE));
+        let transfer_ix = system_instruction::transfer(creator.key, escrow_account.key, top_up);
+        invoke(&transfer_ix, &[creator.clone(), escrow_account.clone(), system_program.clone()])?;
+        escrow_account.realloc(new_len, true)?;
+        escrow.serialize(&mut &mut escrow_account.data.borrow_mut()[..])?;
+
+        Self::reserve_creator_slot(program_id, creator, creator_stats_account, system_program, 0)?;
+
+        verbose_msg!("Escrow {} migrated from the pre-upgrade layout", escrow.id);
+        Ok(())
+    }
+
+    fn process_sweep_expired(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let counter_account = next_account_info(account_info_iter)?;
+        let treasury = next_account_info(account_info_iter)?;
+        let escrows = &accounts[2..];
+
+        if escrows.len() % 4 != 0 || escrows.len() / 4 > EscrowInstruction::MAX_SWEEPS {
+            msg!(
+                "Expected up to {} groups of escrow accounts, got {} accounts",
+                EscrowInstruction::MAX_SWEEPS,
+                escrows.len()
+            );
+            return Err(ProgramError::InvalidInstructionData);
+        }
+
+        Self::check_counter_account(program_id, counter_account)?;
+        let counter = {
+            let data = counter_account.data.borrow();
+            let mut data_slice: &[u8] = &data;
+            EscrowCounter::deserialize(&mut data_slice)?
+        };
+        if counter.authority != *treasury.key {
+            msg!("Treasury {} is not the counter authority {}", treasury.key, counter.authority);
+            return Err(EscrowError::InvalidTreasury.into());
+        }
+
+        let now = Clock::get()?.unix_timestamp;
+        let mut swept = 0;
+        // Each escrow stands alone: a bad one is logged and skipped, never failing the batch
+        for group in escrows.chunks(4) {
+            let (escrow_account, creator) = (&group[0], &group[1]);
+            let (refund_account, creator_stats_account) = (&group[2], &group[3]);
+
+            if escrow_account.owner != program_id {
+                msg!("Skipping {}: not owned by the program", escrow_account.key);
+                continue;
+            }
+
+            let escrow = {
+                let data = escrow_account.data.borrow();
+                let mut data_slice: &[u8] = &data;
+                match Escrow::deserialize(&mut data_slice) {
+                    Ok(escrow) if escrow.is_initialized() => escrow,
+                    _ => {
+                        msg!("Skipping {}: not an escrow account", escrow_account.key);
+                        continue;
+                    }
+                }
+            };
+
+            if escrow.is_completed {
+                msg!("Skipping escrow {}: already completed", escrow.id);
+                continue;
+            }
+            if !escrow.is_expired(now) {
+                msg!("Skipping escrow {}: not expired", escrow.id);
+                continue;
+            }
+            if escrow.creator != *creator.key {
+                msg!("Skipping escrow {}: {} is not its creator", escrow.id, creator.key);
+                continue;
+            }
+            if *refund_account.key != escrow.refund_recipient() {
+                msg!(
+                    "Skipping escrow {}: {} is not its refund recipient",
+                    escrow.id,
+                    refund_account.key
+                );
+                continue;
+            }
+            if escrow.frozen {
+                msg!("Skipping escrow {}: frozen", escrow.id);
+                continue;
+            }
+            // Quorum already granted the funds to the beneficiary, only the delay remains
+            if escrow.releasable_at != 0 {
+                msg!("Skipping escrow {}: approved, pending release", escrow.id);
+                continue;
+            }
+            // Wrapped funds and tokens need accounts a sweep does not take
+            if escrow.wrapped_sol || escrow.token_mint.is_some() {
+                msg!("Skipping escrow {}: cancel it with its own accounts", escrow.id);
+                continue;
+            }
+            if let Err(err) = Self::sweep_escrow(
+                program_id,
+                &counter,
+                &escrow,
+                escrow_account,
+                creator,
+                refund_account,
+                creator_stats_account,
+                treasury,
+            ) {
+                msg!("Skipping escrow {}: {}", escrow.id, err);
+                continue;
+            }
+            swept += 1;
+        }
+
+        msg!("Swept {} of {} escrows", swept, escrows.len() / 4);
+        Ok(())
+    }
+
+    /// Refund and close one expired escrow for `SweepExpired`. The balance is checked
+    /// before anything moves, so an error leaves this escrow as it was
+    #[allow(clippy::too_many_arguments)]
+    fn sweep_escrow(
+        program_id: &Pubkey,
+        counter: &EscrowCounter,
+        escrow: &Escrow,
+        escrow_account: &AccountInfo,
+        creator: &AccountInfo,
+        refund_account: &AccountInfo,
+        creator_stats_account: &AccountInfo,
+        treasury: &AccountInfo,
+    ) -> ProgramResult {
+        // The refund recipient takes the escrowed lamports less the expiry fee, and
+        // the creator, who paid the rent, the rest of the balance
+        let remaining = escrow.remaining();
+        if escrow_account.lamports() < remaining {
+            msg!("Escrow {} holds less than its {} lamports", escrow.id, remaining);
+            return Err(EscrowError::InsufficientFunds.into());
+        }
+        let fee = counter.expiry_fee(remaining);
+        let refund = remaining - fee;
+        Self::log_settlement_snapshot(escrow)?;
+        Self::release_creator_slot(program_id, &escrow.creator, creator_stats_account)?;
+
+        Self::transfer_lamports(escrow_account, treasury, fee)?;
+        Self::transfer_lamports(escrow_account, refund_account, refund)?;
+        Self::transfer_lamports(escrow_account, creator, escrow_account.lamports())?;
+        escrow_account.data.borrow_mut().fill(0);
+
+        // One return data slot cannot hold a batch, so each receipt is logged
+        let receipt = CancellationReceipt {
+            escrow_id: escrow.id,
+            reason: CancelReason::Expiry,
+            refunded: refund,
+        };
+        sol_log_data(&[&receipt.try_to_vec()?]);
+
+        msg!(
+            "Escrow {} expired: {} lamports refunded, {} lamports fee, account closed",
+            escrow.id,
+            refund,
+            fee
+        );
+        Ok(())
+    }
+
+    fn process_request_release(accounts: &[AccountInfo]) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let creator = next_account_info(account_info_iter)?;
+        let escrow_account = next_account_info(account_info_iter)?;
+
+        if !creator.is_signer {
+            msg!("Missing signature from {}", creator.key);
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        let data = escrow_account.data.borrow();
+        let mut data_slice: &[u8] = &data;
+        let escrow = Escrow::deserialize(&mut data_slice)?;
+
+        if escrow.creator != *creator.key {
+            msg!("{} is not the creator of escrow {}", creator.key, escrow.id);
+            return Err(EscrowError::Unauthorized.into());
+        }
+
+        if escrow.is_completed {
+            msg!("Escrow {} is already completed", escrow.id);
+            return Err(EscrowError::EscrowCompleted.into());
+        }
+
+        if escrow.is_declined {
+            msg!("Escrow {} was declined", escrow.id);
+            return Err(EscrowError::EscrowDeclined.into());
+        }
+
+        let now = Clock::get()?.unix_timestamp;
+        let event = ReleaseRequestedEvent {
+            escrow_id: escrow.id,
+            approvals: escrow.approvals.len() as u8,
+            remaining_weight: escrow.required_weight().saturating_sub(escrow.approved_weight(now)),
+        };
+        sol_log_data(&[&event.try_to_vec()?]);
+
+        verbose_msg!("Escrow {} release requested by creator", escrow.id);
+        Ok(())
+    }
+
+    /// Record `escrow` in the beneficiary's index, creating or growing the account as needed
+    fn append_beneficiary_index<'a>(
+        program_id: &Pubkey,
+        payer: &AccountInfo<'a>,
+        beneficiary_index_account: &AccountInfo<'a>,
+        system_program: &AccountInfo<'a>,
+        beneficiary: &Pubkey,
+        escrow: &Pubkey,
+    ) -> ProgramResult {
+        let (index_pda, index_bump) =
+            Pubkey::find_program_address(&[b"beneficiary", beneficiary.as_ref()], program_id);
+        if index_pda != *beneficiary_index_account.key {
+            msg!(
+                "Beneficiary index account {} does not match PDA {}",
+                beneficiary_index_account.key,
+                index_pda
+            );
+            return Err(EscrowError::InvalidBeneficiaryIndexAccount.into());
+        }
+
+        let rent = Rent::get()?;
+
+        let mut index = if beneficiary_index_account.data_is_empty() {
+            let space = BeneficiaryIndex::size_for(1);
+            let create_account_ix = system_instruction::create_account(
+                payer.key,
+                beneficiary_index_account.key,
+                rent.minimum_balance(space),
+                space as u64,
+                program_id,
+            );
+            invoke_signed(
+                &create_account_ix,
+                &[payer.clone(), beneficiary_index_account.clone(), system_program.clone()],
+                &[&[b"beneficiary".as_ref(), beneficiary.as_ref(), &[index_bump]]],
+            )?;
+
+            BeneficiaryIndex {
+                beneficiary: *beneficiary,
+                escrows: Vec::new(),
+            }
+        } else {
+            let data = beneficiary_index_account.data.borrow();
+            let mut data_slice: &[u8] = &data;
+            BeneficiaryIndex::deserialize(&mut data_slice)?
+        };
+
+        index.escrows.push(*escrow);
+
+        // Grow the account, with the payer topping up rent for the extra space
+        let new_len = BeneficiaryIndex::size_for(index.escrows.len());
+        if new_len > beneficiary_index_account.data_len() {
+            let required = rent
+                .minimum_balance(new_len)
+                .saturating_sub(beneficiary_index_account.lamports());
+            if required > 0 {
+                let transfer_ix =
+                    system_instruction::transfer(payer.key, beneficiary_index_account.key, required);
+                invoke(
+                    &transfer_ix,
+                    &[payer.clone(), beneficiary_index_account.clone(), system_program.clone()],
+                )?;
+            }
+            beneficiary_index_account.realloc(new_len, false)?;
+        }
+
+        index.serialize(&mut &mut beneficiary_index_account.data.borrow_mut()[..])?;
+        Ok(())
+    }
+
+    /// Fund a WSOL account owned by the escrow PDA with `amount`
+    #[allow(clippy::too_many_arguments)]
+    fn wrap_sol<'a>(
+        program_id: &Pubkey,
+        creator: &AccountInfo<'a>,
+        escrow_account: &AccountInfo<'a>,
+        wsol_account: &AccountInfo<'a>,
+        native_mint: &AccountInfo<'a>,
+        token_program: &AccountInfo<'a>,
+        system_program: &AccountInfo<'a>,
+        amount: u64,
+    ) -> ProgramResult {
+        if !spl_token::check_id(token_program.key) {
+            msg!("Expected the token program, got {}", token_program.key);
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        Self::check_native_mint(native_mint)?;
+
+        let (wsol_pda, wsol_bump) =
+            Pubkey::find_program_address(&[b"wsol", escrow_account.key.as_ref()], program_id);
+        if wsol_pda != *wsol_account.key {
+            msg!("WSOL account {} does not match PDA {}", wsol_account.key, wsol_pda);
+            return Err(EscrowError::InvalidWrappedSolAccount.into());
+        }
+
+        let rent = Rent::get()?;
+        let space = spl_token::state::Account::LEN;
+
+        let create_account_ix = system_instruction::create_account(
+            creator.key,
+            wsol_account.key,
+            rent.minimum_balance(space),
+            space as u64,
+            &spl_token::id(),
+        );
+        invoke_signed(
+            &create_account_ix,
+            &[creator.clone(), wsol_account.clone(), system_program.clone()],
+            &[&[b"wsol".as_ref(), escrow_account.key.as_ref(), &[wsol_bump]]],
+        )?;
+
+        let init_ix = spl_token::instruction::initialize_account3(
+            token_program.key,
+            wsol_account.key,
+            native_mint.key,
+            escrow_account.key,
+        )?;
+        invoke(&init_ix, &[wsol_account.clone(), native_mint.clone(), token_program.clone()])?;
+
+        let transfer_ix = system_instruction::transfer(creator.key, wsol_account.key, amount);
+        invoke(
+            &transfer_ix,
+            &[creator.clone(), wsol_account.clone(), system_program.clone()],
+        )?;
+
+        let sync_ix = spl_token::instruction::sync_native(token_program.key, wsol_account.key)?;
+        invoke(&sync_ix, &[wsol_account.clone(), token_program.clone()])?;
+
+        verbose_msg!("Wrapped {} lamports into {}", amount, wsol_account.key);
+        Ok(())
+    }
+
+    /// `mint` must be the native mint, at its address and with its decimals
+    fn check_native_mint(mint: &AccountInfo) -> ProgramResult {
+        if !spl_token::native_mint::check_id(mint.key) {
+            msg!("Mint {} is not the native mint", mint.key);
+            return Err(EscrowError::MintMismatch.into());
+        }
+        let mint_state = spl_token::state::Mint::unpack(&mint.data.borrow())?;
+        if mint_state.decimals != spl_token::native_mint::DECIMALS {
+            msg!("Native mint has {} decimals", mint_state.decimals);
+            return Err(EscrowError::MintMismatch.into());
+        }
+        Ok(())
+    }
+
+    /// The token account released from must still hold the native mint
+    fn check_wsol_mint(wsol_account: &AccountInfo) -> ProgramResult {
+        let token_account = spl_token::state::Account::unpack(&wsol_account.data.borrow())?;
+        if !spl_token::native_mint::check_id(&token_account.mint) {
+            msg!("WSOL account {} holds mint {}", wsol_account.key, token_account.mint);
+            return Err(EscrowError::MintMismatch.into());
+        }
+        Ok(())
+    }
+
+    /// Close the escrow's WSOL account back into the escrow PDA as native lamports
+    fn unwrap_sol<'a>(
+        program_id: &Pubkey,
+        escrow: &mut Escrow,
+        escrow_account: &AccountInfo<'a>,
+        wsol_account: &AccountInfo<'a>,
+        token_program: &AccountInfo<'a>,
+    ) -> ProgramResult {
+        if !spl_token::check_id(token_program.key) {
+            msg!("Expected the token program, got {}", token_program.key);
+            return Err(ProgramError::IncorrectProgramId);
+        }
+
+        let (wsol_pda, _wsol_bump) =
+            Pubkey::find_program_address(&[b"wsol", escrow_account.key.as_ref()], program_id);
+        if wsol_pda != *wsol_account.key {
+            msg!("WSOL account {} does not match PDA {}", wsol_account.key, wsol_pda);
+            return Err(EscrowError::InvalidWrappedSolAccount.into());
+        }
+        Self::check_wsol_mint(wsol_account)?;
+
+        let close_ix = spl_token::instruction::close_account(
+            token_program.key,
+            wsol_account.key,
+            escrow_account.key,
+            escrow_account.key,
+            &[],
+        )?;
+        Self::invoke_signed_by_escrow(
+            program_id,
+            escrow,
+            &close_ix,
+            &[wsol_account.clone(), escrow_account.clone(), token_program.clone()],
+        )?;
+
+        escrow.wrapped_sol = false;
+        verbose_msg!("Unwrapped escrow {} funds", escrow.id);
+        Ok(())
+    }
+
+    /// The mint's decimals, once `mint` is checked to be `expected`
+    fn mint_decimals(mint: &AccountInfo, expected: &Pubkey) -> Result<u8, ProgramError> {
+        if mint.key != expected {
+            msg!("Mint {} is not the escrow mint {}", mint.key, expected);
+            return Err(EscrowError::MintMismatch.into());
+        }
+        if !spl_token::check_id(mint.owner) {
+            msg!("Mint {} is not owned by the token program", mint.key);
+            return Err(EscrowError::MintMismatch.into());
+        }
+        Ok(spl_token::state::Mint::unpack(&mint.data.borrow())?.decimals)
+    }
+
+    /// Move `amount` of `token_mint` from the creator's token account into a vault
+    /// owned by the escrow PDA, checked against the mint's decimals
+    #[allow(clippy::too_many_arguments)]
+    fn deposit_tokens<'a>(
+        program_id: &Pubkey,
+        creator: &AccountInfo<'a>,
+        source_account: &AccountInfo<'a>,
+        escrow_account: &AccountInfo<'a>,
+        vault_account: &AccountInfo<'a>,
+        mint: &AccountInfo<'a>,
+        token_program: &AccountInfo<'a>,
+        system_program: &AccountInfo<'a>,
+        token_mint: &Pubkey,
+        amount: u64,
+    ) -> ProgramResult {
+        if !spl_token::check_id(token_program.key) {
+            msg!("Expected the token program, got {}", token_program.key);
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let decimals = Self::mint_decimals(mint, token_mint)?;
+
+        let (vault_pda, vault_bump) =
+            Pubkey::find_program_address(&[b"vault", escrow_account.key.as_ref()], program_id);
+        if vault_pda != *vault_account.key {
+            msg!("Vault {} does not match PDA {}", vault_account.key, vault_pda);
+            return Err(EscrowError::InvalidTokenAccount.into());
+        }
+
+        let rent = Rent::get()?;
+        let space = spl_token::state::Account::LEN;
+
+        let create_account_ix = system_instruction::create_account(
+            creator.key,
+            vault_account.key,
+            rent.minimum_balance(space),
+            space as u64,
+            &spl_token::id(),
+        );
+        invoke_signed(
+            &create_account_ix,
+            &[creator.clone(), vault_account.clone(), system_program.clone()],
+            &[&[b"vault".as_ref(), escrow_account.key.as_ref(), &[vault_bump]]],
+        )?;
+
+        let init_ix = spl_token::instruction::initialize_account3(
+            token_program.key,
+            vault_account.key,
+            mint.key,
+            escrow_account.key,
+        )?;
+        invoke(&init_ix, &[vault_account.clone(), mint.clone(), token_program.clone()])?;
+
+        let transfer_ix = spl_token::instruction::transfer_checked(
+            token_program.key,
+            source_account.key,
+            mint.key,
+            vault_account.key,
+            creator.key,
+            &[],
+            amount,
+            decimals,
+        )?;
+        invoke(
+            &transfer_ix,
+            &[
+                source_account.clone(),
+                mint.clone(),
+                vault_account.clone(),
+                creator.clone(),
+                token_program.clone(),
+            ],
+        )?;
+
+        verbose_msg!("Deposited {} base units of {} into {}", amount, mint.key, vault_account.key);
+        Ok(())
+    }
+
+    /// The vault, mint, token program and payee token account a payout reads from
+    /// the trailing accounts, only for SPL token escrows
+    fn next_token_accounts<'a, 'b: 'a, I: Iterator<Item = &'a AccountInfo<'b>>>(
+        escrow: &Escrow,
+        account_info_iter: &mut I,
+    ) -> Result<Option<[&'a AccountInfo<'b>; 4]>, ProgramError> {
+        if escrow.token_mint.is_none() {
+            return Ok(None);
+        }
+        Ok(Some([
+            next_account_info(account_info_iter)?,
+            next_account_info(account_info_iter)?,
+            next_account_info(account_info_iter)?,
+            next_account_info(account_info_iter)?,
+        ]))
+    }
+
+    /// Pay `amount` of the escrowed funds to `recipient`: tokens into its token
+    /// account for SPL token escrows, lamports onto the account otherwise
+    fn pay_out<'a>(
+        program_id: &Pubkey,
+        escrow: &Escrow,
+        escrow_account: &AccountInfo<'a>,
+        recipient: &AccountInfo<'a>,
+        token_accounts: Option<[&AccountInfo<'a>; 4]>,
+        amount: u64,
+    ) -> ProgramResult {
+        match token_accounts {
+            Some(token_accounts) => Self::transfer_tokens(
+                program_id,
+                escrow,
+                escrow_account,
+                token_accounts,
+                recipient.key,
+                amount,
+            ),
+            None => Self::transfer_lamports(escrow_account, recipient, amount),
+        }
+    }
+
+    /// Pay `amount` from the escrow's vault to the payee token account, which must
+    /// hold the escrow mint for `recipient`, closing the vault into the escrow once empty
+    fn transfer_tokens<'a>(
+        program_id: &Pubkey,
+        escrow: &Escrow,
+        escrow_account: &AccountInfo<'a>,
+        token_accounts: [&AccountInfo<'a>; 4],
+        recipient: &Pubkey,
+        amount: u64,
+    ) -> ProgramResult {
+        let [vault_account, mint, token_program, destination] = token_accounts;
+        let token_mint = escrow.token_mint.ok_or(EscrowError::UnsupportedForTokenEscrow)?;
+        if !spl_token::check_id(token_program.key) {
+            msg!("Expected the token program, got {}", token_program.key);
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let decimals = Self::mint_decimals(mint, &token_mint)?;
+
+        let (vault_pda, _vault_bump) =
+            Pubkey::find_program_address(&[b"vault", escrow_account.key.as_ref()], program_id);
+        if vault_pda != *vault_account.key {
+            msg!("Vault {} does not match PDA {}", vault_account.key, vault_pda);
+            return Err(EscrowError::InvalidTokenAccount.into());
+        }
+
+        let payee = spl_token::state::Account::unpack(&destination.data.borrow())?;
+        if payee.mint != token_mint {
+            msg!("Token account {} holds mint {}", destination.key, payee.mint);
+            return Err(EscrowError::MintMismatch.into());
+        }
+        if payee.owner != *recipient {
+            msg!(
+                "Token account {} is owned by {}, not {}",
+                destination.key,
+                payee.owner,
+                recipient
+            );
+            return Err(EscrowError::InvalidTokenAccount.into());
+        }
+
+        let transfer_ix = spl_token::instruction::transfer_checked(
+            token_program.key,
+            vault_account.key,
+            mint.key,
+            destination.key,
+            escrow_account.key,
+            &[],
+            amount,
+            decimals,
+        )?;
+        Self::invoke_signed_by_escrow(
+            program_id,
+            escrow,
+            &transfer_ix,
+            &[
+                vault_account.clone(),
+                mint.clone(),
+                destination.clone(),
+                escrow_account.clone(),
+                token_program.clone(),
+            ],
+        )?;
+
+        let remaining = spl_token::state::Account::unpack(&vault_account.data.borrow())?.amount;
+        if remaining == 0 {
+            let close_ix = spl_token::instruction::close_account(
+                token_program.key,
+                vault_account.key,
+                escrow_account.key,
+                escrow_account.key,
+                &[],
+            )?;
+            Self::invoke_signed_by_escrow(
+                program_id,
+                escrow,
+                &close_ix,
+                &[vault_account.clone(), escrow_account.clone(), token_program.clone()],
+            )?;
+        }
+
+        verbose_msg!("Paid {} base units of {} to {}", amount, token_mint, destination.key);
+        Ok(())
+    }
+
+    /// Invoke `instruction` with the escrow PDA as signer
+    fn invoke_signed_by_escrow(
+        program_id: &Pubkey,
+        escrow: &Escrow,
+        instruction: &Instruction,
+        account_infos: &[AccountInfo],
+    ) -> ProgramResult {
+        let escrow_id_bytes = escrow.id.to_le_bytes();
+        let mut seeds: Vec<&[u8]> = if escrow.creator_scoped {
+            vec![b"escrow", escrow.creator.as_ref(), &escrow_id_bytes]
+        } else {
+            vec![b"escrow", &escrow_id_bytes]
+        };
+        let bump = [escrow.bump];
+        seeds.push(&bump);
+
+        // The stored bump skips the find_program_address search
+        Pubkey::create_program_address(&seeds, program_id)
+            .map_err(|_| EscrowError::InvalidEscrowAccount)?;
+
+        invoke_signed(instruction, account_infos, &[&seeds])
+    }
+
+    /// Log the escrow as it stands right before it is released or refunded
+    fn log_settlement_snapshot(escrow: &Escrow) -> ProgramResult {
+        sol_log_data(&[SETTLEMENT_SNAPSHOT_TAG, &escrow.try_to_vec()?]);
+        Ok(())
+    }
+
+    /// Set the return data telling clients why `escrow` ended in a refund
+    fn set_cancellation_receipt(escrow: &Escrow, reason: CancelReason, refunded: u64) -> ProgramResult {
+        let receipt = CancellationReceipt { escrow_id: escrow.id, reason, refunded };
+        set_return_data(&receipt.try_to_vec()?);
+        Ok(())
+    }
+
+    /// The escrow account must hold the lamports it escrows directly plus rent for its allocation
+    fn check_escrow_funded(escrow: &Escrow, escrow_account: &AccountInfo) -> ProgramResult {
+        let rent = Rent::get()?;
+        let required = escrow
+            .held_lamports()
+            .checked_add(rent.minimum_balance(escrow_account.data_len()))
+            .ok_or(EscrowError::AmountOverflow)?;
+        if escrow_account.lamports() < required {
+            msg!(
+                "Escrow {} holds {} lamports, needs {}",
+                escrow.id,
+                escrow_account.lamports(),
+                required
+            );
+            return Err(EscrowError::InsufficientFunds.into());
+        }
+        Ok(())
+    }
+
+    /// Read the escrow's recorded Pyth price account, refusing any other account and
+    /// any price that is not trading, fresh at `now` and tight enough to pay out by
+    fn load_price_feed(
+        escrow: &Escrow,
+        price_feed: &AccountInfo,
+        now: i64,
+    ) -> Result<PriceFeed, ProgramError> {
+        if escrow.price_feed != Some(*price_feed.key)
+            || *price_feed.owner != PriceFeed::ORACLE_PROGRAM_ID
+        {
+            msg!("Account {} is not the price feed of escrow {}", price_feed.key, escrow.id);
+            return Err(EscrowError::InvalidPriceFeed.into());
+        }
+        let price = PriceFeed::unpack(&price_feed.data.borrow())?;
+        price.check(now)?;
+        Ok(price)
+    }
+
+    /// Move lamports between accounts, failing with `InsufficientFunds` or `AmountOverflow`
+    fn transfer_lamports(from: &AccountInfo, to: &AccountInfo, amount: u64) -> ProgramResult {
+        // A duplicated account keeps its balance; computing both sides first would mint
+        if from.key == to.key {
+            return Ok(());
+        }
+        let from_lamports =
+            from.lamports().checked_sub(amount).ok_or(EscrowError::InsufficientFunds)?;
+        let to_lamports = to.lamports().checked_add(amount).ok_or(EscrowError::AmountOverflow)?;
+        **from.try_borrow_mut_lamports()? = from_lamports;
+        **to.try_borrow_mut_lamports()? = to_lamports;
+        Ok(())
+    }
+
+    /// Reject any account other than the counter PDA before its data is trusted
+    fn check_counter_account(program_id: &Pubkey, counter_account: &AccountInfo) -> ProgramResult {
+        let (counter_pda, _counter_bump) = Pubkey::find_program_address(&[b"counter"], program_id);
+        if counter_pda != *counter_account.key {
+            msg!("Counter account {} does not match PDA {}", counter_account.key, counter_pda);
+            return Err(EscrowError::InvalidCounterAccount.into());
+        }
+        // The right address but not yet created by Initialize
+        if counter_account.owner != program_id || counter_account.data_len() < EscrowCounter::SIZE {
+            msg!("Counter {} is not initialized", counter_account.key);
+            return Err(EscrowError::InvalidCounterAccount.into());
+        }
+        Ok(())
+    }
+
+    /// Reserve the next escrow id, returning the updated counter whose `count` is the id.
+    /// Invariant: the counter is read, incremented and written back (and its
+    /// borrow released) before any CPI runs, so the id returned is the committed
+    /// counter value and no two escrows can ever be derived from the same id.
+    fn reserve_escrow_id(counter_account: &AccountInfo) -> Result<EscrowCounter, ProgramError> {
+        let mut counter_data = counter_account.data.borrow_mut();
+        let mut counter_slice: &[u8] = &counter_data;
+        let mut counter = EscrowCounter::deserialize(&mut counter_slice)?;
+        if counter.max_escrow_id != 0 {
+            if counter.count > counter.max_escrow_id {
+                msg!(
+                    "Counter holds {}, above the ceiling {}",
+                    counter.count,
+                    counter.max_escrow_id
+                );
+                return Err(EscrowError::CounterCorrupt.into());
+            }
+            if counter.count == counter.max_escrow_id {
+                msg!("Counter reached the ceiling {}", counter.max_escrow_id);
+                return Err(EscrowError::CounterExhausted.into());
+            }
+        }
+        counter.count = counter.count.checked_add(1).ok_or(EscrowError::CounterExhausted)?;
+        counter.serialize(&mut &mut counter_data[..])?;
+        Ok(counter)
+    }
+
+    /// Track active escrows per creator and enforce the configured cap
+    fn reserve_creator_slot<'a>(
+        program_id: &Pubkey,
+        creator: &AccountInfo<'a>,
+        creator_stats_account: &AccountInfo<'a>,
+        system_program: &AccountInfo<'a>,
+        max_active_escrows: u32,
+    ) -> Result<CreatorStats, ProgramError> {
+        let mut creator_stats = Self::load_or_create_creator_stats(
+            program_id,
+            creator,
+            creator_stats_account,
+            system_program,
+        )?;
+        if max_active_escrows > 0 && creator_stats.active_count >= max_active_escrows {
+            msg!("{} already has {} active escrows", creator.key, creator_stats.active_count);
+            return Err(EscrowError::TooManyEscrows.into());
+        }
+        creator_stats.active_count = creator_stats
+            .active_count
+            .checked_add(1)
+            .ok_or(EscrowError::AmountOverflow)?;
+        creator_stats.serialize(&mut &mut creator_stats_account.data.borrow_mut()[..])?;
+        Ok(creator_stats)
+    }
+
+    /// Verify the escrow PDA for `seeds` and allocate `space` bytes for it, funded
+    /// with rent by `payer`. Returns the bump so it can be stored on the escrow
+    fn create_escrow_account<'a>(
+        program_id: &Pubkey,
+        payer: &AccountInfo<'a>,
+        escrow_account: &AccountInfo<'a>,
+        system_program: &AccountInfo<'a>,
+        space: usize,
+        seeds: &[&[u8]],
+    ) -> Result<u8, ProgramError> {
+        let (escrow_pda, escrow_bump) = Pubkey::find_program_address(seeds, program_id);
+        if escrow_pda != *escrow_account.key {
+            msg!("Escrow account {} does not match PDA {}", escrow_account.key, escrow_pda);
+            return Err(EscrowError::InvalidEscrowAccount.into());
+        }
+
+        let rent = Rent::get()?;
+        let rent_lamports = rent.minimum_balance(space);
+
+        let create_account_ix = system_instruction::create_account(
+            payer.key,
+            escrow_account.key,
+            rent_lamports,
+            space as u64,
+            program_id,
+        );
+
+        let bump = [escrow_bump];
+        let mut signer_seeds = seeds.to_vec();
+        signer_seeds.push(&bump);
+
+        invoke_signed(
+            &create_account_ix,
+            &[payer.clone(), escrow_account.clone(), system_program.clone()],
+            &[&signer_seeds],
+        )?;
+
+        if !rent.is_exempt(escrow_account.lamports(), escrow_account.data_len()) {
+            msg!("Account {} is not rent-exempt after creation", escrow_account.key);
+            return Err(EscrowError::NotRentExempt.into());
+        }
+        Ok(escrow_bump)
+    }
+
+    fn load_or_create_creator_stats<'a>(
+        program_id: &Pubkey,
+        creator: &AccountInfo<'a>,
+        creator_stats_account: &AccountInfo<'a>,
+        system_program: &AccountInfo<'a>,
+    ) -> Result<CreatorStats, ProgramError> {
+        let (stats_pda, stats_bump) =
+            Pubkey::find_program_address(&[b"creator", creator.key.as_ref()], program_id);
+        if stats_pda != *creator_stats_account.key {
+            msg!(
+                "Creator stats account {} does not match PDA {}",
+                creator_stats_account.key,
+                stats_pda
+            );
+            return Err(EscrowError::InvalidCreatorStatsAccount.into());
+        }
+
+        if !creator_stats_account.data_is_empty() {
+            let data = creator_stats_account.data.borrow();
+            let mut data_slice: &[u8] = &data;
+            return Ok(CreatorStats::deserialize(&mut data_slice)?);
+        }
+
+        let rent = Rent::get()?;
+        let space = CreatorStats::SIZE;
+        let rent_lamports = rent.minimum_balance(space);
+
+        let create_account_ix = system_instruction::create_account(
+            creator.key,
+            creator_stats_account.key,
+            rent_lamports,
+            space as u64,
+            program_id,
+        );
+
+        invoke_signed(
+            &create_account_ix,
+            &[creator.clone(), creator_stats_account.clone(), system_program.clone()],
+            &[&[b"creator".as_ref(), creator.key.as_ref(), &[stats_bump]]],
+        )?;
+
+        Ok(CreatorStats {
+            creator: *creator.key,
+            active_count: 0,
+            nonce: 0,
+        })
+    }
+
+    /// Decrement the creator's active escrow count once an escrow reaches a terminal state
+    fn release_creator_slot(
+        program_id: &Pubkey,
+        creator: &Pubkey,
+        creator_stats_account: &AccountInfo,
+    ) -> ProgramResult {
+        let (stats_pda, _stats_bump) =
+            Pubkey::find_program_address(&[b"creator", creator.as_ref()], program_id);
+        if stats_pda != *creator_stats_account.key {
+            msg!(
+                "Creator stats account {} does not match PDA {}",
+                creator_stats_account.key,
+                stats_pda
+            );
+            return Err(EscrowError::InvalidCreatorStatsAccount.into());
+        }
+
+        let mut data = creator_stats_account.data.borrow_mut();
+        let mut data_slice: &[u8] = &data;
+        let mut stats = CreatorStats::deserialize(&mut data_slice)?;
+        stats.active_count = stats.active_count.saturating_sub(1);
+        stats.serialize(&mut &mut data[..])?;
+        Ok(())
+    }
+
+    fn process_consent_cancel(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        extra_consenters: u8,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let consenter = next_account_info(account_info_iter)?;
+        let escrow_account = next_account_info(account_info_iter)?;
+        let refund_account = next_account_info(account_info_iter)?;
+        let creator_stats_account = next_account_info(account_info_iter)?;
+
+        let mut consenters = vec![consenter];
+        for _ in 0..extra_consenters {
+            consenters.push(next_account_info(account_info_iter)?);
+        }
+
+        // Every claimed consenter must have signed, not merely be listed
+        if consenters.iter().any(|c| !c.is_signer) {
+            msg!("Every consenter must sign");
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        let mut escrow = {
+            let data = escrow_account.data.borrow();
+            let mut data_slice: &[u8] = &data;
+            Escrow::deserialize(&mut data_slice)?
+        };
+
+        if escrow.is_completed {
+            msg!("Escrow {} is already completed", escrow.id);
+            return Err(EscrowError::EscrowCompleted.into());
+        }
+
+        if *refund_account.key != escrow.refund_recipient() {
+            msg!(
+                "Refund account {} does not match {}",
+                refund_account.key,
+                escrow.refund_recipient()
+            );
+            return Err(EscrowError::InvalidRefundAccount.into());
+        }
+
+        for consenter in consenters {
+            if !escrow.can_consent_cancel(consenter.key) {
+                msg!("{} may not consent to cancel escrow {}", consenter.key, escrow.id);
+                return Err(EscrowError::Unauthorized.into());
+            }
+
+            if escrow.cancel_approvals.contains(consenter.key) {
+                msg!("{} already consented to cancel escrow {}", consenter.key, escrow.id);
+                return Err(EscrowError::AlreadyApproved.into());
+            }
+
+            escrow.cancel_approvals.push(*consenter.key);
+
+            verbose_msg!("Escrow {} cancellation consented by {}", escrow.id, consenter.key);
+        }
+
+        if escrow.has_cancel_consensus() {
+            let penalty = escrow.cancel_penalty();
+            let beneficiary = if penalty > 0 {
+                let beneficiary = next_account_info(account_info_iter)?;
+                if escrow.beneficiary != *beneficiary.key {
+                    msg!(
+                        "Account {} is not the beneficiary of escrow {}",
+                        beneficiary.key,
+                        escrow.id
+                    );
+                    return Err(EscrowError::InvalidBeneficiary.into());
+                }
+                Some(beneficiary)
+            } else {
+                None
+            };
+
+            Self::log_settlement_snapshot(&escrow)?;
+            if escrow.wrapped_sol {
+                let wsol_account = next_account_info(account_info_iter)?;
+                let token_program = next_account_info(account_info_iter)?;
+                Self::unwrap_sol(program_id, &mut escrow, escrow_account, wsol_account, token_program)?;
+            }
+            // The beneficiary's token account follows the refund's, only with a penalty
+            let refund_tokens = Self::next_token_accounts(&escrow, account_info_iter)?;
+            let penalty_tokens = match (refund_tokens, beneficiary) {
+                (Some([vault_account, mint, token_program, _]), Some(_)) => Some([
+                    vault_account,
+                    mint,
+                    token_program,
+                    next_account_info(account_info_iter)?,
+                ]),
+                _ => None,
+            };
+
+            let clock = Clock::get()?;
+            escrow.is_completed = true;
+            escrow.completed_at = clock.unix_timestamp;
+            if beneficiary.is_some() {
+                escrow.record_release(penalty)?;
+            }
+
+            // Pay the beneficiary its penalty share and return the rest to the
+            // creator or the configured refund account
+            let refund =
+                escrow.amount.checked_sub(penalty).ok_or(EscrowError::InsufficientFunds)?;
+            Self::pay_out(
+                program_id,
+                &escrow,
+                escrow_account,
+                refund_account,
+                refund_tokens,
+                refund,
+            )?;
+            if let Some(beneficiary) = beneficiary {
+                Self::pay_out(
+                    program_id,
+                    &escrow,
+                    escrow_account,
+                    beneficiary,
+                    penalty_tokens,
+                    penalty,
+                )?;
+            }
+
+            Self::release_creator_slot(program_id, &escrow.creator, creator_stats_account)?;
+            Self::set_cancellation_receipt(&escrow, CancelReason::Consent, refund)?;
+
+            verbose_msg!(
+                "Escrow {} cancelled by consent, {} lamports refunded, {} lamports penalty",
+                escrow.id,
+                refund,
+                penalty
+            );
+        }
+
+        escrow.serialize(&mut &mut escrow_account.data.borrow_mut()[..])?;
+        Ok(())
+    }
+
+    fn process_estimate_size(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        description_len: u16,
+        num_approvers: u8,
+    ) -> ProgramResult {
+        if description_len as usize > Escrow::MAX_DESCRIPTION_LEN
+            || !(2..=Escrow::MAX_APPROVERS).contains(&(num_approvers as usize))
+        {
+            msg!("Unsupported escrow shape");
+            return Err(ProgramError::InvalidInstructionData);
+        }
+
+        // Creation sizes the account for the configured description limit
+        let max_description_len = match accounts.first() {
+            Some(counter_account) => {
+                Self::check_counter_account(program_id, counter_account)?;
+                let data = counter_account.data.borrow();
+                let mut data_slice: &[u8] = &data;
+                EscrowCounter::deserialize(&mut data_slice)?.max_description_len as usize
+            }
+            None => Escrow::MAX_DESCRIPTION_LEN,
+        };
+        let extra_approvers = (num_approvers as usize).saturating_sub(3);
+
+        let rent = Rent::get()?;
+        let size = Escrow::serialized_size(description_len as usize, num_approvers as usize);
+        let allocation = Escrow::size_with_description(max_description_len, extra_approvers);
+
+        msg!("Escrow size: {} bytes, rent: {} lamports", size, rent.minimum_balance(size));
+        msg!(
+            "Escrow allocation: {} bytes, rent: {} lamports",
+            allocation,
+            rent.minimum_balance(allocation)
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_program::program_stubs;
+    use std::sync::Mutex;
+
+    fn reserve_from(count: u64) -> Result<EscrowCounter, ProgramError> {
+        reserve_below(count, 0)
+    }
+
+    fn reserve_below(count: u64, max_escrow_id: u64) -> Result<EscrowCounter, ProgramError> {
+        let key = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let mut lamports = 0;
+        let mut data = EscrowCounter {
+            count,
+            authority: Pubkey::default(),
+            max_active_escrows: 0,
+            max_description_len: 0,
+            strict_description: false,
+            max_expiry_secs: 0,
+            expiry_fee_bps: 0,
+            max_escrow_id,
+        }
+        .try_to_vec()
+        .unwrap();
+        let counter_account =
+            AccountInfo::new(&key, false, true, &mut lamports, &mut data, &owner, false, 0);
+        Processor::reserve_escrow_id(&counter_account)
+    }
+
+    #[test]
+    fn process_rejects_short_account_list_before_decoding() {
+        // A CreateEscrow discriminator followed by a payload that would not decode
+        let mut data = vec![1u8];
+        data.extend_from_slice(&[0xff; 8]);
+        assert_eq!(
+            Processor::process(&Pubkey::new_unique(), &[], &data).unwrap_err(),
+            ProgramError::NotEnoughAccountKeys
+        );
+        assert_eq!(
+            EscrowInstruction::unpack(&data).unwrap_err(),
+            ProgramError::InvalidInstructionData
+        );
+    }
+
+    #[test]
+    fn approve_distinguishes_missing_from_extra_accounts() {
+        let program_id = Pubkey::new_unique();
+        let system_id = system_program::ID;
+        let approver = Pubkey::new_unique();
+        let escrow = Pubkey::new_unique();
+        let beneficiary = Pubkey::new_unique();
+        let stats = Pubkey::new_unique();
+        let extra = Pubkey::new_unique();
+        let recorded = Escrow::new(
+            1,
+            Pubkey::new_unique(),
+            beneficiary,
+            1_000,
+            approver,
+            Pubkey::new_unique(),
+            None,
+            String::new(),
+            &EscrowOptions::default(),
+            0,
+        );
+        let (mut l0, mut l1, mut l2, mut l3, mut l4, mut l5) = (1_000_000, 1_000_000, 0, 0, 0, 0);
+        let (mut d0, mut d2, mut d3, mut d4, mut d5) = (vec![], vec![], vec![], vec![], vec![]);
+        let mut d1 = recorded.try_to_vec().unwrap();
+        let accounts = [
+            AccountInfo::new(&approver, true, false, &mut l0, &mut d0, &system_id, false, 0),
+            AccountInfo::new(&escrow, false, true, &mut l1, &mut d1, &program_id, false, 0),
+            AccountInfo::new(&beneficiary, false, true, &mut l2, &mut d2, &system_id, false, 0),
+            AccountInfo::new(&system_id, false, false, &mut l3, &mut d3, &system_id, true, 0),
+            AccountInfo::new(&stats, false, true, &mut l4, &mut d4, &program_id, false, 0),
+            AccountInfo::new(&extra, false, false, &mut l5, &mut d5, &system_id, false, 0),
+        ];
+        let data = EscrowInstruction::ApproveRelease.try_to_vec().unwrap();
+
+        assert_eq!(
+            Processor::process(&program_id, &accounts[..3], &data).unwrap_err(),
+            ProgramError::NotEnoughAccountKeys
+        );
+        assert_eq!(
+            Processor::process(&program_id, &accounts, &data).unwrap_err(),
+            EscrowError::UnexpectedAccounts.into()
+        );
+    }
+
+    #[test]
+    fn cancel_rejects_zeroed_escrow_account() {
+        let program_id = Pubkey::new_unique();
+        let system_id = system_program::ID;
+        let creator = Pubkey::new_unique();
+        let escrow = Pubkey::new_unique();
+        let stats = Pubkey::new_unique();
+        let (mut l0, mut l1, mut l2, mut l3) = (1_000_000, 1_000_000, 0, 0);
+        let (mut d0, mut d2, mut d3) = (vec![], vec![], vec![]);
+        let mut d1 = vec![0u8; Escrow::MAX_SIZE];
+        let accounts = [
+            AccountInfo::new(&creator, true, true, &mut l0, &mut d0, &system_id, false, 0),
+            AccountInfo::new(&escrow, false, true, &mut l1, &mut d1, &program_id, false, 0),
+            AccountInfo::new(&system_id, false, false, &mut l2, &mut d2, &system_id, true, 0),
+            AccountInfo::new(&stats, false, true, &mut l3, &mut d3, &program_id, false, 0),
+        ];
+
+        assert_eq!(
+            Processor::process_cancel_escrow(&program_id, &accounts).unwrap_err(),
+            EscrowError::InvalidEscrowAccount.into()
+        );
+        assert_eq!(accounts[1].lamports(), 1_000_000);
+
+        // The same account under another owner is rejected before decoding
+        assert_eq!(
+            Processor::process_cancel_escrow(&Pubkey::new_unique(), &accounts).unwrap_err(),
+            EscrowError::InvalidEscrowAccount.into()
+        );
+    }
+
+    #[test]
+    fn approve_rejects_uninitialized_escrow_account() {
+        let program_id = Pubkey::new_unique();
+        let system_id = system_program::ID;
+        let approver = Pubkey::new_unique();
+        let escrow = Pubkey::new_unique();
+        let beneficiary = Pubkey::new_unique();
+        let stats = Pubkey::new_unique();
+        let (mut l0, mut l1, mut l2, mut l3, mut l4) = (1_000_000, 0, 0, 0, 0);
+        let (mut d0, mut d1, mut d2, mut d3, mut d4) = (vec![], vec![], vec![], vec![], vec![]);
+
+        // Never created: no data and still owned by the system program
+        let accounts = [
+            AccountInfo::new(&approver, true, false, &mut l0, &mut d0, &system_id, false, 0),
+            AccountInfo::new(&escrow, false, true, &mut l1, &mut d1, &system_id, false, 0),
+            AccountInfo::new(&beneficiary, false, true, &mut l2, &mut d2, &system_id, false, 0),
+            AccountInfo::new(&system_id, false, false, &mut l3, &mut d3, &system_id, true, 0),
+            AccountInfo::new(&stats, false, true, &mut l4, &mut d4, &program_id, false, 0),
+        ];
+        assert_eq!(
+            Processor::process_approve_release(&program_id, &accounts, None).unwrap_err(),
+            EscrowError::InvalidEscrowAccount.into()
+        );
+
+        // Allocated to the program but never written
+        let mut zeroed = vec![0u8; Escrow::MAX_SIZE];
+        let mut lamports = 1_000_000;
+        let mut accounts = accounts;
+        accounts[1] =
+            AccountInfo::new(&escrow, false, true, &mut lamports, &mut zeroed, &program_id, false, 0);
+        assert_eq!(
+            Processor::process_approve_release(&program_id, &accounts, None).unwrap_err(),
+            EscrowError::InvalidEscrowAccount.into()
+        );
+    }
+
+    #[test]
+    fn six_decimal_mint_is_checked_by_key() {
+        let token_program = spl_token::id();
+        let mint = spl_token::state::Mint {
+            decimals: 6,
+            is_initialized: true,
+            ..Default::default()
+        };
+        let mut mint_data = vec![0u8; spl_token::state::Mint::LEN];
+        spl_token::state::Mint::pack(mint, &mut mint_data).unwrap();
+        let mut mint_lamports = 0;
+
+        let mint_key = Pubkey::new_unique();
+        let mint_account = AccountInfo::new(
+            &mint_key,
+            false,
+            false,
+            &mut mint_lamports,
+            &mut mint_data,
+            &token_program,
+            false,
+            0,
+        );
+        assert_eq!(Processor::mint_decimals(&mint_account, &mint_key).unwrap(), 6);
+        assert_eq!(
+            Processor::mint_decimals(&mint_account, &Pubkey::new_unique()).unwrap_err(),
+            EscrowError::MintMismatch.into()
+        );
+        // Nor can it stand in for the native mint of a wrapped escrow
+        assert_eq!(
+            Processor::check_native_mint(&mint_account).unwrap_err(),
+            EscrowError::MintMismatch.into()
+        );
+
+        // A WSOL account holding any other mint is refused on release
+        let other_mint = Pubkey::new_unique();
+        let token_account = spl_token::state::Account {
+            mint: other_mint,
+            state: spl_token::state::AccountState::Initialized,
+            ..Default::default()
+        };
+        let mut token_data = vec![0u8; spl_token::state::Account::LEN];
+        spl_token::state::Account::pack(token_account, &mut token_data).unwrap();
+        let mut token_lamports = 0;
+        let wsol = Pubkey::new_unique();
+        let wsol_account = AccountInfo::new(
+            &wsol,
+            false,
+            true,
+            &mut token_lamports,
+            &mut token_data,
+            &token_program,
+            false,
+            0,
+        );
+        assert_eq!(
+            Processor::check_wsol_mint(&wsol_account).unwrap_err(),
+            EscrowError::MintMismatch.into()
+        );
+    }
+
+    #[test]
+    fn create_before_initialize_reports_invalid_counter() {
+        let program_id = Pubkey::new_unique();
+        let system_id = system_program::ID;
+        let (counter, _) = Pubkey::find_program_address(&[b"counter"], &program_id);
+        let creator = Pubkey::new_unique();
+        let escrow = Pubkey::new_unique();
+        let stats = Pubkey::new_unique();
+        let index = Pubkey::new_unique();
+        let (mut l0, mut l1, mut l2, mut l3, mut l4, mut l5) = (1_000_000, 0, 0, 0, 0, 0);
+        let (mut d0, mut d1, mut d2) = (vec![], vec![], vec![]);
+        let (mut d3, mut d4, mut d5) = (vec![], vec![], vec![]);
+
+        // The counter PDA exists only as an address, never initialized
+        let accounts = [
+            AccountInfo::new(&creator, true, true, &mut l0, &mut d0, &system_id, false, 0),
+            AccountInfo::new(&escrow, false, true, &mut l1, &mut d1, &system_id, false, 0),
+            AccountInfo::new(&counter, false, true, &mut l2, &mut d2, &system_id, false, 0),
+            AccountInfo::new(&system_id, false, false, &mut l3, &mut d3, &system_id, true, 0),
+            AccountInfo::new(&stats, false, true, &mut l4, &mut d4, &system_id, false, 0),
+            AccountInfo::new(&index, false, true, &mut l5, &mut d5, &system_id, false, 0),
+        ];
+        let instruction = EscrowInstruction::CreateEscrow {
+            amount: 1,
+            beneficiary: Pubkey::new_unique(),
+            approver1: Pubkey::new_unique(),
+            approver2: Pubkey::new_unique(),
+            approver3: None,
+            description: String::new(),
+            options: EscrowOptions::default(),
+        };
+
+        assert_eq!(
+            Processor::process(&program_id, &accounts, &instruction.try_to_vec().unwrap())
+                .unwrap_err(),
+            EscrowError::InvalidCounterAccount.into()
+        );
+    }
+
+    #[test]
+    fn process_rejects_oversized_instruction_data() {
+        // A valid AppendNote followed by padding past the limit
+        let mut data = EscrowInstruction::AppendNote { note: "x".to_string() }.try_to_vec().unwrap();
+        data.resize(EscrowInstruction::MAX_DATA_LEN + 1, 0);
+        assert_eq!(
+            Processor::process(&Pubkey::new_unique(), &[], &data).unwrap_err(),
+            ProgramError::InvalidInstructionData
+        );
+    }
+
+    #[test]
+    fn release_refuses_drained_escrow() {
+        let program_id = Pubkey::new_unique();
+        let escrow = Pubkey::new_unique();
+        let beneficiary = Pubkey::new_unique();
+        let stats = Pubkey::new_unique();
+        let (mut l0, mut l1, mut l2) = (1_000_000, 0, 0);
+        let (mut d0, mut d1, mut d2) = (vec![0u8; Escrow::MAX_SIZE], vec![], vec![]);
+        let escrow_account =
+            AccountInfo::new(&escrow, false, true, &mut l0, &mut d0, &program_id, false, 0);
+        let beneficiary_account =
+            AccountInfo::new(&beneficiary, false, true, &mut l1, &mut d1, &program_id, false, 0);
+        let stats_account =
+            AccountInfo::new(&stats, false, true, &mut l2, &mut d2, &program_id, false, 0);
+
+        let mut drained = Escrow::new(
+            1,
+            Pubkey::new_unique(),
+            beneficiary,
+            0,
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            None,
+            String::new(),
+            &EscrowOptions::default(),
+            0,
+        );
+        assert_eq!(
+            Processor::release_to_beneficiary(
+                &program_id,
+                &mut drained,
+                &escrow_account,
+                &beneficiary_account,
+                &stats_account,
+                &mut [].iter(),
+                0,
+            )
+            .unwrap_err(),
+            EscrowError::NothingToRelease.into()
+        );
+        assert!(!drained.is_completed);
+        assert_eq!(escrow_account.lamports(), 1_000_000);
+    }
+
+    #[test]
+    fn require_existing_beneficiary_checks_the_real_beneficiary() {
+        let program_id = Pubkey::new_unique();
+        let escrow = Pubkey::new_unique();
+        let beneficiary = Pubkey::new_unique();
+        let impostor = Pubkey::new_unique();
+        let stats = Pubkey::new_unique();
+        let (mut l0, mut l1, mut l2, mut l3) = (3_000_000, 0, 5_000_000, 0);
+        let (mut d0, mut d1, mut d2, mut d3) =
+            (vec![0u8; Escrow::MAX_SIZE], vec![], vec![], vec![]);
+        let escrow_account =
+            AccountInfo::new(&escrow, false, true, &mut l0, &mut d0, &program_id, false, 0);
+        let beneficiary_account =
+            AccountInfo::new(&beneficiary, false, true, &mut l1, &mut d1, &program_id, false, 0);
+        let impostor_account =
+            AccountInfo::new(&impostor, false, true, &mut l2, &mut d2, &program_id, false, 0);
+        let stats_account =
+            AccountInfo::new(&stats, false, true, &mut l3, &mut d3, &program_id, false, 0);
+
+        let mut pending = Escrow::new(
+            1,
+            Pubkey::new_unique(),
+            beneficiary,
+            1_000_000,
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            None,
+            String::new(),
+            &EscrowOptions {
+                require_existing_beneficiary: true,
+                ..EscrowOptions::default()
+            },
+            0,
+        );
+        // A funded stand-in no longer satisfies the existence check
+        for (payee, err) in [
+            (&impostor_account, EscrowError::InvalidBeneficiary),
+            (&beneficiary_account, EscrowError::BeneficiaryNotFound),
+        ] {
+            assert_eq!(
+                Processor::release_to_beneficiary(
+                    &program_id,
+                    &mut pending,
+                    &escrow_account,
+                    payee,
+                    &stats_account,
+                    &mut [].iter(),
+                    0,
+                )
+                .unwrap_err(),
+                err.into()
+            );
+        }
+        assert_eq!(escrow_account.lamports(), 3_000_000);
+    }
+
+    #[test]
+    fn finalize_needs_quorum_after_the_delay() {
+        let program_id = Pubkey::new_unique();
+        let beneficiary = Pubkey::new_unique();
+        let mut lamports = 0;
+        let mut data = vec![];
+        let beneficiary_account = AccountInfo::new(
+            &beneficiary,
+            false,
+            true,
+            &mut lamports,
+            &mut data,
+            &program_id,
+            false,
+            0,
+        );
+
+        let mut pending = Escrow::new(
+            1,
+            Pubkey::new_unique(),
+            beneficiary,
+            1_000_000,
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            None,
+            String::new(),
+            &EscrowOptions::default(),
+            0,
+        );
+        for approver in [pending.approver1, pending.approver2] {
+            pending.approvals.push(Approval { approver, approved_at: 10 });
+        }
+        pending.releasable_at = 100;
+        assert_eq!(Processor::check_finalizable(&pending, &beneficiary_account, 100), Ok(()));
+
+        // An approval dropped during the delay takes quorum with it
+        pending.approvals.pop();
+        assert_eq!(
+            Processor::check_finalizable(&pending, &beneficiary_account, 100),
+            Err(EscrowError::EscrowNotReleasable.into())
+        );
+    }
+
+    #[test]
+    fn force_finalize_drains_a_pre_upgrade_escrow() {
+        let program_id = Pubkey::new_unique();
+        let escrow = Pubkey::new_unique();
+        let creator = Pubkey::new_unique();
+        let beneficiary = Pubkey::new_unique();
+        let approvers = [Pubkey::new_unique(), Pubkey::new_unique()];
+
+        // Bytes as the baseline program wrote them: approvals as bare keys, nothing
+        // after `completed_at` and the baseline allocation
+        let baseline = |approvals: &[Pubkey]| {
+            let mut data = Vec::with_capacity(LegacyEscrow::SIZE);
+            data.extend_from_slice(&7u64.to_le_bytes());
+            data.extend_from_slice(creator.as_ref());
+            data.extend_from_slice(beneficiary.as_ref());
+            data.extend_from_slice(&1_000_000u64.to_le_bytes());
+            data.extend_from_slice(approvers[0].as_ref());
+            data.extend_from_slice(approvers[1].as_ref());
+            data.push(0);
+            data.extend_from_slice(&4u32.to_le_bytes());
+            data.extend_from_slice(b"rent");
+            data.extend_from_slice(&(approvals.len() as u32).to_le_bytes());
+            for approver in approvals {
+                data.extend_from_slice(approver.as_ref());
+            }
+            data.push(0);
+            data.extend_from_slice(&50i64.to_le_bytes());
+            data.extend_from_slice(&0i64.to_le_bytes());
+            data.resize(LegacyEscrow::SIZE, 0);
+            data
+        };
+
+        // Quorum under the baseline rule releases, anything short of it refunds
+        for (approvals, released) in [(&approvers[..], true), (&approvers[..1], false)] {
+            let (mut l0, mut l1, mut l2) = (0, 0, 1_500_000);
+            let (mut d0, mut d1, mut d2) = (vec![], vec![], baseline(approvals));
+            let creator_account =
+                AccountInfo::new(&creator, true, true, &mut l0, &mut d0, &program_id, false, 0);
+            let beneficiary_account =
+                AccountInfo::new(&beneficiary, true, true, &mut l1, &mut d1, &program_id, false, 0);
+            let escrow_account =
+                AccountInfo::new(&escrow, false, true, &mut l2, &mut d2, &program_id, false, 0);
+
+            let finalize = || {
+                Processor::force_finalize_legacy(
+                    &creator_account,
+                    &beneficiary_account,
+                    &escrow_account,
+                    &beneficiary_account,
+                    &creator_account,
+                    60,
+                )
+            };
+            finalize().unwrap();
+
+            let paid = if released { &beneficiary_account } else { &creator_account };
+            assert_eq!(paid.lamports(), 1_000_000);
+            assert_eq!(escrow_account.lamports(), 500_000);
+            let stored = LegacyEscrow::deserialize(&mut &escrow_account.data.borrow()[..]).unwrap();
+            assert!(stored.is_completed);
+            assert_eq!(stored.completed_at, 60);
+            assert_eq!(stored.approvals, approvals);
+            assert_eq!(finalize().unwrap_err(), EscrowError::EscrowCompleted.into());
+        }
+    }
+
+    #[test]
+    fn sweep_failure_leaves_the_escrow_untouched() {
+        let program_id = Pubkey::new_unique();
+        let escrow = Pubkey::new_unique();
+        let creator = Pubkey::new_unique();
+        let refund_to = Pubkey::new_unique();
+        let treasury = Pubkey::new_unique();
+        let (stats, _) = Pubkey::find_program_address(&[b"creator", creator.as_ref()], &program_id);
+        let stats_data = CreatorStats { creator, active_count: 1, nonce: 0 }.try_to_vec().unwrap();
+        let counter = EscrowCounter {
+            count: 1,
+            authority: treasury,
+            max_active_escrows: 0,
+            max_description_len: 0,
+            strict_description: false,
+            max_expiry_secs: 0,
+            expiry_fee_bps: 250,
+            max_escrow_id: 0,
+        };
+        let options = EscrowOptions { refund_to: Some(refund_to), ..EscrowOptions::default() };
+        let expired = Escrow::new(
+            1,
+            creator,
+            Pubkey::new_unique(),
+            1_000_000,
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            None,
+            String::new(),
+            &options,
+            0,
+        );
+
+        // A stats account off its PDA fails this escrow before any lamports move
+        let (mut l0, mut l1, mut l2, mut l3, mut l4) = (1_500_000, 0, 0, 0, 0);
+        let (mut d0, mut d1, mut d2) = (expired.try_to_vec().unwrap(), vec![], vec![]);
+        let (mut d3, mut d4) = (stats_data.clone(), vec![]);
+        let escrow_account =
+            AccountInfo::new(&escrow, false, true, &mut l0, &mut d0, &program_id, false, 0);
+        let creator_account =
+            AccountInfo::new(&creator, false, true, &mut l1, &mut d1, &program_id, false, 0);
+        let refund_account =
+            AccountInfo::new(&refund_to, false, true, &mut l2, &mut d2, &program_id, false, 0);
+        let treasury_account =
+            AccountInfo::new(&treasury, false, true, &mut l4, &mut d4, &program_id, false, 0);
+        let misplaced = Pubkey::new_unique();
+        let misplaced_stats =
+            AccountInfo::new(&misplaced, false, true, &mut l3, &mut d3, &program_id, false, 0);
+        let sweep = |stats_account: &AccountInfo| {
+            Processor::sweep_escrow(
+                &program_id,
+                &counter,
+                &expired,
+                &escrow_account,
+                &creator_account,
+                &refund_account,
+                stats_account,
+                &treasury_account,
+            )
+        };
+        assert_eq!(
+            sweep(&misplaced_stats).unwrap_err(),
+            EscrowError::InvalidCreatorStatsAccount.into()
+        );
+        assert_eq!(escrow_account.lamports(), 1_500_000);
+        assert_eq!(Escrow::try_from_slice(&escrow_account.data.borrow()).unwrap(), expired);
+
+        let (mut l5, mut d5) = (0, stats_data);
+        let stats_account =
+            AccountInfo::new(&stats, false, true, &mut l5, &mut d5, &program_id, false, 0);
+        sweep(&stats_account).unwrap();
+        // 2.5% to the treasury, the rest of the deposit to refund_to, the rent to the creator
+        assert_eq!(treasury_account.lamports(), 25_000);
+        assert_eq!(refund_account.lamports(), 975_000);
+        assert_eq!(creator_account.lamports(), 500_000);
+        assert_eq!(escrow_account.lamports(), 0);
+        assert!(escrow_account.data.borrow().iter().all(|b| *b == 0));
+    }
+
+    #[test]
+    fn release_requires_executor_signature() {
+        let program_id = Pubkey::new_unique();
+        let escrow = Pubkey::new_unique();
+        let beneficiary = Pubkey::new_unique();
+        let stats = Pubkey::new_unique();
+        let executor = Pubkey::new_unique();
+        let (mut l0, mut l1, mut l2, mut l3) = (3_000_000, 0, 0, 0);
+        let (mut d0, mut d1, mut d2) = (vec![0u8; Escrow::MAX_SIZE], vec![], vec![]);
+        let mut d3 = vec![];
+        let escrow_account =
+            AccountInfo::new(&escrow, false, true, &mut l0, &mut d0, &program_id, false, 0);
+        let beneficiary_account =
+            AccountInfo::new(&beneficiary, false, true, &mut l1, &mut d1, &program_id, false, 0);
+        let stats_account =
+            AccountInfo::new(&stats, false, true, &mut l2, &mut d2, &program_id, false, 0);
+        // Listed in the transaction, but without its signature
+        let executor_account =
+            AccountInfo::new(&executor, false, false, &mut l3, &mut d3, &program_id, false, 0);
+
+        let options = EscrowOptions { executor: Some(executor), ..EscrowOptions::default() };
+        let mut pending = Escrow::new(
+            1,
+            Pubkey::new_unique(),
+            beneficiary,
+            1_000_000,
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            None,
+            String::new(),
+            &options,
+            0,
+        );
+        assert_eq!(
+            Processor::release_to_beneficiary(
+                &program_id,
+                &mut pending,
+                &escrow_account,
+                &beneficiary_account,
+                &stats_account,
+                &mut [executor_account].iter(),
+                0,
+            )
+            .unwrap_err(),
+            ProgramError::MissingRequiredSignature
+        );
+        assert!(!pending.is_completed);
+        assert_eq!(escrow_account.lamports(), 3_000_000);
+    }
+
+    #[test]
+    fn usd_pegged_release_pays_the_price_value_and_refunds_the_rest() {
+        let program_id = Pubkey::new_unique();
+        let escrow = Pubkey::new_unique();
+        let beneficiary = Pubkey::new_unique();
+        let creator = Pubkey::new_unique();
+        let feed = Pubkey::new_unique();
+        let (stats, _) = Pubkey::find_program_address(&[b"creator", creator.as_ref()], &program_id);
+        let stats_data = CreatorStats { creator, active_count: 1, nonce: 0 }.try_to_vec().unwrap();
+
+        // A mocked Pyth price account quoting $150.00000000 per SOL at t=1_000
+        let mut feed_data = vec![0u8; PriceFeed::MIN_LEN];
+        feed_data[0..4].copy_from_slice(&PriceFeed::MAGIC.to_le_bytes());
+        feed_data[4..8].copy_from_slice(&PriceFeed::VERSION.to_le_bytes());
+        feed_data[8..12].copy_from_slice(&PriceFeed::ACCOUNT_TYPE_PRICE.to_le_bytes());
+        feed_data[20..24].copy_from_slice(&(-8i32).to_le_bytes());
+        feed_data[96..104].copy_from_slice(&1_000i64.to_le_bytes());
+        feed_data[208..216].copy_from_slice(&15_000_000_000i64.to_le_bytes());
+        feed_data[216..224].copy_from_slice(&1_000_000u64.to_le_bytes());
+        feed_data[224..228].copy_from_slice(&PriceFeed::STATUS_TRADING.to_le_bytes());
+
+        let options = EscrowOptions {
+            target_usd: Some(30_000),
+            price_feed: Some(feed),
+            ..EscrowOptions::default()
+        };
+        let new_pegged = || {
+            Escrow::new(
+                1,
+                creator,
+                beneficiary,
+                3_000_000_000,
+                Pubkey::new_unique(),
+                Pubkey::new_unique(),
+                None,
+                String::new(),
+                &options,
+                0,
+            )
+        };
+        let oracle = PriceFeed::ORACLE_PROGRAM_ID;
+        let impostor = Pubkey::new_unique();
+        let rejected: ProgramResult = Err(EscrowError::InvalidPriceFeed.into());
+        for (feed_owner, result) in [(&impostor, rejected), (&oracle, Ok(()))] {
+            let (mut l0, mut l1, mut l2, mut l3, mut l4) = (3_001_000_000, 1, 1, 1, 0);
+            let (mut d0, mut d1) = (vec![0u8; Escrow::MAX_SIZE], vec![]);
+            let (mut d2, mut d3, mut d4) = (stats_data.clone(), feed_data.clone(), vec![]);
+            let escrow_account =
+                AccountInfo::new(&escrow, false, true, &mut l0, &mut d0, &program_id, false, 0);
+            let beneficiary_account = AccountInfo::new(
+                &beneficiary,
+                false,
+                true,
+                &mut l1,
+                &mut d1,
+                &program_id,
+                false,
+                0,
+            );
+            let stats_account =
+                AccountInfo::new(&stats, false, true, &mut l2, &mut d2, &program_id, false, 0);
+            let feed_account =
+                AccountInfo::new(&feed, false, false, &mut l3, &mut d3, feed_owner, false, 0);
+            let creator_account =
+                AccountInfo::new(&creator, false, true, &mut l4, &mut d4, &program_id, false, 0);
+
+            let mut pegged = new_pegged();
+            assert_eq!(
+                Processor::release_to_beneficiary(
+                    &program_id,
+                    &mut pegged,
+                    &escrow_account,
+                    &beneficiary_account,
+                    &stats_account,
+                    &mut [feed_account, creator_account.clone()].iter(),
+                    1_030,
+                ),
+                result
+            );
+            if result.is_ok() {
+                // $300 at $150 is 2 SOL; the third SOL goes back to the creator
+                assert_eq!(beneficiary_account.lamports(), 2_000_000_001);
+                assert_eq!(creator_account.lamports(), 1_000_000_000);
+                assert_eq!(escrow_account.lamports(), 1_000_000);
+                assert_eq!(pegged.released, 2_000_000_000);
+            } else {
+                assert_eq!(escrow_account.lamports(), 3_001_000_000);
+                assert!(!pegged.is_completed);
+            }
+        }
+    }
+
+    #[test]
+    fn verbose_logs_only_change_the_log() {
+        // `npm run test:rust` runs this with and without `verbose-logs`: the outcome
+        // must match and only the success narration may come and go
+        static LOGS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+        struct CaptureLogs;
+        impl program_stubs::SyscallStubs for CaptureLogs {
+            fn sol_log(&self, message: &str) {
+                LOGS.lock().unwrap().push(message.to_string());
+            }
+        }
+        program_stubs::set_syscall_stubs(Box::new(CaptureLogs));
+
+        let program_id = Pubkey::new_unique();
+        let authority = Pubkey::new_unique();
+        let (counter, _) = Pubkey::find_program_address(&[b"counter"], &program_id);
+        let (mut l0, mut l1) = (0, 1_000_000);
+        let mut d0 = vec![];
+        let mut d1 = EscrowCounter {
+            count: 0,
+            authority,
+            max_active_escrows: 0,
+            max_description_len: 0,
+            strict_description: false,
+            max_expiry_secs: 0,
+            expiry_fee_bps: 0,
+            max_escrow_id: 0,
+        }
+        .try_to_vec()
+        .unwrap();
+        let accounts = [
+            AccountInfo::new(&authority, true, false, &mut l0, &mut d0, &program_id, false, 0),
+            AccountInfo::new(&counter, false, true, &mut l1, &mut d1, &program_id, false, 0),
+        ];
+        let data = EscrowInstruction::UpdateConfig {
+            max_active_escrows: None,
+            max_description_len: None,
+            strict_description: None,
+            max_expiry_secs: None,
+            expiry_fee_bps: None,
+            max_escrow_id: Some(4_242),
+        }
+        .try_to_vec()
+        .unwrap();
+
+        Processor::process(&program_id, &accounts, &data).unwrap();
+        let updated = EscrowCounter::deserialize(&mut &accounts[1].data.borrow()[..]).unwrap();
+        assert_eq!(updated.max_escrow_id, 4_242);
+
+        let narrated = LOGS.lock().unwrap().iter().any(|line| line == "Max escrow id set to 4242");
+        assert_eq!(narrated, cfg!(feature = "verbose-logs"));
+    }
+
+    #[test]
+    fn reclaim_rejects_zeroed_account_at_global_seeds() {
+        let program_id = Pubkey::new_unique();
+        let creator = Pubkey::new_unique();
+        let (escrow, _) =
+            Pubkey::find_program_address(&[b"escrow", &7u64.to_le_bytes()], &program_id);
+        let (mut l0, mut l1) = (1_000, 2_000_000);
+        let (mut d0, mut d1) = (vec![], vec![0; 64]);
+        let accounts = [
+            AccountInfo::new(&creator, true, true, &mut l0, &mut d0, &program_id, false, 0),
+            AccountInfo::new(&escrow, false, true, &mut l1, &mut d1, &program_id, false, 0),
+        ];
+
+        assert_eq!(
+            Processor::process_reclaim_failed_create(&program_id, &accounts, 7).unwrap_err(),
+            EscrowError::Unauthorized.into()
+        );
+        assert_eq!(accounts[1].lamports(), 2_000_000);
+    }
+
+    #[test]
+    fn transfer_lamports_rejects_overdraft() {
+        let owner = Pubkey::new_unique();
+        let (from, to) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (mut l0, mut l1) = (10, 0);
+        let (mut d0, mut d1) = (vec![], vec![]);
+        let from_account = AccountInfo::new(&from, false, true, &mut l0, &mut d0, &owner, false, 0);
+        let to_account = AccountInfo::new(&to, false, true, &mut l1, &mut d1, &owner, false, 0);
+
+        assert_eq!(
+            Processor::transfer_lamports(&from_account, &to_account, 11).unwrap_err(),
+            EscrowError::InsufficientFunds.into()
+        );
+        assert_eq!((from_account.lamports(), to_account.lamports()), (10, 0));
+        Processor::transfer_lamports(&from_account, &to_account, 10).unwrap();
+        assert_eq!((from_account.lamports(), to_account.lamports()), (0, 10));
+    }
+
+    #[test]
+    fn transfer_lamports_rejects_overflow() {
+        let owner = Pubkey::new_unique();
+        let (from, to) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (mut l0, mut l1) = (10, u64::MAX - 5);
+        let (mut d0, mut d1) = (vec![], vec![]);
+        let from_account = AccountInfo::new(&from, false, true, &mut l0, &mut d0, &owner, false, 0);
+        let to_account = AccountInfo::new(&to, false, true, &mut l1, &mut d1, &owner, false, 0);
+
+        assert_eq!(
+            Processor::transfer_lamports(&from_account, &to_account, 6).unwrap_err(),
+            EscrowError::AmountOverflow.into()
+        );
+        assert_eq!((from_account.lamports(), to_account.lamports()), (10, u64::MAX - 5));
+    }
+
+    #[test]
+    fn transfer_lamports_to_same_account_keeps_balance() {
+        let owner = Pubkey::new_unique();
+        let key = Pubkey::new_unique();
+        let mut lamports = 10;
+        let mut data = vec![];
+        let account =
+            AccountInfo::new(&key, false, true, &mut lamports, &mut data, &owner, false, 0);
+        // Duplicate account metas share one balance
+        let duplicate = account.clone();
+
+        Processor::transfer_lamports(&account, &duplicate, 10).unwrap();
+        assert_eq!(account.lamports(), 10);
+    }
+
+    #[test]
+    fn reclaim_reports_overflowing_creator_balance() {
+        let program_id = Pubkey::new_unique();
+        let creator = Pubkey::new_unique();
+        let (escrow, _) = Pubkey::find_program_address(
+            &[b"escrow", creator.as_ref(), &7u64.to_le_bytes()],
+            &program_id,
+        );
+        let (mut l0, mut l1) = (u64::MAX, 2_000_000);
+        let (mut d0, mut d1) = (vec![], vec![0; 64]);
+        let accounts = [
+            AccountInfo::new(&creator, true, true, &mut l0, &mut d0, &program_id, false, 0),
+            AccountInfo::new(&escrow, false, true, &mut l1, &mut d1, &program_id, false, 0),
+        ];
+
+        assert_eq!(
+            Processor::process_reclaim_failed_create(&program_id, &accounts, 7).unwrap_err(),
+            EscrowError::AmountOverflow.into()
+        );
+        assert_eq!(accounts[1].lamports(), 2_000_000);
+    }
+
+    #[test]
+    fn reserve_escrow_id_reports_counter_exhaustion() {
+        assert_eq!(reserve_from(u64::MAX - 1).unwrap().count, u64::MAX);
+        assert_eq!(reserve_from(u64::MAX).unwrap_err(), EscrowError::CounterExhausted.into());
+    }
+
+    #[test]
+    fn reserve_escrow_id_rejects_absurd_count() {
+        assert_eq!(
+            reserve_below(u64::MAX - 1, 1_000_000).unwrap_err(),
+            EscrowError::CounterCorrupt.into()
+        );
+    }
+}
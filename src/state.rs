@@ -1,6 +1,54 @@
+use std::fmt;
+
 use borsh::{BorshDeserialize, BorshSerialize};
 use solana_program::pubkey::Pubkey;
 
+use crate::{error::EscrowError, instruction::EscrowOptions};
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct Approval {
+    pub approver: Pubkey,
+    pub approved_at: i64,
+}
+
+impl Approval {
+    pub const SIZE: usize = 32 + 8;
+}
+
+/// Lets `delegate` approve on behalf of `approver`
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct Delegation {
+    pub approver: Pubkey,
+    pub delegate: Pubkey,
+}
+
+impl Delegation {
+    pub const SIZE: usize = 32 + 32;
+}
+
+/// Slot of an approver's latest approval, kept after the approval itself expires
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct ApprovalSlot {
+    pub approver: Pubkey,
+    pub last_approval_slot: u64,
+}
+
+impl ApprovalSlot {
+    pub const SIZE: usize = 32 + 8;
+}
+
+/// A note an approver left with their approval, kept only while the approval stands
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct ApprovalComment {
+    pub approver: Pubkey,
+    pub comment: String,
+}
+
+impl ApprovalComment {
+    pub const MAX_COMMENT_LEN: usize = 64;
+    pub const MAX_SIZE: usize = 32 + 4 + Self::MAX_COMMENT_LEN;
+}
+
 #[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
 pub struct Escrow {
     pub id: u64,
@@ -11,55 +59,1894 @@ pub struct Escrow {
     pub approver2: Pubkey,
     pub approver3: Option<Pubkey>,
     pub description: String,
-    pub approvals: Vec<Pubkey>,
+    pub approvals: Vec<Approval>,
     pub is_completed: bool,
     pub created_at: i64,
     pub completed_at: i64,
+    pub notes: Vec<String>,
+    pub is_declined: bool,
+    pub cancel_approvals: Vec<Pubkey>,
+    pub weights: [u16; 3],
+    pub refund_to: Option<Pubkey>,
+    pub arbiter: Option<Pubkey>,
+    pub frozen: bool,
+    /// PDA seeded by creator + per-creator nonce instead of the global id
+    pub creator_scoped: bool,
+    /// Funds are held in a WSOL token account owned by the escrow PDA
+    pub wrapped_sol: bool,
+    /// Cooling-off window after quorum before funds can be released
+    pub release_delay_secs: i64,
+    /// When a delayed release may be finalized, 0 until quorum is reached
+    pub releasable_at: i64,
+    /// Minimum age before release; approvals still accumulate while younger
+    pub min_age_secs: i64,
+    /// Canonical bump of the escrow PDA, stored to avoid re-deriving it
+    pub bump: u8,
+    /// At most one delegate per approver
+    pub delegations: Vec<Delegation>,
+    /// Seconds an approval counts toward quorum, 0 means approvals never expire
+    pub approval_ttl_secs: i64,
+    /// Category tag per approver slot
+    pub categories: [u8; 3],
+    /// Bitmask of categories that must each be represented among the approvals
+    pub required_categories: u8,
+    /// Release only to a beneficiary account that already holds lamports
+    pub require_existing_beneficiary: bool,
+    /// Delivery evidence hashes attached by the beneficiary, oldest first
+    pub proofs: Vec<[u8; 32]>,
+    /// At most one comment per standing approval
+    pub approval_comments: Vec<ApprovalComment>,
+    /// Basis points of the amount paid to the beneficiary when cancelled by consent
+    pub cancel_penalty_bps: u16,
+    /// Approvals are kept sorted by approver so equal approver sets serialize identically
+    pub sort_approvals: bool,
+    /// Approvals required for release as set by `ChangeThreshold`, 0 for the default rule
+    pub threshold: u8,
+    /// Seconds after creation during which the creator cannot cancel, unless declined
+    pub cancel_lock_secs: i64,
+    /// Release waits for `beneficiary_acknowledged`
+    pub require_ack: bool,
+    pub beneficiary_acknowledged: bool,
+    /// Approvers beyond the three slots, each with weight 1 and category 0.
+    /// Escrows from the three-slot layout get this empty from `MigrateEscrow`
+    pub extra_approvers: Vec<Pubkey>,
+    /// Must sign the transaction that pays out the escrow, without counting toward quorum
+    pub executor: Option<Pubkey>,
+    /// Unix time from which `SweepExpired` may refund the escrow, 0 never
+    pub expires_at: i64,
+    /// Parent agreement this escrow is bundled under, if any
+    pub group_id: Option<[u8; 32]>,
+    /// Lamports paid out to the beneficiary so far; `amount` stays the deposit
+    pub released: u64,
+    /// Each approver waits for every approver listed before it to approve first
+    pub approval_order: bool,
+    /// Slots each approver must wait between approvals, 0 for no limit
+    pub approval_rate_limit_slots: u64,
+    /// Latest approval slot per approver, recorded only while rate limited
+    pub approval_slots: Vec<ApprovalSlot>,
+    /// SPL mint `amount` is denominated in, held in the escrow's token vault;
+    /// `None` for lamports
+    pub token_mint: Option<Pubkey>,
+    /// US cents the release pays in lamports at the current price, with `amount`
+    /// as the cap; the surplus goes back to the creator
+    pub target_usd: Option<u64>,
+    /// Pyth price account `target_usd` is priced by
+    pub price_feed: Option<Pubkey>,
 }
 
 impl Escrow {
-    pub const MAX_SIZE: usize = 8 + 32 + 32 + 8 + 32 + 32 + 1 + 32 + 4 + 200 + 4 + (32 * 3) + 1 + 8 + 8;
+    /// Per-approver budgets in MAX_SIZE cover the three slots; extra approvers add their own
+    pub const MAX_APPROVALS: usize = 3;
+    /// Slot approvers plus extra approvers
+    pub const MAX_APPROVERS: usize = 10;
+    pub const MAX_EXTRA_APPROVERS: usize = Self::MAX_APPROVERS - 3;
+    /// Room for one extra approver: its key, approval, cancel consent, delegation,
+    /// comment and approval slot
+    pub const EXTRA_APPROVER_SIZE: usize = 32
+        + Approval::SIZE
+        + 32
+        + Delegation::SIZE
+        + ApprovalComment::MAX_SIZE
+        + ApprovalSlot::SIZE;
+    pub const MAX_NOTES: usize = 5;
+    pub const MAX_NOTES_LEN: usize = 200;
+    pub const MAX_PROOFS: usize = 4;
+    /// Absolute ceiling on descriptions; deployments may configure a lower limit
+    pub const MAX_DESCRIPTION_LEN: usize = 200;
+    pub const DEFAULT_WEIGHTS: [u16; 3] = [1, 1, 1];
+    pub const MAX_BPS: u16 = 10_000;
+
+    pub const MAX_SIZE: usize = 8 + 32 + 32 + 8 + 32 + 32 + 1 + 32 + 4 + Self::MAX_DESCRIPTION_LEN
+        + 4 + (Approval::SIZE * Self::MAX_APPROVALS)
+        + 1 + 8 + 8
+        + 4 + (4 * Self::MAX_NOTES) + Self::MAX_NOTES_LEN
+        + 1
+        + 4 + (32 * 4)
+        + (2 * 3)
+        + 1 + 32
+        + 1 + 32 + 1
+        + 1
+        + 1
+        + 8 + 8
+        + 8
+        + 1
+        + 4 + (Delegation::SIZE * Self::MAX_APPROVALS)
+        + 8
+        + 3 + 1
+        + 1
+        + 4 + (32 * Self::MAX_PROOFS)
+        + 4 + (ApprovalComment::MAX_SIZE * Self::MAX_APPROVALS)
+        + 2
+        + 1
+        + 1
+        + 8
+        + 1 + 1
+        + 4 + (Self::EXTRA_APPROVER_SIZE * Self::MAX_EXTRA_APPROVERS)
+        + 1 + 32
+        + 8
+        + 1 + 32
+        + 8
+        + 1
+        + 8
+        + 4 + (ApprovalSlot::SIZE * Self::MAX_APPROVALS)
+        + 1 + 32
+        + 1 + 8
+        + 1 + 32;
+
+    /// A fresh, active escrow with the terms from `options` and no approvals,
+    /// notes or consents yet. The PDA bump is left for the caller to fill in
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        id: u64,
+        creator: Pubkey,
+        beneficiary: Pubkey,
+        amount: u64,
+        approver1: Pubkey,
+        approver2: Pubkey,
+        approver3: Option<Pubkey>,
+        description: String,
+        options: &EscrowOptions,
+        created_at: i64,
+    ) -> Self {
+        Self {
+            id,
+            creator,
+            beneficiary,
+            amount,
+            approver1,
+            approver2,
+            approver3,
+            description,
+            approvals: Vec::new(),
+            is_completed: false,
+            created_at,
+            completed_at: 0,
+            notes: Vec::new(),
+            is_declined: false,
+            cancel_approvals: Vec::new(),
+            weights: options.weights.unwrap_or(Self::DEFAULT_WEIGHTS),
+            refund_to: options.refund_to,
+            arbiter: options.arbiter,
+            frozen: false,
+            creator_scoped: false,
+            wrapped_sol: options.wrap_sol,
+            release_delay_secs: options.release_delay_secs,
+            releasable_at: 0,
+            min_age_secs: options.min_age_secs,
+            bump: 0,
+            delegations: Vec::new(),
+            approval_ttl_secs: options.approval_ttl_secs,
+            categories: options.categories,
+            required_categories: options.required_categories,
+            require_existing_beneficiary: options.require_existing_beneficiary,
+            proofs: Vec::new(),
+            approval_comments: Vec::new(),
+            cancel_penalty_bps: options.cancel_penalty_bps,
+            sort_approvals: options.sort_approvals,
+            threshold: options.threshold,
+            cancel_lock_secs: options.cancel_lock_secs,
+            require_ack: options.require_ack,
+            beneficiary_acknowledged: false,
+            extra_approvers: options.extra_approvers.clone(),
+            executor: options.executor,
+            expires_at: if options.expiry_secs > 0 {
+                created_at.saturating_add(options.expiry_secs)
+            } else {
+                0
+            },
+            group_id: options.group_id,
+            released: 0,
+            approval_order: options.approval_order,
+            approval_rate_limit_slots: options.approval_rate_limit_slots,
+            approval_slots: Vec::new(),
+            token_mint: options.token_mint,
+            target_usd: options.target_usd,
+            price_feed: options.price_feed,
+        }
+    }
+
+    /// Allocation for an escrow whose description is capped at `max_description_len`
+    /// and which has `extra_approvers` approvers beyond the three slots
+    pub fn size_with_description(max_description_len: usize, extra_approvers: usize) -> usize {
+        Self::MAX_SIZE - Self::MAX_DESCRIPTION_LEN + max_description_len
+            - Self::EXTRA_APPROVER_SIZE * (Self::MAX_EXTRA_APPROVERS - extra_approvers)
+    }
+
+    /// Exact Borsh size of a freshly created escrow (no approvals, notes or consents)
+    /// with none of the optional terms set. Approvers past the third are extra approvers
+    pub fn serialized_size(description_len: usize, num_approvers: usize) -> usize {
+        let approver3_len = if num_approvers > 2 { 32 } else { 0 };
+        let extra_approvers_len = 32 * num_approvers.saturating_sub(3);
+        8 + 32 + 32 + 8 + 32 + 32
+            + 1 + approver3_len
+            + 4 + description_len
+            + 4
+            + 1 + 8 + 8
+            + 4
+            + 1
+            + 4
+            + (2 * 3)
+            + 1
+            + 1 + 1
+            + 1
+            + 1
+            + 8 + 8
+            + 8
+            + 1
+            + 4
+            + 8
+            + 3 + 1
+            + 1
+            + 4
+            + 4
+            + 2
+            + 1
+            + 1
+            + 8
+            + 1 + 1
+            + 4
+            + 1
+            + 8
+            + 1
+            + 8
+            + 1
+            + 8
+            + 4
+            + 1
+            + 1
+            + 1
+            + extra_approvers_len
+    }
+
+    /// Exact Borsh length of this escrow, counted without serializing it
+    pub fn current_size(&self) -> usize {
+        let num_approvers = if self.approver3.is_some() { 3 } else { 2 };
+        Self::serialized_size(self.description.len(), num_approvers)
+            + Approval::SIZE * self.approvals.len()
+            + self.notes.iter().map(|note| 4 + note.len()).sum::<usize>()
+            + 32 * self.cancel_approvals.len()
+            + 32 * usize::from(self.refund_to.is_some())
+            + 32 * usize::from(self.arbiter.is_some())
+            + Delegation::SIZE * self.delegations.len()
+            + 32 * self.proofs.len()
+            + self
+                .approval_comments
+                .iter()
+                .map(|c| 32 + 4 + c.comment.len())
+                .sum::<usize>()
+            + 32 * self.extra_approvers.len()
+            + 32 * usize::from(self.executor.is_some())
+            + 32 * usize::from(self.group_id.is_some())
+            + ApprovalSlot::SIZE * self.approval_slots.len()
+            + 32 * usize::from(self.token_mint.is_some())
+            + 8 * usize::from(self.target_usd.is_some())
+            + 32 * usize::from(self.price_feed.is_some())
+    }
+
+    /// Offset of the `approvals` length prefix in the serialized escrow
+    fn approvals_offset(&self) -> usize {
+        8 + 32 + 32 + 8 + 32 + 32
+            + 1 + 32 * usize::from(self.approver3.is_some())
+            + 4 + self.description.len()
+    }
+
+    /// Rewrite only the `approvals` vector in `data`, which must hold this escrow
+    /// as last serialized with `prior_len` approvals and every other field unchanged.
+    /// The fields after it are shifted rather than re-encoded, which is cheaper
+    /// than a full serialize of the escrow
+    pub fn write_approvals_in_place(
+        &self,
+        data: &mut [u8],
+        prior_len: usize,
+    ) -> Result<(), EscrowError> {
+        let start = self.approvals_offset();
+        let old_end = start + 4 + Approval::SIZE * prior_len;
+        let new_end = start + 4 + Approval::SIZE * self.approvals.len();
+        let tail_len = self.current_size() - new_end;
+        if new_end.max(old_end) + tail_len > data.len() {
+            return Err(EscrowError::InvalidEscrowAccount);
+        }
+
+        data.copy_within(old_end..old_end + tail_len, new_end);
+        data[start..start + 4].copy_from_slice(&(self.approvals.len() as u32).to_le_bytes());
+        for (i, approval) in self.approvals.iter().enumerate() {
+            let at = start + 4 + Approval::SIZE * i;
+            data[at..at + 32].copy_from_slice(approval.approver.as_ref());
+            data[at + 32..at + Approval::SIZE].copy_from_slice(&approval.approved_at.to_le_bytes());
+        }
+        Ok(())
+    }
+
+    /// False for zeroed data, which decodes with a default creator
+    pub fn is_initialized(&self) -> bool {
+        self.creator != Pubkey::default()
+    }
 
     pub fn is_approver(&self, addr: &Pubkey) -> bool {
-        &self.approver1 == addr 
-            || &self.approver2 == addr 
-            || (self.approver3.is_some() && &self.approver3.unwrap() == addr)
+        &self.approver1 == addr
+            || &self.approver2 == addr
+            || self.approver3.as_ref() == Some(addr)
+            || self.extra_approvers.contains(addr)
+    }
+
+    /// The approver currently assigned to `slot`, if the slot is in use
+    pub fn approver_in_slot(&self, slot: u8) -> Option<Pubkey> {
+        match slot {
+            0 => Some(self.approver1),
+            1 => Some(self.approver2),
+            2 => self.approver3,
+            _ => None,
+        }
+    }
+
+    pub fn is_delegate(&self, addr: &Pubkey) -> bool {
+        self.delegations.iter().any(|d| &d.delegate == addr)
+    }
+
+    /// The approver `signer` acts for: itself if it is an approver, otherwise
+    /// the approver that delegated to it
+    pub fn approver_for(&self, signer: &Pubkey) -> Option<Pubkey> {
+        if self.is_approver(signer) {
+            return Some(*signer);
+        }
+        self.delegations
+            .iter()
+            .find(|d| &d.delegate == signer)
+            .map(|d| d.approver)
+    }
+
+    /// Add `approval` in arrival order, or at its sorted position for escrows with `sort_approvals`
+    pub fn record_approval(&mut self, approval: Approval) {
+        if self.sort_approvals {
+            let at = self.approvals.partition_point(|a| a.approver < approval.approver);
+            self.approvals.insert(at, approval);
+        } else {
+            self.approvals.push(approval);
+        }
     }
 
     pub fn has_approved(&self, addr: &Pubkey) -> bool {
-        self.approvals.contains(addr)
+        self.approvals.iter().any(|a| &a.approver == addr)
+    }
+
+    /// Approvals can never outnumber the distinct approvers, nor the space budgeted in MAX_SIZE
+    pub fn approval_limit(&self) -> usize {
+        self.total_approvers().min(Self::MAX_APPROVERS)
     }
 
     pub fn required_approvals(&self) -> usize {
+        if self.threshold > 0 {
+            return self.threshold as usize;
+        }
         let unique_approvers = self.total_approvers();
+        // Never fewer than one required approval, even for a degenerate approver set
         match unique_approvers {
-            0 => 0,
-            1 => 1,
+            0 | 1 => 1,
             2 => 2,
             _ => 2,
         }
     }
 
-    pub fn total_approvers(&self) -> usize {
+    /// Distinct approvers, sorted
+    pub fn unique_approvers(&self) -> Vec<Pubkey> {
         let mut unique_approvers = vec![self.approver1, self.approver2];
         if let Some(a3) = self.approver3 {
             unique_approvers.push(a3);
         }
+        unique_approvers.extend_from_slice(&self.extra_approvers);
         unique_approvers.sort();
         unique_approvers.dedup();
-        unique_approvers.len()
+        unique_approvers
+    }
+
+    pub fn total_approvers(&self) -> usize {
+        self.unique_approvers().len()
+    }
+
+    pub fn approver_weight(&self, addr: &Pubkey) -> u32 {
+        if &self.approver1 == addr {
+            self.weights[0] as u32
+        } else if &self.approver2 == addr {
+            self.weights[1] as u32
+        } else if self.approver3.as_ref() == Some(addr) {
+            self.weights[2] as u32
+        } else if self.extra_approvers.contains(addr) {
+            1
+        } else {
+            0
+        }
+    }
+
+    /// An approval counts until `approval_ttl_secs` has passed since it was given,
+    /// forever when no TTL is set
+    pub fn is_approval_live(&self, approval: &Approval, now: i64) -> bool {
+        self.approval_ttl_secs == 0
+            || now.saturating_sub(approval.approved_at) <= self.approval_ttl_secs
+    }
+
+    pub fn has_live_approval(&self, addr: &Pubkey, now: i64) -> bool {
+        self.approvals
+            .iter()
+            .any(|a| &a.approver == addr && self.is_approval_live(a, now))
+    }
+
+    /// Whether `approver` approved fewer than `approval_rate_limit_slots` slots before `slot`
+    pub fn is_approval_rate_limited(&self, approver: &Pubkey, slot: u64) -> bool {
+        self.approval_rate_limit_slots > 0
+            && self.approval_slots.iter().any(|s| {
+                &s.approver == approver
+                    && slot < s.last_approval_slot.saturating_add(self.approval_rate_limit_slots)
+            })
+    }
+
+    /// Remember `slot` as the latest approval by `approver`
+    pub fn record_approval_slot(&mut self, approver: Pubkey, slot: u64) {
+        match self.approval_slots.iter_mut().find(|s| s.approver == approver) {
+            Some(entry) => entry.last_approval_slot = slot,
+            None => self.approval_slots.push(ApprovalSlot { approver, last_approval_slot: slot }),
+        }
+    }
+
+    /// Drop expired approvals so their approvers can re-submit
+    pub fn prune_expired_approvals(&mut self, now: i64) {
+        let approvals = std::mem::take(&mut self.approvals);
+        self.approvals = approvals
+            .into_iter()
+            .filter(|a| self.is_approval_live(a, now))
+            .collect();
+        let approvals = &self.approvals;
+        self.approval_comments.retain(|c| approvals.iter().any(|a| a.approver == c.approver));
+    }
+
+    pub fn approved_weight(&self, now: i64) -> u32 {
+        self.approvals
+            .iter()
+            .filter(|a| self.is_approval_live(a, now))
+            .map(|a| self.approver_weight(&a.approver))
+            .sum()
+    }
+
+    /// With the default weights of 1 this equals the approval count threshold
+    pub fn required_weight(&self) -> u32 {
+        self.required_approvals() as u32
+    }
+
+    /// Bitmask with the category bit of `addr`'s slot, 0 for non-approvers
+    pub fn approver_category_bit(&self, addr: &Pubkey) -> u8 {
+        if &self.approver1 == addr {
+            1 << self.categories[0]
+        } else if &self.approver2 == addr {
+            1 << self.categories[1]
+        } else if self.approver3.as_ref() == Some(addr) {
+            1 << self.categories[2]
+        } else if self.extra_approvers.contains(addr) {
+            1 << 0
+        } else {
+            0
+        }
+    }
+
+    /// Bitmask of the categories represented among live approvals
+    pub fn approved_categories(&self, now: i64) -> u8 {
+        self.approvals
+            .iter()
+            .filter(|a| self.is_approval_live(a, now))
+            .fold(0, |mask, a| mask | self.approver_category_bit(&a.approver))
+    }
+
+    pub fn can_be_released(&self, now: i64) -> bool {
+        self.is_active()
+            && self.is_acknowledged()
+            && self.approved_weight(now) >= self.required_weight()
+            && self.approved_categories(now) & self.required_categories == self.required_categories
+    }
+
+    /// The beneficiary acknowledged, or the escrow does not ask it to
+    pub fn is_acknowledged(&self) -> bool {
+        !self.require_ack || self.beneficiary_acknowledged
+    }
+
+    /// Whether an approval from `who` would be accepted and counted: the escrow is
+    /// active and unfrozen, and `who` is an approver without a live approval
+    pub fn can_approve(&self, who: &Pubkey, now: i64) -> bool {
+        let live_approvals =
+            self.approvals.iter().filter(|a| self.is_approval_live(a, now)).count();
+        self.is_active()
+            && !self.is_declined
+            && !self.frozen
+            && self.is_approver(who)
+            && !self.has_live_approval(who, now)
+            && self.is_in_approval_order(who, now)
+            && live_approvals < self.approval_limit()
+    }
+
+    /// Always true unless `approval_order` is set, in which case every approver
+    /// before `addr` in slot order, then extra approvers, must hold a live approval
+    pub fn is_in_approval_order(&self, addr: &Pubkey, now: i64) -> bool {
+        if !self.approval_order {
+            return true;
+        }
+        [Some(self.approver1), Some(self.approver2), self.approver3]
+            .into_iter()
+            .flatten()
+            .chain(self.extra_approvers.iter().copied())
+            .take_while(|approver| approver != addr)
+            .all(|approver| self.has_live_approval(&approver, now))
+    }
+
+    /// Whether an approval from `addr` would reach quorum, without recording it
+    pub fn would_release_with(&self, addr: &Pubkey, now: i64) -> bool {
+        if self.is_completed || !self.is_approver(addr) || self.has_live_approval(addr, now) {
+            return false;
+        }
+        let categories = self.approved_categories(now) | self.approver_category_bit(addr);
+        self.is_acknowledged()
+            && self.approved_weight(now) + self.approver_weight(addr) >= self.required_weight()
+            && categories & self.required_categories == self.required_categories
+    }
+
+    pub fn is_in_group(&self, group_id: &[u8; 32]) -> bool {
+        self.group_id.as_ref() == Some(group_id)
+    }
+
+    /// Both escrows are bundled under the same group; ungrouped escrows share none
+    pub fn shares_group_with(&self, other: &Escrow) -> bool {
+        self.group_id.map_or(false, |group_id| other.is_in_group(&group_id))
+    }
+
+    pub fn is_expired(&self, now: i64) -> bool {
+        self.expires_at != 0 && now >= self.expires_at
+    }
+
+    /// `expires_at` for a copy of this escrow created at `created_at`: the same
+    /// lifetime as this escrow, not the same absolute deadline
+    pub fn expiry_for_copy_at(&self, created_at: i64) -> i64 {
+        if self.expires_at == 0 {
+            return 0;
+        }
+        created_at.saturating_add(self.expires_at.saturating_sub(self.created_at))
     }
 
-    pub fn can_be_released(&self) -> bool {
-        !self.is_completed && self.approvals.len() >= self.required_approvals()
+    /// Only the creator may cancel an active escrow, and only before any
+    /// approvals unless the beneficiary declined it
+    pub fn check_cancel(&self, caller: &Pubkey) -> Result<(), EscrowError> {
+        if &self.creator != caller {
+            return Err(EscrowError::Unauthorized);
+        }
+        if self.is_completed {
+            return Err(EscrowError::EscrowCompleted);
+        }
+        if !self.approvals.is_empty() && !self.is_declined {
+            return Err(EscrowError::CannotCancelAfterApprovals);
+        }
+        Ok(())
+    }
+
+    pub fn can_be_cancelled(&self, caller: &Pubkey) -> bool {
+        self.check_cancel(caller).is_ok()
+    }
+
+    /// The creator may not cancel yet; a decline by the beneficiary lifts the lock
+    pub fn is_cancel_locked(&self, now: i64) -> bool {
+        !self.is_declined && now.saturating_sub(self.created_at) < self.cancel_lock_secs
+    }
+
+    pub fn is_too_young(&self, now: i64) -> bool {
+        now.saturating_sub(self.created_at) < self.min_age_secs
+    }
+
+    /// Earliest release time for quorum reached at `quorum_at`, honouring both
+    /// the release delay and the minimum age
+    pub fn release_unlocks_at(&self, quorum_at: i64) -> Option<i64> {
+        let delayed = quorum_at.checked_add(self.release_delay_secs)?;
+        let aged = self.created_at.checked_add(self.min_age_secs)?;
+        Some(delayed.max(aged))
+    }
+
+    /// Quorum was reached and the release delay has elapsed
+    pub fn is_releasable(&self, now: i64) -> bool {
+        !self.is_completed && self.releasable_at != 0 && now >= self.releasable_at
+    }
+
+    pub fn approver_matrix(&self, now: i64) -> ApproverMatrix {
+        let approvers = (0..3)
+            .filter_map(|slot| {
+                let approver = self.approver_in_slot(slot)?;
+                Some(ApproverEntry {
+                    approver,
+                    approved: self.has_live_approval(&approver, now),
+                    weight: self.weights[slot as usize],
+                    category: self.categories[slot as usize],
+                })
+            })
+            .chain(self.extra_approvers.iter().map(|&approver| ApproverEntry {
+                approver,
+                approved: self.has_live_approval(&approver, now),
+                weight: 1,
+                category: 0,
+            }))
+            .collect();
+        ApproverMatrix {
+            escrow_id: self.id,
+            approvers,
+            required_weight: self.required_weight(),
+            approved_weight: self.approved_weight(now),
+        }
+    }
+
+    /// The release rule from before weights, categories, approval expiry and release
+    /// timing existed: enough approvals by count, on an escrow that was not declined.
+    /// Pre-upgrade accounts are judged by `LegacyEscrow::meets_legacy_quorum` instead
+    pub fn meets_legacy_quorum(&self) -> bool {
+        !self.is_declined && self.approvals.len() >= self.required_approvals()
+    }
+
+    /// Whether a release could happen at `now`, and what stands in its way
+    pub fn release_status(&self, now: i64) -> ReleaseStatus {
+        let blocked_by_min_age = self.is_too_young(now);
+        let open = !self.is_declined && !self.frozen;
+        ReleaseStatus {
+            escrow_id: self.id,
+            releasable_now: open && self.is_releasable(now) && !blocked_by_min_age,
+            remaining_weight: self.required_weight().saturating_sub(self.approved_weight(now)),
+            blocked_by_min_age,
+            blocked_by_delay: !self.is_completed
+                && self.release_delay_secs > 0
+                && (self.releasable_at == 0 || now < self.releasable_at),
+        }
+    }
+
+    /// Log the `Display` summary line, the one format handlers use for escrow state
+    pub fn log_summary(&self) {
+        verbose_msg!("{}", self);
+    }
+
+    pub fn status(&self) -> EscrowStatus {
+        if self.is_completed {
+            EscrowStatus::Completed
+        } else if self.is_declined {
+            EscrowStatus::Declined
+        } else if self.frozen {
+            EscrowStatus::Frozen
+        } else if self.releasable_at != 0 {
+            EscrowStatus::PendingRelease
+        } else {
+            EscrowStatus::Active
+        }
+    }
+
+    /// Still open: anything short of released or cancelled. Declined, frozen and
+    /// pending escrows hold their funds and count as active
+    pub fn is_active(&self) -> bool {
+        self.status() != EscrowStatus::Completed
+    }
+
+    pub fn summary(&self) -> EscrowSummary {
+        EscrowSummary {
+            id: self.id,
+            amount: self.amount,
+            status: self.status(),
+            approvals: self.approvals.len() as u8,
+            released: self.released,
+        }
+    }
+
+    /// Deposited lamports not yet paid to the beneficiary
+    pub fn remaining(&self) -> u64 {
+        self.amount.saturating_sub(self.released)
+    }
+
+    /// Count `lamports` paid to the beneficiary, never more than what remains
+    pub fn record_release(&mut self, lamports: u64) -> Result<(), EscrowError> {
+        if lamports > self.remaining() {
+            return Err(EscrowError::InsufficientFunds);
+        }
+        self.released += lamports;
+        Ok(())
+    }
+
+    pub fn is_arbiter(&self, addr: &Pubkey) -> bool {
+        self.arbiter.as_ref() == Some(addr)
+    }
+
+    /// The arbiter is also the creator, the beneficiary or an approver, letting
+    /// one key both vote and settle disputes
+    pub fn has_role_conflict(&self) -> bool {
+        self.arbiter.map_or(false, |arbiter| {
+            arbiter == self.creator || arbiter == self.beneficiary || self.is_approver(&arbiter)
+        })
+    }
+
+    /// Lamports of the escrowed amount still held on the escrow account itself;
+    /// wrapped funds and tokens sit in a token account and completed escrows hold none
+    pub fn held_lamports(&self) -> u64 {
+        if self.is_completed || self.wrapped_sol || self.token_mint.is_some() {
+            0
+        } else {
+            self.amount
+        }
+    }
+
+    /// Both escrows share a creator, beneficiary, approvers and every release term,
+    /// so their funds can be held as one
+    pub fn has_same_terms(&self, other: &Escrow) -> bool {
+        self.creator == other.creator
+            && self.beneficiary == other.beneficiary
+            && self.approver1 == other.approver1
+            && self.approver2 == other.approver2
+            && self.approver3 == other.approver3
+            && self.weights == other.weights
+            && self.refund_to == other.refund_to
+            && self.arbiter == other.arbiter
+            && self.release_delay_secs == other.release_delay_secs
+            && self.min_age_secs == other.min_age_secs
+            && self.approval_ttl_secs == other.approval_ttl_secs
+            && self.categories == other.categories
+            && self.required_categories == other.required_categories
+            && self.require_existing_beneficiary == other.require_existing_beneficiary
+            && self.cancel_penalty_bps == other.cancel_penalty_bps
+            && self.threshold == other.threshold
+            && self.cancel_lock_secs == other.cancel_lock_secs
+            && self.require_ack == other.require_ack
+            && self.extra_approvers == other.extra_approvers
+            && self.executor == other.executor
+            && self.expires_at == other.expires_at
+            && self.group_id == other.group_id
+            && self.approval_order == other.approval_order
+            && self.approval_rate_limit_slots == other.approval_rate_limit_slots
+            && self.token_mint == other.token_mint
+            && self.target_usd == other.target_usd
+            && self.price_feed == other.price_feed
+    }
+
+    /// Lamports of the amount owed to the beneficiary when cancelled by consent
+    pub fn cancel_penalty(&self) -> u64 {
+        (self.amount as u128 * self.cancel_penalty_bps as u128 / Self::MAX_BPS as u128) as u64
+    }
+
+    pub fn refund_recipient(&self) -> Pubkey {
+        self.refund_to.unwrap_or(self.creator)
+    }
+
+    pub fn can_consent_cancel(&self, addr: &Pubkey) -> bool {
+        &self.creator == addr || self.has_approved(addr)
+    }
+
+    /// The creator and every approver who already approved must consent
+    pub fn has_cancel_consensus(&self) -> bool {
+        self.cancel_approvals.contains(&self.creator)
+            && self.approvals.iter().all(|a| self.cancel_approvals.contains(&a.approver))
+    }
+
+    pub fn notes_len(&self) -> usize {
+        self.notes.iter().map(|n| n.len()).sum()
+    }
+
+    pub fn can_append_note(&self, note: &str) -> bool {
+        self.notes.len() < Self::MAX_NOTES && self.notes_len() + note.len() <= Self::MAX_NOTES_LEN
+    }
+}
+
+/// One parseable line of space-separated `key=value` pairs
+impl fmt::Display for Escrow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "escrow id={} amount={} released={} status={:?} approvals={}/{} releasable_at={}",
+            self.id,
+            self.amount,
+            self.released,
+            self.status(),
+            self.approvals.len(),
+            self.required_approvals(),
+            self.releasable_at
+        )
+    }
+}
+
+/// An escrow as written before the first program upgrade, the three-slot layout:
+/// approvals as bare keys and nothing after `completed_at`. Those accounts were
+/// allocated at exactly `SIZE` bytes, which `Escrow::size_with_description` never
+/// returns and `ResizeEscrow` refuses, so the allocation tells the layouts apart
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct LegacyEscrow {
+    pub id: u64,
+    pub creator: Pubkey,
+    pub beneficiary: Pubkey,
+    pub amount: u64,
+    pub approver1: Pubkey,
+    pub approver2: Pubkey,
+    pub approver3: Option<Pubkey>,
+    pub description: String,
+    pub approvals: Vec<Pubkey>,
+    pub is_completed: bool,
+    pub created_at: i64,
+    pub completed_at: i64,
+}
+
+impl LegacyEscrow {
+    pub const SIZE: usize = 8 + 32 + 32 + 8 + 32 + 32 + 1 + 32 + 4 + 200 + 4 + (32 * 3) + 1 + 8 + 8;
+
+    /// Two approvals, or one when the slots name a single distinct approver
+    pub fn required_approvals(&self) -> usize {
+        let mut approvers = vec![self.approver1, self.approver2];
+        approvers.extend(self.approver3);
+        approvers.sort();
+        approvers.dedup();
+        approvers.len().min(2)
+    }
+
+    /// The release rule the escrow was created under
+    pub fn meets_legacy_quorum(&self) -> bool {
+        self.approvals.len() >= self.required_approvals()
+    }
+
+    /// The same escrow in the current layout under default terms, whose quorum rule
+    /// matches the legacy one. Approval times were never recorded, so each approval
+    /// takes the creation time. The PDA bump is left for the caller to fill in
+    pub fn into_escrow(self) -> Escrow {
+        let created_at = self.created_at;
+        let mut escrow = Escrow::new(
+            self.id,
+            self.creator,
+            self.beneficiary,
+            self.amount,
+            self.approver1,
+            self.approver2,
+            self.approver3,
+            self.description,
+            &EscrowOptions::default(),
+            created_at,
+        );
+        escrow.approvals = self
+            .approvals
+            .into_iter()
+            .map(|approver| Approval { approver, approved_at: created_at })
+            .collect();
+        escrow.is_completed = self.is_completed;
+        escrow.completed_at = self.completed_at;
+        escrow
     }
 }
 
 #[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
 pub struct EscrowCounter {
     pub count: u64,
+    pub authority: Pubkey,
+    /// Maximum active escrows per creator, 0 means unlimited
+    pub max_active_escrows: u32,
+    /// Description limit for new escrows, at most `Escrow::MAX_DESCRIPTION_LEN`
+    pub max_description_len: u16,
+    /// Reject descriptions containing control characters such as newlines or NUL
+    pub strict_description: bool,
+    /// Longest expiry new escrows may set, in seconds after creation
+    pub max_expiry_secs: i64,
+    /// Share of an expiry refund, in basis points, paid to the authority as treasury
+    pub expiry_fee_bps: u16,
+    /// Highest id the counter may hand out, 0 for no ceiling. A stored count above
+    /// it can only be corruption and stops escrow creation
+    pub max_escrow_id: u64,
 }
 
 impl EscrowCounter {
-    pub const SIZE: usize = 8;
+    pub const SIZE: usize = 8 + 32 + 4 + 2 + 1 + 8 + 2 + 8;
+    /// One year
+    pub const DEFAULT_MAX_EXPIRY_SECS: i64 = 365 * 24 * 60 * 60;
+    /// Ceiling on `expiry_fee_bps`, so the creator keeps the bulk of an expiry refund
+    pub const MAX_EXPIRY_FEE_BPS: u16 = 1_000;
+
+    /// A description within the configured limit, free of control characters in strict mode
+    pub fn accepts_description(&self, description: &str) -> bool {
+        description.len() <= self.max_description_len as usize
+            && !(self.strict_description && description.chars().any(char::is_control))
+    }
+
+    /// No expiry, or one strictly after creation and within `max_expiry_secs`
+    pub fn accepts_expiry(&self, expiry_secs: i64) -> bool {
+        (0..=self.max_expiry_secs).contains(&expiry_secs)
+    }
+
+    /// Lamports of an expiry refund of `refund` owed to the treasury
+    pub fn expiry_fee(&self, refund: u64) -> u64 {
+        (refund as u128 * self.expiry_fee_bps as u128 / Escrow::MAX_BPS as u128) as u64
+    }
+}
+
+/// Escrow accounts addressed to a beneficiary, grown with realloc as escrows are created
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct BeneficiaryIndex {
+    pub beneficiary: Pubkey,
+    pub escrows: Vec<Pubkey>,
+}
+
+impl BeneficiaryIndex {
+    pub fn size_for(count: usize) -> usize {
+        32 + 4 + (32 * count)
+    }
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct CreatorStats {
+    pub creator: Pubkey,
+    pub active_count: u32,
+    /// Last nonce handed out to a creator-scoped escrow
+    pub nonce: u64,
+}
+
+impl CreatorStats {
+    pub const SIZE: usize = 32 + 4 + 8;
+}
+
+/// Records the escrow created under a creator's idempotency key
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct IdempotencyMarker {
+    pub escrow_id: u64,
+    pub escrow: Pubkey,
+}
+
+impl IdempotencyMarker {
+    pub const SIZE: usize = 8 + 32;
+}
+
+/// The fields a USD-pegged release reads from a Pyth price account (v2 layout),
+/// decoded by offset as the account is not Borsh
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PriceFeed {
+    /// Power of ten `price` and `conf` are scaled by
+    pub expo: i32,
+    /// Unix time of the aggregate price
+    pub timestamp: i64,
+    /// Aggregate price of one SOL in USD
+    pub price: i64,
+    /// Confidence interval around `price`, in the same units
+    pub conf: u64,
+    pub status: u32,
+}
+
+impl PriceFeed {
+    /// Owner of every Pyth price account
+    pub const ORACLE_PROGRAM_ID: Pubkey =
+        solana_program::pubkey!("FsJ3A3u2vn5cTVofAjvy6y5kwABJAqYWpe4975bi2epH");
+    pub const MAGIC: u32 = 0xa1b2_c3d4;
+    pub const VERSION: u32 = 2;
+    pub const ACCOUNT_TYPE_PRICE: u32 = 3;
+    pub const STATUS_TRADING: u32 = 1;
+    /// Bytes up to the end of the aggregate price
+    pub const MIN_LEN: usize = 240;
+    /// Oldest aggregate a release accepts
+    pub const MAX_AGE_SECS: i64 = 60;
+    /// Widest confidence interval a release accepts, relative to the price
+    pub const MAX_CONF_BPS: u64 = 200;
+
+    const EXPO_OFFSET: usize = 20;
+    const TIMESTAMP_OFFSET: usize = 96;
+    const PRICE_OFFSET: usize = 208;
+    const CONF_OFFSET: usize = 216;
+    const STATUS_OFFSET: usize = 224;
+
+    /// Decode a price account, refusing anything without the Pyth price header
+    pub fn unpack(data: &[u8]) -> Result<Self, EscrowError> {
+        if data.len() < Self::MIN_LEN
+            || Self::read_u32(data, 0) != Self::MAGIC
+            || Self::read_u32(data, 4) != Self::VERSION
+            || Self::read_u32(data, 8) != Self::ACCOUNT_TYPE_PRICE
+        {
+            return Err(EscrowError::InvalidPriceFeed);
+        }
+        Ok(Self {
+            expo: Self::read_u32(data, Self::EXPO_OFFSET) as i32,
+            timestamp: Self::read_u64(data, Self::TIMESTAMP_OFFSET) as i64,
+            price: Self::read_u64(data, Self::PRICE_OFFSET) as i64,
+            conf: Self::read_u64(data, Self::CONF_OFFSET),
+            status: Self::read_u32(data, Self::STATUS_OFFSET),
+        })
+    }
+
+    /// The price must be trading, positive, at most `MAX_AGE_SECS` old at `now`
+    /// and within `MAX_CONF_BPS`, with an exponent the payout math can scale
+    pub fn check(&self, now: i64) -> Result<(), EscrowError> {
+        if self.status != Self::STATUS_TRADING
+            || self.price <= 0
+            || !(-12..=0).contains(&self.expo)
+            || self.conf as u128 * 10_000 > self.price as u128 * Self::MAX_CONF_BPS as u128
+        {
+            return Err(EscrowError::InvalidPriceFeed);
+        }
+        if now.saturating_sub(self.timestamp) > Self::MAX_AGE_SECS {
+            return Err(EscrowError::StalePrice);
+        }
+        Ok(())
+    }
+
+    /// Lamports worth `usd_cents` at this price, saturating at `u64::MAX`.
+    /// Only meaningful once `check` has passed
+    pub fn lamports_for_usd_cents(&self, usd_cents: u64) -> u64 {
+        // lamports = cents / 100 * 10^9 / (price * 10^expo)
+        let scale = 10u128.pow((7 - self.expo) as u32);
+        let lamports = usd_cents as u128 * scale / self.price as u128;
+        u64::try_from(lamports).unwrap_or(u64::MAX)
+    }
+
+    fn read_u32(data: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+    }
+
+    fn read_u64(data: &[u8], offset: usize) -> u64 {
+        u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+    }
+}
+
+/// Lifecycle stage of an escrow, derived from its flags
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq)]
+pub enum EscrowStatus {
+    Active,
+    Frozen,
+    Declined,
+    /// Quorum reached, waiting out the release delay
+    PendingRelease,
+    Completed,
+}
+
+/// Per-account answer of `ClassifyEscrows`
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq)]
+pub enum EscrowClass {
+    /// Not owned by the program or not an escrow
+    NotEscrow,
+    Active,
+    /// Released or cancelled
+    Terminal,
+}
+
+/// Compact per-escrow view emitted by `SummarizeEscrows`
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct EscrowSummary {
+    pub id: u64,
+    pub amount: u64,
+    pub status: EscrowStatus,
+    pub approvals: u8,
+    pub released: u64,
+}
+
+/// One approver slot as reported by `ListApprovers`
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct ApproverEntry {
+    pub approver: Pubkey,
+    /// Holds an approval that still counts toward quorum
+    pub approved: bool,
+    pub weight: u16,
+    pub category: u8,
+}
+
+/// Borsh-encoded via `sol_log_data` by `ListApprovers`
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct ApproverMatrix {
+    pub escrow_id: u64,
+    /// One entry per approver slot in use, in slot order
+    pub approvers: Vec<ApproverEntry>,
+    /// Effective quorum, i.e. `required_approvals`
+    pub required_weight: u32,
+    pub approved_weight: u32,
+}
+
+/// Borsh-encoded via `sol_log_data` by `EscrowReleaseStatus`
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct ReleaseStatus {
+    pub escrow_id: u64,
+    /// `FinalizeRelease` would pay out right now
+    pub releasable_now: bool,
+    /// Approval weight still needed for quorum
+    pub remaining_weight: u32,
+    /// The escrow is younger than its minimum age
+    pub blocked_by_min_age: bool,
+    /// A release delay defers payout past quorum and has not yet elapsed
+    pub blocked_by_delay: bool,
+}
+
+/// Borsh-encoded via `sol_log_data` when the creator asks approvers to act
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct ReleaseRequestedEvent {
+    pub escrow_id: u64,
+    pub approvals: u8,
+    /// Approval weight still needed for quorum, one per approval with default weights
+    pub remaining_weight: u32,
+}
+
+/// Borsh-encoded via `sol_log_data` when an approver approves with a comment
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct ApprovalEvent {
+    pub escrow_id: u64,
+    pub approver: Pubkey,
+    pub approved_at: i64,
+    pub comment: String,
+}
+
+/// First `sol_log_data` slice of the record holding an escrow's full Borsh state
+/// just before it is released or refunded, the encoded `Escrow` being the second
+pub const SETTLEMENT_SNAPSHOT_TAG: &[u8] = b"snapshot";
+
+/// Why an escrow ended in a refund rather than a release, encoded as a single byte
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CancelReason {
+    /// `CancelEscrow` by the creator
+    Creator,
+    /// `CancelEscrow` after the beneficiary declined
+    Declined,
+    /// `ConsentCancel` once the creator and every approver agreed
+    Consent,
+    /// Refund under `ForceFinalize`, settled by the arbiter
+    Dispute,
+    /// `EmergencyRecover` by the counter authority
+    Emergency,
+    /// `SweepExpired` once the escrow's `expires_at` passed
+    Expiry,
+}
+
+/// Borsh-encoded as return data by every instruction that refunds a single escrow,
+/// and logged via `sol_log_data` for each escrow `SweepExpired` refunds
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct CancellationReceipt {
+    pub escrow_id: u64,
+    pub reason: CancelReason,
+    /// Lamports returned to the refund recipient
+    pub refunded: u64,
+}
+
+/// Borsh-encoded via `sol_log_data` when the counter authority force-refunds an escrow
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct EmergencyRecoveryEvent {
+    pub escrow_id: u64,
+    pub authority: Pubkey,
+    pub creator: Pubkey,
+    pub amount: u64,
+    /// Approvals standing when the escrow was overridden
+    pub approvals: u8,
+    pub recovered_at: i64,
+}
+
+/// Borsh-encoded via `sol_log_data` each time the beneficiary attaches a proof
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct ProofAttachedEvent {
+    pub escrow_id: u64,
+    pub proof_hash: [u8; 32],
+    /// Proofs on the escrow including this one
+    pub proofs: u8,
+}
+
+/// Borsh-encoded via `sol_log_data` when an escrow releases, recording the
+/// approvals that reached consensus in the order they are stored
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct ReleaseEvent {
+    pub escrow_id: u64,
+    pub beneficiary: Pubkey,
+    pub amount: u64,
+    pub approvals: Vec<Approval>,
+    pub completed_at: i64,
+    /// Cumulative lamports released: `amount` once fully paid out, or less for a
+    /// USD-pegged escrow that refunded its surplus
+    pub released: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn escrow(creator: Pubkey) -> Escrow {
+        Escrow::new(
+            1,
+            creator,
+            Pubkey::new_unique(),
+            100,
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            None,
+            String::new(),
+            &EscrowOptions::default(),
+            0,
+        )
+    }
+
+    #[test]
+    fn release_status_reports_min_age_block() {
+        let mut escrow = escrow(Pubkey::new_unique());
+        escrow.min_age_secs = 100;
+        for approver in [escrow.approver1, escrow.approver2] {
+            escrow.approvals.push(Approval { approver, approved_at: 10 });
+        }
+        escrow.releasable_at = escrow.release_unlocks_at(10).unwrap();
+
+        let status = escrow.release_status(50);
+        assert!(!status.releasable_now);
+        assert_eq!(status.remaining_weight, 0);
+        assert!(status.blocked_by_min_age);
+        assert!(!status.blocked_by_delay);
+
+        let status = escrow.release_status(100);
+        assert!(status.releasable_now);
+        assert!(!status.blocked_by_min_age);
+    }
+
+    fn price_account(price: i64, expo: i32, timestamp: i64) -> Vec<u8> {
+        let mut data = vec![0u8; PriceFeed::MIN_LEN];
+        data[0..4].copy_from_slice(&PriceFeed::MAGIC.to_le_bytes());
+        data[4..8].copy_from_slice(&PriceFeed::VERSION.to_le_bytes());
+        data[8..12].copy_from_slice(&PriceFeed::ACCOUNT_TYPE_PRICE.to_le_bytes());
+        data[20..24].copy_from_slice(&expo.to_le_bytes());
+        data[96..104].copy_from_slice(&timestamp.to_le_bytes());
+        data[208..216].copy_from_slice(&price.to_le_bytes());
+        data[216..224].copy_from_slice(&(price as u64 / 1_000).to_le_bytes());
+        data[224..228].copy_from_slice(&PriceFeed::STATUS_TRADING.to_le_bytes());
+        data
+    }
+
+    #[test]
+    fn price_feed_converts_usd_cents_to_lamports() {
+        // $150.00000000 per SOL: $300 is 2 SOL
+        let feed = PriceFeed::unpack(&price_account(15_000_000_000, -8, 1_000)).unwrap();
+        assert!(feed.check(1_060).is_ok());
+        assert_eq!(feed.lamports_for_usd_cents(30_000), 2_000_000_000);
+        assert!(matches!(feed.check(1_061), Err(EscrowError::StalePrice)));
+    }
+
+    #[test]
+    fn price_feed_rejects_foreign_and_halted_accounts() {
+        let mut data = price_account(15_000_000_000, -8, 1_000);
+        data[0] ^= 1;
+        assert!(matches!(PriceFeed::unpack(&data), Err(EscrowError::InvalidPriceFeed)));
+
+        let data = price_account(15_000_000_000, -8, 1_000);
+        assert!(matches!(
+            PriceFeed::unpack(&data[..PriceFeed::MIN_LEN - 1]),
+            Err(EscrowError::InvalidPriceFeed)
+        ));
+
+        let mut halted = PriceFeed::unpack(&data).unwrap();
+        halted.status = 0;
+        assert!(matches!(halted.check(1_000), Err(EscrowError::InvalidPriceFeed)));
+
+        let mut uncertain = PriceFeed::unpack(&data).unwrap();
+        uncertain.conf = uncertain.price as u64 / 10;
+        assert!(matches!(uncertain.check(1_000), Err(EscrowError::InvalidPriceFeed)));
+    }
+
+    #[test]
+    fn legacy_layout_is_told_apart_by_its_allocation() {
+        assert!(Escrow::size_with_description(0, 0) > LegacyEscrow::SIZE);
+
+        let key = Pubkey::new_unique();
+        let mut legacy = LegacyEscrow {
+            id: 1,
+            creator: Pubkey::new_unique(),
+            beneficiary: Pubkey::new_unique(),
+            amount: 100,
+            approver1: key,
+            approver2: key,
+            approver3: None,
+            description: "x".repeat(200),
+            approvals: vec![],
+            is_completed: false,
+            created_at: 0,
+            completed_at: 0,
+        };
+        // One distinct approver needs one approval, as it did before the upgrade
+        assert!(!legacy.meets_legacy_quorum());
+        legacy.approvals.push(key);
+        assert!(legacy.meets_legacy_quorum());
+        legacy.approver3 = Some(Pubkey::new_unique());
+        assert!(!legacy.meets_legacy_quorum());
+
+        // A full description and three approvals fill the allocation exactly
+        legacy.approvals = vec![key; 3];
+        assert_eq!(legacy.try_to_vec().unwrap().len(), LegacyEscrow::SIZE);
+    }
+
+    #[test]
+    fn legacy_escrow_migrates_with_its_approvals() {
+        let approvers = [Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique()];
+        let legacy = LegacyEscrow {
+            id: 3,
+            creator: Pubkey::new_unique(),
+            beneficiary: Pubkey::new_unique(),
+            amount: 500,
+            approver1: approvers[0],
+            approver2: approvers[1],
+            approver3: Some(approvers[2]),
+            description: "x".repeat(Escrow::MAX_DESCRIPTION_LEN),
+            approvals: vec![approvers[2]],
+            is_completed: false,
+            created_at: 40,
+            completed_at: 0,
+        };
+
+        let mut escrow = legacy.clone().into_escrow();
+        assert_eq!(escrow.approvals, vec![Approval { approver: approvers[2], approved_at: 40 }]);
+        assert_eq!(escrow.required_approvals(), 2);
+        assert!(escrow.extra_approvers.is_empty());
+        assert!(!escrow.can_be_released(100));
+        assert!(
+            escrow.current_size() <= Escrow::size_with_description(Escrow::MAX_DESCRIPTION_LEN, 0)
+        );
+
+        escrow.approvals.push(Approval { approver: approvers[0], approved_at: 40 });
+        assert!(escrow.can_be_released(100));
+        assert_eq!(escrow.amount, legacy.amount);
+        assert_eq!(escrow.description, legacy.description);
+    }
+
+    #[test]
+    fn cancel_penalty_rounds_down_in_creator_favour() {
+        let mut escrow = escrow(Pubkey::new_unique());
+        assert_eq!(escrow.cancel_penalty(), 0);
+
+        escrow.amount = 999;
+        escrow.cancel_penalty_bps = 2_500;
+        assert_eq!(escrow.cancel_penalty(), 249);
+
+        escrow.cancel_penalty_bps = Escrow::MAX_BPS;
+        assert_eq!(escrow.cancel_penalty(), 999);
+    }
+
+    #[test]
+    fn approver_matrix_marks_live_approvals() {
+        let mut escrow = escrow(Pubkey::new_unique());
+        escrow.approver3 = Some(Pubkey::new_unique());
+        escrow.weights = [1, 2, 3];
+        escrow.categories = [0, 1, 2];
+        escrow.approvals.push(Approval { approver: escrow.approver2, approved_at: 0 });
+
+        let matrix = escrow.approver_matrix(0);
+        assert_eq!(matrix.approvers.len(), 3);
+        assert_eq!(
+            matrix.approvers.iter().map(|e| e.approved).collect::<Vec<_>>(),
+            [false, true, false]
+        );
+        assert_eq!(matrix.approvers[2].approver, escrow.approver3.unwrap());
+        assert_eq!(matrix.approvers[2].weight, 3);
+        assert_eq!(matrix.approvers[1].category, 1);
+        assert_eq!(matrix.required_weight, escrow.required_weight());
+        assert_eq!(matrix.approved_weight, 2);
+    }
+
+    #[test]
+    fn sorted_approvals_serialize_independent_of_arrival_order() {
+        let creator = Pubkey::new_unique();
+        let mut first = escrow(creator);
+        first.approver3 = Some(Pubkey::new_unique());
+        first.sort_approvals = true;
+        let mut second = first.clone();
+
+        let approvers = [first.approver1, first.approver2, first.approver3.unwrap()];
+        for approver in approvers {
+            first.record_approval(Approval { approver, approved_at: 7 });
+        }
+        for approver in approvers.iter().rev() {
+            second.record_approval(Approval { approver: *approver, approved_at: 7 });
+        }
+
+        assert_eq!(first.approvals.try_to_vec().unwrap(), second.approvals.try_to_vec().unwrap());
+        assert!(first.approvals.windows(2).all(|w| w[0].approver < w[1].approver));
+    }
+
+    #[test]
+    fn legacy_quorum_ignores_later_terms() {
+        let mut escrow = escrow(Pubkey::new_unique());
+        escrow.required_categories = 1 << 5;
+        escrow.release_delay_secs = 1_000;
+        for approver in [escrow.approver1, escrow.approver2] {
+            escrow.approvals.push(Approval { approver, approved_at: 0 });
+        }
+        assert!(!escrow.can_be_released(0));
+        assert!(escrow.meets_legacy_quorum());
+
+        escrow.is_declined = true;
+        assert!(!escrow.meets_legacy_quorum());
+    }
+
+    #[test]
+    fn threshold_overrides_default_quorum() {
+        let mut escrow = escrow(Pubkey::new_unique());
+        escrow.approver3 = Some(Pubkey::new_unique());
+        assert_eq!(escrow.required_approvals(), 2);
+
+        escrow.threshold = 3;
+        assert_eq!(escrow.required_approvals(), 3);
+        for approver in [escrow.approver1, escrow.approver2] {
+            escrow.approvals.push(Approval { approver, approved_at: 0 });
+        }
+        assert!(!escrow.can_be_released(0));
+    }
+
+    #[test]
+    fn five_of_seven_with_extra_approvers() {
+        let mut escrow = escrow(Pubkey::new_unique());
+        escrow.approver3 = Some(Pubkey::new_unique());
+        escrow.extra_approvers = (0..4).map(|_| Pubkey::new_unique()).collect();
+        escrow.threshold = 5;
+        assert_eq!(escrow.total_approvers(), 7);
+        assert_eq!(escrow.approval_limit(), 7);
+        assert_eq!(escrow.approver_matrix(0).approvers.len(), 7);
+
+        let extras = escrow.extra_approvers.clone();
+        for approver in [escrow.approver1, escrow.approver2, extras[0], extras[1]] {
+            escrow.approvals.push(Approval { approver, approved_at: 0 });
+        }
+        assert!(escrow.is_approver(&extras[3]));
+        assert!(!escrow.can_be_released(0));
+        assert!(escrow.would_release_with(&extras[3], 0));
+
+        escrow.approvals.push(Approval { approver: extras[3], approved_at: 0 });
+        assert!(escrow.can_be_released(0));
+    }
+
+    #[test]
+    fn serialized_size_counts_extra_approvers() {
+        let mut escrow = escrow(Pubkey::new_unique());
+        escrow.approver3 = Some(Pubkey::new_unique());
+        escrow.extra_approvers = (0..4).map(|_| Pubkey::new_unique()).collect();
+        assert_eq!(Escrow::serialized_size(0, 7), escrow.try_to_vec().unwrap().len());
+        assert_eq!(Escrow::serialized_size(0, 7), escrow.current_size());
+    }
+
+    #[test]
+    fn three_slot_escrow_fits_original_allocation() {
+        let escrow = escrow(Pubkey::new_unique());
+        assert!(escrow.extra_approvers.is_empty());
+        assert_eq!(
+            Escrow::size_with_description(Escrow::MAX_DESCRIPTION_LEN, 0),
+            Escrow::MAX_SIZE - Escrow::EXTRA_APPROVER_SIZE * Escrow::MAX_EXTRA_APPROVERS
+        );
+    }
+
+    #[test]
+    fn cancellation_receipt_encodes_reason_as_byte() {
+        let receipt = CancellationReceipt {
+            escrow_id: 7,
+            reason: CancelReason::Dispute,
+            refunded: 1_000,
+        };
+        let data = receipt.try_to_vec().unwrap();
+        assert_eq!(data.len(), 8 + 1 + 8);
+        assert_eq!(data[8], 3);
+        assert_eq!(CancellationReceipt::try_from_slice(&data).unwrap(), receipt);
+        assert_eq!(CancelReason::Expiry.try_to_vec().unwrap(), [5]);
+    }
+
+    #[test]
+    fn can_approve_only_pending_approvers() {
+        let mut escrow = escrow(Pubkey::new_unique());
+        escrow.approver3 = Some(Pubkey::new_unique());
+        escrow.approvals.push(Approval { approver: escrow.approver1, approved_at: 0 });
+
+        assert!(!escrow.can_approve(&escrow.approver1, 0));
+        assert!(escrow.can_approve(&escrow.approver2, 0));
+        assert!(!escrow.can_approve(&Pubkey::new_unique(), 0));
+
+        escrow.frozen = true;
+        assert!(!escrow.can_approve(&escrow.approver2, 0));
+    }
+
+    #[test]
+    fn can_approve_again_once_approval_expires() {
+        let mut escrow = escrow(Pubkey::new_unique());
+        escrow.approval_ttl_secs = 100;
+        escrow.approvals.push(Approval { approver: escrow.approver1, approved_at: 0 });
+
+        assert!(!escrow.can_approve(&escrow.approver1, 50));
+        assert!(escrow.can_approve(&escrow.approver1, 100));
+    }
+
+    #[test]
+    fn expiry_counts_from_creation() {
+        let options = EscrowOptions { expiry_secs: 60, ..EscrowOptions::default() };
+        let escrow = Escrow::new(
+            1,
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            1,
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            None,
+            String::new(),
+            &options,
+            1_000,
+        );
+        assert_eq!(escrow.expires_at, 1_060);
+        assert!(!escrow.is_expired(1_059));
+        assert!(escrow.is_expired(1_060));
+
+        let never = escrow(Pubkey::new_unique());
+        assert!(!never.is_expired(i64::MAX));
+    }
+
+    #[test]
+    fn arbiter_must_hold_no_other_role() {
+        let mut escrow = escrow(Pubkey::new_unique());
+        escrow.approver3 = Some(Pubkey::new_unique());
+        escrow.extra_approvers = vec![Pubkey::new_unique()];
+        escrow.arbiter = Some(Pubkey::new_unique());
+        assert!(!escrow.has_role_conflict());
+
+        let overlaps = [
+            escrow.creator,
+            escrow.beneficiary,
+            escrow.approver1,
+            escrow.approver2,
+            escrow.approver3.unwrap(),
+            escrow.extra_approvers[0],
+        ];
+        for overlap in overlaps {
+            escrow.arbiter = Some(overlap);
+            assert!(escrow.has_role_conflict());
+        }
+
+        escrow.arbiter = None;
+        assert!(!escrow.has_role_conflict());
+    }
+
+    #[test]
+    fn escrows_in_the_same_group() {
+        let mut first = escrow(Pubkey::new_unique());
+        let mut second = escrow(first.creator);
+        assert!(!first.shares_group_with(&second));
+
+        first.group_id = Some([7; 32]);
+        second.group_id = Some([7; 32]);
+        assert!(first.shares_group_with(&second));
+        assert!(second.is_in_group(&[7; 32]));
+
+        second.group_id = Some([8; 32]);
+        assert!(!first.shares_group_with(&second));
+        assert!(!second.is_in_group(&[7; 32]));
+    }
+
+    #[test]
+    fn missing_approver3_never_matches() {
+        let escrow = escrow(Pubkey::new_unique());
+        assert_eq!(escrow.approver3, None);
+        assert!(!escrow.is_approver(&Pubkey::default()));
+        assert!(!escrow.is_approver(&Pubkey::new_unique()));
+        assert!(escrow.is_approver(&escrow.approver2));
+    }
+
+    #[test]
+    fn strict_counter_rejects_control_characters() {
+        let mut counter = EscrowCounter {
+            count: 0,
+            authority: Pubkey::new_unique(),
+            max_active_escrows: 0,
+            max_description_len: Escrow::MAX_DESCRIPTION_LEN as u16,
+            strict_description: false,
+            max_expiry_secs: EscrowCounter::DEFAULT_MAX_EXPIRY_SECS,
+            expiry_fee_bps: 0,
+            max_escrow_id: 0,
+        };
+        assert!(counter.accepts_description("line\nbreak"));
+
+        counter.strict_description = true;
+        assert!(!counter.accepts_description("line\nbreak"));
+        assert!(!counter.accepts_description("nul\0byte"));
+        assert!(counter.accepts_description("plain – unicode é"));
+    }
+
+    #[test]
+    fn display_formats_summary_line() {
+        let mut escrow = escrow(Pubkey::new_unique());
+        escrow.id = 42;
+        escrow.approvals.push(Approval { approver: escrow.approver1, approved_at: 0 });
+        escrow.releasable_at = 1_700_000_000;
+
+        assert_eq!(
+            escrow.to_string(),
+            concat!(
+                "escrow id=42 amount=100 released=0 status=PendingRelease approvals=1/2 ",
+                "releasable_at=1700000000"
+            )
+        );
+    }
+
+    #[test]
+    fn cancel_lock_expires_or_lifts_on_decline() {
+        let mut escrow = escrow(Pubkey::new_unique());
+        assert!(!escrow.is_cancel_locked(0));
+
+        escrow.cancel_lock_secs = 60;
+        assert!(escrow.is_cancel_locked(59));
+        assert!(!escrow.is_cancel_locked(60));
+
+        escrow.is_declined = true;
+        assert!(!escrow.is_cancel_locked(0));
+    }
+
+    #[test]
+    fn release_waits_for_required_acknowledgment() {
+        let mut escrow = escrow(Pubkey::new_unique());
+        escrow.require_ack = true;
+        for approver in [escrow.approver1, escrow.approver2] {
+            escrow.approvals.push(Approval { approver, approved_at: 0 });
+        }
+        assert!(!escrow.can_be_released(0));
+
+        escrow.beneficiary_acknowledged = true;
+        assert!(escrow.can_be_released(0));
+    }
+
+    #[test]
+    fn new_escrow_starts_active_with_default_terms() {
+        let escrow = escrow(Pubkey::new_unique());
+        assert!(escrow.approvals.is_empty());
+        assert!(!escrow.is_completed);
+        assert_eq!(escrow.completed_at, 0);
+        assert!(escrow.notes.is_empty());
+        assert!(!escrow.is_declined);
+        assert!(escrow.cancel_approvals.is_empty());
+        assert!(escrow.delegations.is_empty());
+        assert!(escrow.proofs.is_empty());
+        assert!(escrow.approval_comments.is_empty());
+        assert!(!escrow.frozen);
+        assert_eq!(escrow.releasable_at, 0);
+        assert_eq!(escrow.weights, Escrow::DEFAULT_WEIGHTS);
+        assert_eq!(escrow.status(), EscrowStatus::Active);
+    }
+
+    #[test]
+    fn open_escrows_are_active() {
+        let mut escrow = escrow(Pubkey::new_unique());
+        assert!(escrow.is_active());
+        escrow.releasable_at = 100;
+        assert!(escrow.is_active());
+        escrow.frozen = true;
+        assert!(escrow.is_active());
+        escrow.is_declined = true;
+        assert!(escrow.is_active());
+    }
+
+    #[test]
+    fn released_escrow_is_not_active() {
+        let mut escrow = escrow(Pubkey::new_unique());
+        escrow.record_release(escrow.amount).unwrap();
+        escrow.is_completed = true;
+        assert!(!escrow.is_active());
+        assert_eq!(escrow.remaining(), 0);
+    }
+
+    #[test]
+    fn cancelled_escrow_is_not_active() {
+        let mut escrow = escrow(Pubkey::new_unique());
+        escrow.is_declined = true;
+        escrow.is_completed = true;
+        assert!(!escrow.is_active());
+        assert_eq!(escrow.released, 0);
+    }
+
+    #[test]
+    fn copy_near_expiry_gets_a_fresh_deadline() {
+        let mut source = escrow(Pubkey::new_unique());
+        assert_eq!(source.expiry_for_copy_at(95), 0);
+
+        source.created_at = 0;
+        source.expires_at = 100;
+        let copy_expires_at = source.expiry_for_copy_at(95);
+        assert_eq!(copy_expires_at, 195);
+        assert!(source.is_expired(100));
+        assert!(!Escrow { created_at: 95, expires_at: copy_expires_at, ..source }.is_expired(100));
+    }
+
+    #[test]
+    fn creator_can_cancel_untouched_escrow() {
+        let creator = Pubkey::new_unique();
+        assert!(escrow(creator).can_be_cancelled(&creator));
+    }
+
+    #[test]
+    fn cancel_rejects_non_creator() {
+        let escrow = escrow(Pubkey::new_unique());
+        assert!(!escrow.can_be_cancelled(&escrow.approver1));
+        assert!(matches!(escrow.check_cancel(&escrow.approver1), Err(EscrowError::Unauthorized)));
+    }
+
+    #[test]
+    fn cancel_rejects_completed_escrow() {
+        let creator = Pubkey::new_unique();
+        let mut escrow = escrow(creator);
+        escrow.is_completed = true;
+        assert!(!escrow.can_be_cancelled(&creator));
+        assert!(matches!(escrow.check_cancel(&creator), Err(EscrowError::EscrowCompleted)));
+    }
+
+    #[test]
+    fn cancel_rejects_approved_escrow_unless_declined() {
+        let creator = Pubkey::new_unique();
+        let mut escrow = escrow(creator);
+        escrow.approvals.push(Approval {
+            approver: escrow.approver1,
+            approved_at: 0,
+        });
+        assert!(!escrow.can_be_cancelled(&creator));
+        assert!(matches!(
+            escrow.check_cancel(&creator),
+            Err(EscrowError::CannotCancelAfterApprovals)
+        ));
+
+        escrow.is_declined = true;
+        assert!(escrow.can_be_cancelled(&creator));
+    }
+
+    #[test]
+    fn approvals_written_in_place_match_full_serialize() {
+        let mut escrow = escrow(Pubkey::new_unique());
+        escrow.approver3 = Some(Pubkey::new_unique());
+        escrow.sort_approvals = true;
+        escrow.description = "in place".to_string();
+        escrow.notes.push("note".to_string());
+        escrow.cancel_approvals.push(escrow.approver1);
+        escrow.arbiter = Some(Pubkey::new_unique());
+        escrow.proofs.push([7; 32]);
+        escrow.extra_approvers.push(Pubkey::new_unique());
+        escrow.executor = Some(Pubkey::new_unique());
+        escrow.expires_at = 99;
+        escrow.group_id = Some([3; 32]);
+        escrow.approvals.push(Approval { approver: escrow.approver2, approved_at: 5 });
+        escrow.approval_comments.push(ApprovalComment {
+            approver: escrow.approver2,
+            comment: "ok".to_string(),
+        });
+        assert_eq!(escrow.current_size(), escrow.try_to_vec().unwrap().len());
+
+        let mut data = vec![0u8; Escrow::MAX_SIZE];
+        escrow.serialize(&mut &mut data[..]).unwrap();
+        escrow.record_approval(Approval { approver: escrow.approver1, approved_at: 6 });
+        escrow.write_approvals_in_place(&mut data, 1).unwrap();
+
+        let full = escrow.try_to_vec().unwrap();
+        assert_eq!(&data[..full.len()], &full[..]);
+        assert_eq!(Escrow::deserialize(&mut &data[..]).unwrap(), escrow);
+
+        escrow.approvals.clear();
+        escrow.write_approvals_in_place(&mut data, 2).unwrap();
+        assert_eq!(Escrow::deserialize(&mut &data[..]).unwrap(), escrow);
+        let short = escrow.current_size() - 1;
+        assert!(escrow.write_approvals_in_place(&mut data[..short], 0).is_err());
+    }
+
+    #[test]
+    fn released_plus_remaining_is_the_deposit() {
+        let mut escrow = escrow(Pubkey::new_unique());
+        escrow.amount = 1_000;
+        assert_eq!(escrow.remaining(), 1_000);
+
+        escrow.record_release(250).unwrap();
+        assert_eq!(escrow.released, 250);
+        assert_eq!(escrow.released + escrow.remaining(), escrow.amount);
+        assert_eq!(escrow.summary().released, 250);
+        assert!(escrow.to_string().contains("amount=1000 released=250"));
+
+        assert!(matches!(escrow.record_release(751), Err(EscrowError::InsufficientFunds)));
+        escrow.record_release(escrow.remaining()).unwrap();
+        assert_eq!(escrow.remaining(), 0);
+        assert_eq!(escrow.released, escrow.amount);
+    }
+
+    #[test]
+    fn ordered_approvals_follow_slot_order() {
+        let mut escrow = escrow(Pubkey::new_unique());
+        escrow.approver3 = Some(Pubkey::new_unique());
+        escrow.approval_order = true;
+        let (first, second) = (escrow.approver1, escrow.approver2);
+        let third = escrow.approver3.unwrap();
+
+        assert!(escrow.is_in_approval_order(&first, 0));
+        assert!(!escrow.is_in_approval_order(&second, 0));
+        assert!(!escrow.can_approve(&third, 0));
+
+        escrow.approvals.push(Approval { approver: first, approved_at: 0 });
+        assert!(escrow.can_approve(&second, 0));
+        assert!(!escrow.is_in_approval_order(&third, 0));
+
+        escrow.approval_order = false;
+        assert!(escrow.can_approve(&third, 0));
+    }
+
+    #[test]
+    fn counter_bounds_expiry() {
+        let counter = EscrowCounter {
+            count: 0,
+            authority: Pubkey::new_unique(),
+            max_active_escrows: 0,
+            max_description_len: 0,
+            strict_description: false,
+            max_expiry_secs: 3_600,
+            expiry_fee_bps: 0,
+            max_escrow_id: 0,
+        };
+        assert!(counter.accepts_expiry(0));
+        assert!(counter.accepts_expiry(1));
+        assert!(counter.accepts_expiry(3_600));
+        assert!(!counter.accepts_expiry(3_601));
+        assert!(!counter.accepts_expiry(-1));
+    }
+
+    #[test]
+    fn expiry_fee_leaves_creator_the_bulk() {
+        let mut counter = EscrowCounter {
+            count: 0,
+            authority: Pubkey::new_unique(),
+            max_active_escrows: 0,
+            max_description_len: 0,
+            strict_description: false,
+            max_expiry_secs: 3_600,
+            expiry_fee_bps: 0,
+            max_escrow_id: 0,
+        };
+        assert_eq!(counter.expiry_fee(1_000_000), 0);
+
+        counter.expiry_fee_bps = 250;
+        assert_eq!(counter.expiry_fee(1_000_000), 25_000);
+        // Rounds down in the creator's favour
+        assert_eq!(counter.expiry_fee(399), 9);
+
+        counter.expiry_fee_bps = EscrowCounter::MAX_EXPIRY_FEE_BPS;
+        assert_eq!(counter.expiry_fee(u64::MAX), u64::MAX / 10);
+    }
+
+    #[test]
+    fn approval_rate_limit_outlives_expired_approval() {
+        let mut escrow = escrow(Pubkey::new_unique());
+        let approver = escrow.approver1;
+        escrow.approval_ttl_secs = 5;
+        escrow.approval_rate_limit_slots = 10;
+        escrow.record_approval(Approval { approver, approved_at: 0 });
+        escrow.record_approval_slot(approver, 100);
+
+        // Expired and pruned, but the slot window is still open
+        escrow.prune_expired_approvals(10);
+        assert!(escrow.approvals.is_empty());
+        assert!(escrow.is_approval_rate_limited(&approver, 100));
+        assert!(escrow.is_approval_rate_limited(&approver, 109));
+        assert!(!escrow.is_approval_rate_limited(&approver, 110));
+        assert!(!escrow.is_approval_rate_limited(&escrow.approver2, 100));
+
+        escrow.approval_rate_limit_slots = 0;
+        assert!(!escrow.is_approval_rate_limited(&approver, 100));
+    }
+
+    #[test]
+    fn current_size_matches_borsh_length() {
+        let mut escrow = escrow(Pubkey::new_unique());
+        assert_eq!(escrow.current_size(), escrow.try_to_vec().unwrap().len());
+
+        for (description_len, approvals) in [(0, 1), (17, 2), (Escrow::MAX_DESCRIPTION_LEN, 3)] {
+            escrow.description = "d".repeat(description_len);
+            escrow.approver3 = Some(Pubkey::new_unique());
+            escrow.approvals = (0..approvals)
+                .map(|i| Approval { approver: Pubkey::new_unique(), approved_at: i })
+                .collect();
+            escrow.notes.push("n".repeat(description_len / 4));
+            escrow.delegations.push(Delegation {
+                approver: escrow.approver1,
+                delegate: Pubkey::new_unique(),
+            });
+            escrow.refund_to = Some(Pubkey::new_unique());
+            escrow.record_approval_slot(Pubkey::new_unique(), approvals as u64);
+            assert_eq!(escrow.current_size(), escrow.try_to_vec().unwrap().len());
+        }
+    }
 }
@@ -2,6 +2,7 @@ import * as anchor from "@coral-xyz/anchor";
 import { Program } from "@coral-xyz/anchor";
 import { Solanascrow } from "../target/types/solanascrow";
 import { PublicKey, SystemProgram, Keypair, LAMPORTS_PER_SOL } from "@solana/web3.js";
+import { createAccount, createMint, getAccount, mintTo } from "@solana/spl-token";
 import { assert, expect } from "chai";
 
 describe("solanascrow", () => {
@@ -10,6 +11,14 @@ describe("solanascrow", () => {
 
   const program = anchor.workspace.Solanascrow as Program<Solanascrow>;
 
+  const TOKEN_PROGRAM_ID = new PublicKey("TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA");
+  const NATIVE_MINT = new PublicKey("So11111111111111111111111111111111111111112");
+  const INCINERATOR = new PublicKey("1nc1nerator11111111111111111111111111111111");
+  // Leading slice of the pre-settlement escrow snapshot log, `SETTLEMENT_SNAPSHOT_TAG`
+  const SNAPSHOT_LOG = `Program data: ${Buffer.from("snapshot").toString("base64")} `;
+  const isEventLog = (log: string) =>
+    log.startsWith("Program data: ") && !log.startsWith(SNAPSHOT_LOG);
+
   let counterPda: PublicKey;
   let counterBump: number;
 
@@ -44,6 +53,100 @@ describe("solanascrow", () => {
     await new Promise((resolve) => setTimeout(resolve, 2000));
   });
 
+  const creatorStatsPda = (creatorKey: PublicKey): PublicKey =>
+    PublicKey.findProgramAddressSync(
+      [Buffer.from("creator"), creatorKey.toBuffer()],
+      program.programId
+    )[0];
+
+  const beneficiaryIndexPda = (beneficiaryKey: PublicKey): PublicKey =>
+    PublicKey.findProgramAddressSync(
+      [Buffer.from("beneficiary"), beneficiaryKey.toBuffer()],
+      program.programId
+    )[0];
+
+  // Derive the escrow PDA for the next id handed out by the counter
+  const nextEscrowPda = async (): Promise<PublicKey> => {
+    const counterAccount = await program.account.escrowCounter.fetch(counterPda);
+    const nextEscrowId = counterAccount.count.add(new anchor.BN(1));
+    const [escrowPda] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("escrow"),
+        nextEscrowId.toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    );
+    return escrowPda;
+  };
+
+  // Optional escrow terms, mirroring `EscrowOptions` in the program
+  const escrowOptions = (overrides: Record<string, unknown> = {}) => ({
+    weights: null,
+    refundTo: null,
+    arbiter: null,
+    wrapSol: false,
+    releaseDelaySecs: new anchor.BN(0),
+    minAgeSecs: new anchor.BN(0),
+    approvalTtlSecs: new anchor.BN(0),
+    categories: [0, 0, 0],
+    requiredCategories: 0,
+    requireExistingBeneficiary: false,
+    cancelPenaltyBps: 0,
+    sortApprovals: false,
+    cancelLockSecs: new anchor.BN(0),
+    requireAck: false,
+    extraApprovers: [],
+    threshold: 0,
+    executor: null,
+    expirySecs: new anchor.BN(0),
+    groupId: null,
+    approvalOrder: false,
+    idempotencyKey: null,
+    approvalRateLimitSlots: new anchor.BN(0),
+    tokenMint: null,
+    targetUsd: null,
+    priceFeed: null,
+    ...overrides,
+  });
+
+  type CreateEscrowOptions = {
+    thirdApprover?: PublicKey | null;
+    escrowCreator?: Keypair;
+    escrowBeneficiary?: PublicKey;
+    terms?: Record<string, unknown>;
+  };
+
+  const createEscrow = async (
+    escrowAmount: anchor.BN,
+    description: string,
+    options: CreateEscrowOptions = {}
+  ): Promise<PublicKey> => {
+    const escrowCreator = options.escrowCreator ?? creator;
+    const escrowBeneficiary = options.escrowBeneficiary ?? beneficiary.publicKey;
+    const escrowPda = await nextEscrowPda();
+    await program.methods
+      .createEscrow(
+        escrowAmount,
+        escrowBeneficiary,
+        approver1.publicKey,
+        approver2.publicKey,
+        options.thirdApprover ?? null,
+        description,
+        escrowOptions(options.terms)
+      )
+      .accounts({
+        escrow: escrowPda,
+        counter: counterPda,
+        creator: escrowCreator.publicKey,
+        systemProgram: SystemProgram.programId,
+        creatorStats: creatorStatsPda(escrowCreator.publicKey),
+        beneficiaryIndex: beneficiaryIndexPda(escrowBeneficiary),
+      })
+      .signers([escrowCreator])
+      .rpc();
+    return escrowPda;
+  };
+
   it("Initializes the escrow counter", async () => {
     try {
       await program.methods
@@ -64,6 +167,24 @@ describe("solanascrow", () => {
     }
   });
 
+  it("Fails to initialize the counter twice", async () => {
+    try {
+      await program.methods
+        .initialize()
+        .accounts({
+          counter: counterPda,
+          authority: provider.wallet.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+
+      assert.fail("Should have thrown error");
+    } catch (error) {
+      expect(error.message).to.include("AlreadyInitialized");
+      console.log("✓ Correctly prevented double initialization");
+    }
+  });
+
   it("Creates an escrow with 2 approvers", async () => {
     const escrowAmount = new anchor.BN(1 * LAMPORTS_PER_SOL);
     
@@ -87,13 +208,16 @@ describe("solanascrow", () => {
         approver1.publicKey,
         approver2.publicKey,
         null,
-        "Test escrow with 2 approvers"
+        "Test escrow with 2 approvers",
+        escrowOptions()
       )
       .accounts({
         escrow: escrowPda,
         counter: counterPda,
         creator: creator.publicKey,
         systemProgram: SystemProgram.programId,
+        creatorStats: creatorStatsPda(creator.publicKey),
+        beneficiaryIndex: beneficiaryIndexPda(beneficiary.publicKey),
       })
       .signers([creator])
       .rpc();
@@ -133,13 +257,16 @@ describe("solanascrow", () => {
         approver1.publicKey,
         approver2.publicKey,
         null,
-        "Test escrow for approval"
+        "Test escrow for approval",
+        escrowOptions()
       )
       .accounts({
         escrow: escrowPda,
         counter: counterPda,
         creator: creator.publicKey,
         systemProgram: SystemProgram.programId,
+        creatorStats: creatorStatsPda(creator.publicKey),
+        beneficiaryIndex: beneficiaryIndexPda(beneficiary.publicKey),
       })
       .signers([creator])
       .rpc();
@@ -155,6 +282,7 @@ describe("solanascrow", () => {
         approver: approver1.publicKey,
         beneficiary: beneficiary.publicKey,
         systemProgram: SystemProgram.programId,
+        creatorStats: creatorStatsPda(creator.publicKey),
       })
       .signers([approver1])
       .rpc();
@@ -172,6 +300,7 @@ describe("solanascrow", () => {
         approver: approver2.publicKey,
         beneficiary: beneficiary.publicKey,
         systemProgram: SystemProgram.programId,
+        creatorStats: creatorStatsPda(creator.publicKey),
       })
       .signers([approver2])
       .rpc();
@@ -208,13 +337,16 @@ describe("solanascrow", () => {
         approver1.publicKey,
         approver2.publicKey,
         approver3.publicKey,
-        "Test escrow with 3 approvers (2 of 3)"
+        "Test escrow with 3 approvers (2 of 3)",
+        escrowOptions()
       )
       .accounts({
         escrow: escrowPda,
         counter: counterPda,
         creator: creator.publicKey,
         systemProgram: SystemProgram.programId,
+        creatorStats: creatorStatsPda(creator.publicKey),
+        beneficiaryIndex: beneficiaryIndexPda(beneficiary.publicKey),
       })
       .signers([creator])
       .rpc();
@@ -229,6 +361,7 @@ describe("solanascrow", () => {
         approver: approver1.publicKey,
         beneficiary: beneficiary.publicKey,
         systemProgram: SystemProgram.programId,
+        creatorStats: creatorStatsPda(creator.publicKey),
       })
       .signers([approver1])
       .rpc();
@@ -245,6 +378,7 @@ describe("solanascrow", () => {
         approver: approver2.publicKey,
         beneficiary: beneficiary.publicKey,
         systemProgram: SystemProgram.programId,
+        creatorStats: creatorStatsPda(creator.publicKey),
       })
       .signers([approver2])
       .rpc();
@@ -280,13 +414,16 @@ describe("solanascrow", () => {
         approver1.publicKey,
         approver2.publicKey,
         null,
-        "Test escrow for cancellation"
+        "Test escrow for cancellation",
+        escrowOptions()
       )
       .accounts({
         escrow: escrowPda,
         counter: counterPda,
         creator: creator.publicKey,
         systemProgram: SystemProgram.programId,
+        creatorStats: creatorStatsPda(creator.publicKey),
+        beneficiaryIndex: beneficiaryIndexPda(beneficiary.publicKey),
       })
       .signers([creator])
       .rpc();
@@ -300,6 +437,7 @@ describe("solanascrow", () => {
         escrow: escrowPda,
         creator: creator.publicKey,
         systemProgram: SystemProgram.programId,
+        creatorStats: creatorStatsPda(creator.publicKey),
       })
       .signers([creator])
       .rpc();
@@ -334,13 +472,16 @@ describe("solanascrow", () => {
         approver1.publicKey,
         approver2.publicKey,
         null,
-        "Test escrow - cannot cancel after approval"
+        "Test escrow - cannot cancel after approval",
+        escrowOptions()
       )
       .accounts({
         escrow: escrowPda,
         counter: counterPda,
         creator: creator.publicKey,
         systemProgram: SystemProgram.programId,
+        creatorStats: creatorStatsPda(creator.publicKey),
+        beneficiaryIndex: beneficiaryIndexPda(beneficiary.publicKey),
       })
       .signers([creator])
       .rpc();
@@ -353,6 +494,7 @@ describe("solanascrow", () => {
         approver: approver1.publicKey,
         beneficiary: beneficiary.publicKey,
         systemProgram: SystemProgram.programId,
+        creatorStats: creatorStatsPda(creator.publicKey),
       })
       .signers([approver1])
       .rpc();
@@ -365,6 +507,7 @@ describe("solanascrow", () => {
           escrow: escrowPda,
           creator: creator.publicKey,
           systemProgram: SystemProgram.programId,
+          creatorStats: creatorStatsPda(creator.publicKey),
         })
         .signers([creator])
         .rpc();
@@ -397,13 +540,16 @@ describe("solanascrow", () => {
         approver1.publicKey,
         approver2.publicKey,
         null,
-        "Test unauthorized approval"
+        "Test unauthorized approval",
+        escrowOptions()
       )
       .accounts({
         escrow: escrowPda,
         counter: counterPda,
         creator: creator.publicKey,
         systemProgram: SystemProgram.programId,
+        creatorStats: creatorStatsPda(creator.publicKey),
+        beneficiaryIndex: beneficiaryIndexPda(beneficiary.publicKey),
       })
       .signers([creator])
       .rpc();
@@ -421,6 +567,7 @@ describe("solanascrow", () => {
           approver: randomUser.publicKey,
           beneficiary: beneficiary.publicKey,
           systemProgram: SystemProgram.programId,
+          creatorStats: creatorStatsPda(creator.publicKey),
         })
         .signers([randomUser])
         .rpc();
@@ -453,13 +600,16 @@ describe("solanascrow", () => {
         approver1.publicKey,
         approver2.publicKey,
         null,
-        "Test double approval prevention"
+        "Test double approval prevention",
+        escrowOptions()
       )
       .accounts({
         escrow: escrowPda,
         counter: counterPda,
         creator: creator.publicKey,
         systemProgram: SystemProgram.programId,
+        creatorStats: creatorStatsPda(creator.publicKey),
+        beneficiaryIndex: beneficiaryIndexPda(beneficiary.publicKey),
       })
       .signers([creator])
       .rpc();
@@ -472,6 +622,7 @@ describe("solanascrow", () => {
         approver: approver1.publicKey,
         beneficiary: beneficiary.publicKey,
         systemProgram: SystemProgram.programId,
+        creatorStats: creatorStatsPda(creator.publicKey),
       })
       .signers([approver1])
       .rpc();
@@ -485,6 +636,7 @@ describe("solanascrow", () => {
           approver: approver1.publicKey,
           beneficiary: beneficiary.publicKey,
           systemProgram: SystemProgram.programId,
+          creatorStats: creatorStatsPda(creator.publicKey),
         })
         .signers([approver1])
         .rpc();
@@ -495,5 +647,3761 @@ describe("solanascrow", () => {
       console.log("✓ Correctly prevented double approval");
     }
   });
-});
 
+  it("Appends notes to an escrow", async () => {
+    const escrowPda = await createEscrow(
+      new anchor.BN(0.1 * LAMPORTS_PER_SOL),
+      "Test escrow notes"
+    );
+
+    await program.methods
+      .appendNote("shipment sent")
+      .accounts({
+        escrow: escrowPda,
+        creator: creator.publicKey,
+      })
+      .signers([creator])
+      .rpc();
+
+    await program.methods
+      .appendNote("shipment received")
+      .accounts({
+        escrow: escrowPda,
+        creator: creator.publicKey,
+      })
+      .signers([creator])
+      .rpc();
+
+    const escrowAccount = await program.account.escrow.fetch(escrowPda);
+    assert.deepEqual(escrowAccount.notes, ["shipment sent", "shipment received"]);
+    console.log("✓ Notes appended");
+  });
+
+  it("Lets the creator reclaim after the beneficiary declines", async () => {
+    const escrowPda = await createEscrow(
+      new anchor.BN(0.1 * LAMPORTS_PER_SOL),
+      "Test beneficiary decline"
+    );
+
+    await program.methods
+      .approveRelease()
+      .accounts({
+        escrow: escrowPda,
+        approver: approver1.publicKey,
+        beneficiary: beneficiary.publicKey,
+        systemProgram: SystemProgram.programId,
+        creatorStats: creatorStatsPda(creator.publicKey),
+      })
+      .signers([approver1])
+      .rpc();
+
+    await program.methods
+      .declineEscrow()
+      .accounts({
+        escrow: escrowPda,
+        beneficiary: beneficiary.publicKey,
+      })
+      .signers([beneficiary])
+      .rpc();
+
+    let escrowAccount = await program.account.escrow.fetch(escrowPda);
+    assert.equal(escrowAccount.isDeclined, true);
+    assert.equal(escrowAccount.isCompleted, false);
+
+    const balanceBefore = await provider.connection.getBalance(creator.publicKey);
+
+    await program.methods
+      .cancelEscrow()
+      .accounts({
+        escrow: escrowPda,
+        creator: creator.publicKey,
+        systemProgram: SystemProgram.programId,
+        creatorStats: creatorStatsPda(creator.publicKey),
+      })
+      .signers([creator])
+      .rpc();
+
+    escrowAccount = await program.account.escrow.fetch(escrowPda);
+    assert.equal(escrowAccount.isCompleted, true);
+
+    const balanceAfter = await provider.connection.getBalance(creator.publicKey);
+    assert.isAtLeast(balanceAfter, balanceBefore);
+    console.log("✓ Creator reclaimed declined escrow");
+  });
+
+  it("Reserves distinct sequential ids for back-to-back creates", async () => {
+    const counterBefore = await program.account.escrowCounter.fetch(counterPda);
+
+    const firstPda = await createEscrow(
+      new anchor.BN(0.1 * LAMPORTS_PER_SOL),
+      "Test sequential id 1"
+    );
+    const secondPda = await createEscrow(
+      new anchor.BN(0.1 * LAMPORTS_PER_SOL),
+      "Test sequential id 2"
+    );
+
+    const first = await program.account.escrow.fetch(firstPda);
+    const second = await program.account.escrow.fetch(secondPda);
+    assert.equal(first.id.toString(), counterBefore.count.add(new anchor.BN(1)).toString());
+    assert.equal(second.id.toString(), counterBefore.count.add(new anchor.BN(2)).toString());
+    assert.notEqual(firstPda.toString(), secondPda.toString());
+
+    const counterAfter = await program.account.escrowCounter.fetch(counterPda);
+    assert.equal(counterAfter.count.toString(), second.id.toString());
+    console.log("✓ Sequential creates reserved distinct ids");
+  });
+
+  it("Enforces the per-creator active escrow cap", async () => {
+    const cappedCreator = Keypair.generate();
+    await provider.connection.requestAirdrop(cappedCreator.publicKey, 2 * LAMPORTS_PER_SOL);
+    await new Promise((resolve) => setTimeout(resolve, 1000));
+
+    await program.methods
+      .updateConfig(2, null, null, null, null, null)
+      .accounts({
+        authority: provider.wallet.publicKey,
+        counter: counterPda,
+      })
+      .rpc();
+
+    const escrowAmount = new anchor.BN(0.1 * LAMPORTS_PER_SOL);
+    const firstPda = await createEscrow(escrowAmount, "Test cap 1", { escrowCreator: cappedCreator });
+    await createEscrow(escrowAmount, "Test cap 2", { escrowCreator: cappedCreator });
+
+    try {
+      await createEscrow(escrowAmount, "Test cap 3", { escrowCreator: cappedCreator });
+      assert.fail("Should have thrown error");
+    } catch (error) {
+      expect(error.message).to.include("TooManyEscrows");
+    }
+
+    await program.methods
+      .cancelEscrow()
+      .accounts({
+        escrow: firstPda,
+        creator: cappedCreator.publicKey,
+        systemProgram: SystemProgram.programId,
+        creatorStats: creatorStatsPda(cappedCreator.publicKey),
+      })
+      .signers([cappedCreator])
+      .rpc();
+
+    await createEscrow(escrowAmount, "Test cap 3 after cancel", { escrowCreator: cappedCreator });
+
+    const stats = await program.account.creatorStats.fetch(creatorStatsPda(cappedCreator.publicKey));
+    assert.equal(stats.activeCount, 2);
+
+    await program.methods
+      .updateConfig(0, null, null, null, null, null)
+      .accounts({
+        authority: provider.wallet.publicKey,
+        counter: counterPda,
+      })
+      .rpc();
+    console.log("✓ Active escrow cap enforced");
+  });
+
+  it("Cancels with consent from the creator and existing approvers", async () => {
+    const escrowPda = await createEscrow(
+      new anchor.BN(0.1 * LAMPORTS_PER_SOL),
+      "Test consent cancel",
+      { thirdApprover: approver3.publicKey }
+    );
+
+    await program.methods
+      .approveRelease()
+      .accounts({
+        escrow: escrowPda,
+        approver: approver1.publicKey,
+        beneficiary: beneficiary.publicKey,
+        systemProgram: SystemProgram.programId,
+        creatorStats: creatorStatsPda(creator.publicKey),
+      })
+      .signers([approver1])
+      .rpc();
+
+    const consentCancel = (consenter: Keypair) =>
+      program.methods
+        .consentCancel(0)
+        .accounts({
+          consenter: consenter.publicKey,
+          escrow: escrowPda,
+          refundAccount: creator.publicKey,
+          creatorStats: creatorStatsPda(creator.publicKey),
+        })
+        .signers([consenter])
+        .rpc();
+
+    await consentCancel(creator);
+    let escrowAccount = await program.account.escrow.fetch(escrowPda);
+    assert.equal(escrowAccount.isCompleted, false);
+
+    // Approver who never approved has no say in the cancellation
+    try {
+      await consentCancel(approver2);
+      assert.fail("Should have thrown error");
+    } catch (error) {
+      expect(error.message).to.include("Unauthorized");
+    }
+
+    const balanceBefore = await provider.connection.getBalance(creator.publicKey);
+    await consentCancel(approver1);
+
+    escrowAccount = await program.account.escrow.fetch(escrowPda);
+    assert.equal(escrowAccount.isCompleted, true);
+    assert.equal(escrowAccount.cancelApprovals.length, 2);
+
+    const balanceAfter = await provider.connection.getBalance(creator.publicKey);
+    assert.approximately(balanceAfter - balanceBefore, 0.1 * LAMPORTS_PER_SOL, 10000);
+    console.log("✓ Escrow cancelled by unanimous consent");
+  });
+
+  it("Releases on a single high-weight approval", async () => {
+    const escrowAmount = new anchor.BN(0.1 * LAMPORTS_PER_SOL);
+    const escrowPda = await createEscrow(escrowAmount, "Test weighted approvals", {
+      thirdApprover: approver3.publicKey,
+      terms: { weights: [2, 1, 1] },
+    });
+
+    const balanceBefore = await provider.connection.getBalance(beneficiary.publicKey);
+
+    await program.methods
+      .approveRelease()
+      .accounts({
+        escrow: escrowPda,
+        approver: approver1.publicKey,
+        beneficiary: beneficiary.publicKey,
+        systemProgram: SystemProgram.programId,
+        creatorStats: creatorStatsPda(creator.publicKey),
+      })
+      .signers([approver1])
+      .rpc();
+
+    const escrowAccount = await program.account.escrow.fetch(escrowPda);
+    assert.equal(escrowAccount.approvals.length, 1);
+    assert.equal(escrowAccount.isCompleted, true);
+
+    const balanceAfter = await provider.connection.getBalance(beneficiary.publicKey);
+    assert.approximately(balanceAfter - balanceBefore, escrowAmount.toNumber(), 10000);
+    console.log("✓ Weighted approval reached quorum");
+  });
+
+  it("Rejects zero approver weights", async () => {
+    try {
+      await createEscrow(new anchor.BN(0.1 * LAMPORTS_PER_SOL), "Test zero weight", {
+        terms: { weights: [1, 0, 1] },
+      });
+      assert.fail("Should have thrown error");
+    } catch (error) {
+      expect(error.message).to.include("InvalidWeight");
+      console.log("✓ Correctly rejected zero weight");
+    }
+  });
+
+  it("Estimates the serialized escrow size", async () => {
+    const configs: [number, PublicKey | null, number][] = [
+      [0, null, 0],
+      [48, approver3.publicKey, 0],
+      [200, null, 0],
+      [16, approver3.publicKey, 4],
+    ];
+
+    for (const [descriptionLen, thirdApprover, extraCount] of configs) {
+      const description = "x".repeat(descriptionLen);
+      const numApprovers = (thirdApprover ? 3 : 2) + extraCount;
+      const extraApprovers = Array.from({ length: extraCount }, () => Keypair.generate().publicKey);
+
+      const simulation = await program.methods
+        .estimateSize(descriptionLen, numApprovers)
+        .accounts({ counter: counterPda })
+        .simulate();
+      const sizeLog = simulation.raw.find((log) => log.includes("Escrow size:"));
+      const estimated = parseInt(sizeLog.match(/Escrow size: (\d+) bytes/)[1]);
+      const allocationLog = simulation.raw.find((log) => log.includes("Escrow allocation:"));
+      const allocation = parseInt(allocationLog.match(/Escrow allocation: (\d+) bytes/)[1]);
+
+      const escrowPda = await createEscrow(new anchor.BN(0.01 * LAMPORTS_PER_SOL), description, {
+        thirdApprover,
+        terms: { extraApprovers },
+      });
+      const escrowAccount = await program.account.escrow.fetch(escrowPda);
+      const encoded = await program.coder.accounts.encode("escrow", escrowAccount);
+      assert.equal(estimated, encoded.length);
+      const escrowInfo = await provider.connection.getAccountInfo(escrowPda);
+      assert.equal(allocation, escrowInfo.data.length);
+    }
+    console.log("✓ Size and allocation estimates match created escrows");
+  });
+
+  it("Rejects a bogus system program account", async () => {
+    const bogusSystemProgram = Keypair.generate().publicKey;
+
+    try {
+      await program.methods
+        .initialize()
+        .accounts({
+          counter: counterPda,
+          authority: provider.wallet.publicKey,
+          systemProgram: bogusSystemProgram,
+        })
+        .rpc();
+      assert.fail("Should have thrown error");
+    } catch (error) {
+      expect(error.message).to.include("incorrect program id");
+    }
+
+    const escrowPda = await nextEscrowPda();
+    try {
+      await program.methods
+        .createEscrow(
+          new anchor.BN(0.1 * LAMPORTS_PER_SOL),
+          beneficiary.publicKey,
+          approver1.publicKey,
+          approver2.publicKey,
+          null,
+          "Test bogus system program",
+          escrowOptions()
+        )
+        .accounts({
+          escrow: escrowPda,
+          counter: counterPda,
+          creator: creator.publicKey,
+          systemProgram: bogusSystemProgram,
+          creatorStats: creatorStatsPda(creator.publicKey),
+          beneficiaryIndex: beneficiaryIndexPda(beneficiary.publicKey),
+        })
+        .signers([creator])
+        .rpc();
+      assert.fail("Should have thrown error");
+    } catch (error) {
+      expect(error.message).to.include("incorrect program id");
+      console.log("✓ Correctly rejected bogus system program");
+    }
+  });
+
+  it("Splits an escrow into two", async () => {
+    const escrowPda = await createEscrow(new anchor.BN(100), "Test split escrow");
+    const newEscrowPda = await nextEscrowPda();
+
+    const lamportsBefore = await provider.connection.getBalance(escrowPda);
+
+    await program.methods
+      .splitEscrow(new anchor.BN(40))
+      .accounts({
+        creator: creator.publicKey,
+        escrow: escrowPda,
+        newEscrow: newEscrowPda,
+        counter: counterPda,
+        systemProgram: SystemProgram.programId,
+        creatorStats: creatorStatsPda(creator.publicKey),
+        beneficiaryIndex: beneficiaryIndexPda(beneficiary.publicKey),
+      })
+      .signers([creator])
+      .rpc();
+
+    const original = await program.account.escrow.fetch(escrowPda);
+    const split = await program.account.escrow.fetch(newEscrowPda);
+    assert.equal(original.amount.toString(), "60");
+    assert.equal(split.amount.toString(), "40");
+    assert.equal(split.beneficiary.toString(), original.beneficiary.toString());
+    assert.equal(split.approver1.toString(), original.approver1.toString());
+    assert.equal(split.approver2.toString(), original.approver2.toString());
+    assert.equal(split.isCompleted, false);
+
+    const lamportsAfter = await provider.connection.getBalance(escrowPda);
+    assert.equal(lamportsBefore - lamportsAfter, 40);
+
+    try {
+      await program.methods
+        .splitEscrow(new anchor.BN(60))
+        .accounts({
+          creator: creator.publicKey,
+          escrow: escrowPda,
+          newEscrow: await nextEscrowPda(),
+          counter: counterPda,
+          systemProgram: SystemProgram.programId,
+          creatorStats: creatorStatsPda(creator.publicKey),
+          beneficiaryIndex: beneficiaryIndexPda(beneficiary.publicKey),
+        })
+        .signers([creator])
+        .rpc();
+      assert.fail("Should have thrown error");
+    } catch (error) {
+      expect(error.message).to.include("InsufficientFunds");
+    }
+    console.log("✓ Escrow split into two");
+  });
+
+  it("Refunds a distinct refund_to account on cancel", async () => {
+    const treasury = Keypair.generate();
+    const escrowAmount = new anchor.BN(0.1 * LAMPORTS_PER_SOL);
+    const escrowPda = await createEscrow(escrowAmount, "Test refund_to", {
+      terms: { refundTo: treasury.publicKey },
+    });
+
+    const creatorBalanceBefore = await provider.connection.getBalance(creator.publicKey);
+
+    await program.methods
+      .cancelEscrow()
+      .accounts({
+        escrow: escrowPda,
+        creator: creator.publicKey,
+        systemProgram: SystemProgram.programId,
+        creatorStats: creatorStatsPda(creator.publicKey),
+        refundAccount: treasury.publicKey,
+      })
+      .signers([creator])
+      .rpc();
+
+    const treasuryBalance = await provider.connection.getBalance(treasury.publicKey);
+    assert.equal(treasuryBalance, escrowAmount.toNumber());
+
+    const creatorBalanceAfter = await provider.connection.getBalance(creator.publicKey);
+    assert.isAtMost(creatorBalanceAfter, creatorBalanceBefore);
+    console.log("✓ Refund credited to refund_to account");
+  });
+
+  it("Blocks approvals while frozen by the arbiter", async () => {
+    const arbiter = Keypair.generate();
+    const escrowPda = await createEscrow(new anchor.BN(0.1 * LAMPORTS_PER_SOL), "Test freeze", {
+      terms: { arbiter: arbiter.publicKey },
+    });
+
+    const approve = (approver: Keypair) =>
+      program.methods
+        .approveRelease()
+        .accounts({
+          escrow: escrowPda,
+          approver: approver.publicKey,
+          beneficiary: beneficiary.publicKey,
+          systemProgram: SystemProgram.programId,
+          creatorStats: creatorStatsPda(creator.publicKey),
+        })
+        .signers([approver])
+        .rpc();
+
+    await program.methods
+      .freezeEscrow()
+      .accounts({ arbiter: arbiter.publicKey, escrow: escrowPda })
+      .signers([arbiter])
+      .rpc();
+
+    try {
+      await approve(approver1);
+      assert.fail("Should have thrown error");
+    } catch (error) {
+      expect(error.message).to.include("EscrowFrozen");
+    }
+
+    await program.methods
+      .unfreezeEscrow()
+      .accounts({ arbiter: arbiter.publicKey, escrow: escrowPda })
+      .signers([arbiter])
+      .rpc();
+
+    await approve(approver1);
+    await approve(approver2);
+
+    const escrowAccount = await program.account.escrow.fetch(escrowPda);
+    assert.equal(escrowAccount.frozen, false);
+    assert.equal(escrowAccount.isCompleted, true);
+    console.log("✓ Approvals resumed after unfreeze");
+  });
+
+  it("Creates creator-scoped escrows without id collisions", async () => {
+    const creatorA = Keypair.generate();
+    const creatorB = Keypair.generate();
+    await provider.connection.requestAirdrop(creatorA.publicKey, LAMPORTS_PER_SOL);
+    await provider.connection.requestAirdrop(creatorB.publicKey, LAMPORTS_PER_SOL);
+    await new Promise((resolve) => setTimeout(resolve, 1000));
+
+    const createV2 = async (escrowCreator: Keypair): Promise<PublicKey> => {
+      const [escrowPda] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("escrow"),
+          escrowCreator.publicKey.toBuffer(),
+          new anchor.BN(1).toArrayLike(Buffer, "le", 8),
+        ],
+        program.programId
+      );
+      await program.methods
+        .createEscrowV2(
+          new anchor.BN(0.1 * LAMPORTS_PER_SOL),
+          beneficiary.publicKey,
+          approver1.publicKey,
+          approver2.publicKey,
+          null,
+          "Test creator-scoped escrow",
+          escrowOptions()
+        )
+        .accounts({
+          escrow: escrowPda,
+          counter: counterPda,
+          creator: escrowCreator.publicKey,
+          systemProgram: SystemProgram.programId,
+          creatorStats: creatorStatsPda(escrowCreator.publicKey),
+          beneficiaryIndex: beneficiaryIndexPda(beneficiary.publicKey),
+        })
+        .signers([escrowCreator])
+        .rpc();
+      return escrowPda;
+    };
+
+    const counterBefore = await program.account.escrowCounter.fetch(counterPda);
+    const escrowA = await createV2(creatorA);
+    const escrowB = await createV2(creatorB);
+
+    assert.notEqual(escrowA.toString(), escrowB.toString());
+    const accountA = await program.account.escrow.fetch(escrowA);
+    const accountB = await program.account.escrow.fetch(escrowB);
+    assert.equal(accountA.id.toString(), "1");
+    assert.equal(accountB.id.toString(), "1");
+    assert.equal(accountA.creatorScoped, true);
+
+    // The global counter is untouched by the creator-scoped path
+    const counterAfter = await program.account.escrowCounter.fetch(counterPda);
+    assert.equal(counterAfter.count.toString(), counterBefore.count.toString());
+    console.log("✓ Creator-scoped escrows created without collision");
+  });
+
+  it("Rejects default-pubkey approvers", async () => {
+    const escrowAmount = new anchor.BN(0.1 * LAMPORTS_PER_SOL);
+    const invalidApproverSets: [PublicKey, PublicKey, PublicKey | null][] = [
+      [PublicKey.default, approver2.publicKey, null],
+      [PublicKey.default, PublicKey.default, null],
+      [approver1.publicKey, approver2.publicKey, PublicKey.default],
+    ];
+
+    for (const [first, second, third] of invalidApproverSets) {
+      try {
+        await program.methods
+          .createEscrow(
+            escrowAmount,
+            beneficiary.publicKey,
+            first,
+            second,
+            third,
+            "Test degenerate approvers",
+            escrowOptions()
+          )
+          .accounts({
+            escrow: await nextEscrowPda(),
+            counter: counterPda,
+            creator: creator.publicKey,
+            systemProgram: SystemProgram.programId,
+            creatorStats: creatorStatsPda(creator.publicKey),
+            beneficiaryIndex: beneficiaryIndexPda(beneficiary.publicKey),
+          })
+          .signers([creator])
+          .rpc();
+        assert.fail("Should have thrown error");
+      } catch (error) {
+        expect(error.message).to.include("InvalidApprover");
+      }
+    }
+    console.log("✓ Correctly rejected degenerate approver sets");
+  });
+
+  it("Requires one approval when both approver slots are the same key", async () => {
+    const escrowPda = await nextEscrowPda();
+    await program.methods
+      .createEscrow(
+        new anchor.BN(0.1 * LAMPORTS_PER_SOL),
+        beneficiary.publicKey,
+        approver1.publicKey,
+        approver1.publicKey,
+        null,
+        "Test duplicate approvers",
+        escrowOptions()
+      )
+      .accounts({
+        escrow: escrowPda,
+        counter: counterPda,
+        creator: creator.publicKey,
+        systemProgram: SystemProgram.programId,
+        creatorStats: creatorStatsPda(creator.publicKey),
+        beneficiaryIndex: beneficiaryIndexPda(beneficiary.publicKey),
+      })
+      .signers([creator])
+      .rpc();
+
+    let escrowAccount = await program.account.escrow.fetch(escrowPda);
+    assert.equal(escrowAccount.isCompleted, false);
+
+    await program.methods
+      .approveRelease()
+      .accounts({
+        escrow: escrowPda,
+        approver: approver1.publicKey,
+        beneficiary: beneficiary.publicKey,
+        systemProgram: SystemProgram.programId,
+        creatorStats: creatorStatsPda(creator.publicKey),
+      })
+      .signers([approver1])
+      .rpc();
+
+    escrowAccount = await program.account.escrow.fetch(escrowPda);
+    assert.equal(escrowAccount.isCompleted, true);
+    console.log("✓ Single unique approver releases with one approval");
+  });
+
+  it("Records a timestamp with each approval", async () => {
+    const escrowPda = await createEscrow(
+      new anchor.BN(0.1 * LAMPORTS_PER_SOL),
+      "Test approval timestamps"
+    );
+
+    const signature = await program.methods
+      .approveRelease()
+      .accounts({
+        escrow: escrowPda,
+        approver: approver1.publicKey,
+        beneficiary: beneficiary.publicKey,
+        systemProgram: SystemProgram.programId,
+        creatorStats: creatorStatsPda(creator.publicKey),
+      })
+      .signers([approver1])
+      .rpc({ commitment: "confirmed" });
+
+    const tx = await provider.connection.getTransaction(signature, { commitment: "confirmed" });
+    const escrowAccount = await program.account.escrow.fetch(escrowPda);
+    assert.equal(escrowAccount.approvals.length, 1);
+    assert.equal(escrowAccount.approvals[0].approver.toString(), approver1.publicKey.toString());
+    assert.approximately(escrowAccount.approvals[0].approvedAt.toNumber(), tx.blockTime, 2);
+    console.log("✓ Approval timestamp recorded");
+  });
+
+  it("Changes the beneficiary before approvals", async () => {
+    const newBeneficiary = Keypair.generate();
+    const escrowAmount = new anchor.BN(0.1 * LAMPORTS_PER_SOL);
+    const escrowPda = await createEscrow(escrowAmount, "Test change beneficiary");
+
+    for (const invalid of [PublicKey.default, creator.publicKey]) {
+      try {
+        await program.methods
+          .changeBeneficiary(invalid)
+          .accounts({ creator: creator.publicKey, escrow: escrowPda })
+          .signers([creator])
+          .rpc();
+        assert.fail("Should have thrown error");
+      } catch (error) {
+        expect(error.message).to.include("InvalidBeneficiary");
+      }
+    }
+
+    await program.methods
+      .changeBeneficiary(newBeneficiary.publicKey)
+      .accounts({ creator: creator.publicKey, escrow: escrowPda })
+      .signers([creator])
+      .rpc();
+
+    for (const approver of [approver1, approver2]) {
+      await program.methods
+        .approveRelease()
+        .accounts({
+          escrow: escrowPda,
+          approver: approver.publicKey,
+          beneficiary: newBeneficiary.publicKey,
+          systemProgram: SystemProgram.programId,
+          creatorStats: creatorStatsPda(creator.publicKey),
+        })
+        .signers([approver])
+        .rpc();
+    }
+
+    const escrowAccount = await program.account.escrow.fetch(escrowPda);
+    assert.equal(escrowAccount.beneficiary.toString(), newBeneficiary.publicKey.toString());
+    assert.equal(escrowAccount.isCompleted, true);
+
+    const balance = await provider.connection.getBalance(newBeneficiary.publicKey);
+    assert.equal(balance, escrowAmount.toNumber());
+    console.log("✓ Released to the updated beneficiary");
+  });
+
+  it("Never stores more approvals than approvers", async () => {
+    const escrowPda = await createEscrow(
+      new anchor.BN(0.1 * LAMPORTS_PER_SOL),
+      "Test approval cap",
+      { thirdApprover: approver3.publicKey }
+    );
+
+    const approve = (approver: Keypair) =>
+      program.methods
+        .approveRelease()
+        .accounts({
+          escrow: escrowPda,
+          approver: approver.publicKey,
+          beneficiary: beneficiary.publicKey,
+          systemProgram: SystemProgram.programId,
+          creatorStats: creatorStatsPda(creator.publicKey),
+        })
+        .signers([approver])
+        .rpc();
+
+    await approve(approver1);
+    await approve(approver2);
+
+    try {
+      await approve(approver3);
+      assert.fail("Should have thrown error");
+    } catch (error) {
+      expect(error.message).to.include("EscrowCompleted");
+    }
+
+    const escrowAccount = await program.account.escrow.fetch(escrowPda);
+    assert.isAtMost(escrowAccount.approvals.length, 3);
+    assert.equal(escrowAccount.approvals.length, 2);
+    console.log("✓ Extra approval rejected");
+  });
+
+  it("Releases a wrapped SOL escrow as native lamports", async () => {
+    const escrowAmount = new anchor.BN(0.1 * LAMPORTS_PER_SOL);
+    const escrowPda = await nextEscrowPda();
+    const [wsolPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("wsol"), escrowPda.toBuffer()],
+      program.programId
+    );
+
+    await program.methods
+      .createEscrow(
+        escrowAmount,
+        beneficiary.publicKey,
+        approver1.publicKey,
+        approver2.publicKey,
+        null,
+        "Test wrapped SOL escrow",
+        escrowOptions({ wrapSol: true })
+      )
+      .accounts({
+        escrow: escrowPda,
+        counter: counterPda,
+        creator: creator.publicKey,
+        systemProgram: SystemProgram.programId,
+        creatorStats: creatorStatsPda(creator.publicKey),
+        beneficiaryIndex: beneficiaryIndexPda(beneficiary.publicKey),
+        wsolAccount: wsolPda,
+        nativeMint: NATIVE_MINT,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([creator])
+      .rpc();
+
+    const wsolBalance = await provider.connection.getTokenAccountBalance(wsolPda);
+    assert.equal(wsolBalance.value.amount, escrowAmount.toString());
+
+    const balanceBefore = await provider.connection.getBalance(beneficiary.publicKey);
+
+    for (const approver of [approver1, approver2]) {
+      await program.methods
+        .approveRelease()
+        .accounts({
+          escrow: escrowPda,
+          approver: approver.publicKey,
+          beneficiary: beneficiary.publicKey,
+          systemProgram: SystemProgram.programId,
+          creatorStats: creatorStatsPda(creator.publicKey),
+          wsolAccount: wsolPda,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([approver])
+        .rpc();
+    }
+
+    const escrowAccount = await program.account.escrow.fetch(escrowPda);
+    assert.equal(escrowAccount.isCompleted, true);
+    assert.equal(escrowAccount.wrappedSol, false);
+    assert.isNull(await provider.connection.getAccountInfo(wsolPda));
+
+    const balanceAfter = await provider.connection.getBalance(beneficiary.publicKey);
+    assert.equal(balanceAfter - balanceBefore, escrowAmount.toNumber());
+    console.log("✓ Wrapped escrow released as native SOL");
+  });
+
+  it("Escrows and releases tokens of a 6-decimal mint", async () => {
+    const connection = provider.connection;
+    const mint = await createMint(connection, creator, creator.publicKey, null, 6);
+    const otherMint = await createMint(connection, creator, creator.publicKey, null, 6);
+    const creatorTokens = await createAccount(connection, creator, mint, creator.publicKey);
+    const payeeTokens = await createAccount(connection, creator, mint, beneficiary.publicKey);
+    const otherPayeeTokens = await createAccount(
+      connection,
+      creator,
+      otherMint,
+      beneficiary.publicKey
+    );
+    await mintTo(connection, creator, mint, creatorTokens, creator, 5_000_000);
+
+    // 2.5 tokens at 6 decimals
+    const escrowAmount = new anchor.BN(2_500_000);
+    const escrowPda = await nextEscrowPda();
+    const [vaultPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("vault"), escrowPda.toBuffer()],
+      program.programId
+    );
+    await program.methods
+      .createEscrow(
+        escrowAmount,
+        beneficiary.publicKey,
+        approver1.publicKey,
+        approver2.publicKey,
+        null,
+        "Test token escrow",
+        escrowOptions({ tokenMint: mint })
+      )
+      .accounts({
+        escrow: escrowPda,
+        counter: counterPda,
+        creator: creator.publicKey,
+        systemProgram: SystemProgram.programId,
+        creatorStats: creatorStatsPda(creator.publicKey),
+        beneficiaryIndex: beneficiaryIndexPda(beneficiary.publicKey),
+        creatorTokenAccount: creatorTokens,
+        tokenVault: vaultPda,
+        mint,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([creator])
+      .rpc();
+
+    assert.equal((await getAccount(connection, vaultPda)).amount.toString(), "2500000");
+    assert.equal((await getAccount(connection, creatorTokens)).amount.toString(), "2500000");
+
+    const approve = (approver: Keypair, payee: PublicKey, payeeMint: PublicKey) =>
+      program.methods
+        .approveRelease()
+        .accounts({
+          escrow: escrowPda,
+          approver: approver.publicKey,
+          beneficiary: beneficiary.publicKey,
+          systemProgram: SystemProgram.programId,
+          creatorStats: creatorStatsPda(creator.publicKey),
+          tokenVault: vaultPda,
+          mint: payeeMint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          payeeTokenAccount: payee,
+        })
+        .signers([approver])
+        .rpc();
+
+    await approve(approver1, payeeTokens, mint);
+    // Both the mint passed in and the payee's token account must match the escrow
+    for (const [payee, payeeMint] of [
+      [payeeTokens, otherMint],
+      [otherPayeeTokens, mint],
+    ]) {
+      try {
+        await approve(approver2, payee, payeeMint);
+        assert.fail("Should have thrown error");
+      } catch (error) {
+        expect(error.message).to.include("MintMismatch");
+      }
+    }
+
+    await approve(approver2, payeeTokens, mint);
+    const escrowAccount = await program.account.escrow.fetch(escrowPda);
+    assert.equal(escrowAccount.isCompleted, true);
+    assert.equal((await getAccount(connection, payeeTokens)).amount.toString(), "2500000");
+    assert.isNull(await connection.getAccountInfo(vaultPda));
+    console.log("✓ 6-decimal token escrow released with transfer_checked");
+  });
+
+  it("Indexes escrows by beneficiary", async () => {
+    const indexedBeneficiary = Keypair.generate().publicKey;
+    const escrowAmount = new anchor.BN(0.01 * LAMPORTS_PER_SOL);
+
+    const firstPda = await createEscrow(escrowAmount, "Test beneficiary index 1", {
+      escrowBeneficiary: indexedBeneficiary,
+    });
+    const secondPda = await createEscrow(escrowAmount, "Test beneficiary index 2", {
+      escrowBeneficiary: indexedBeneficiary,
+    });
+
+    const indexPda = beneficiaryIndexPda(indexedBeneficiary);
+    const index = await program.account.beneficiaryIndex.fetch(indexPda);
+    assert.deepEqual(
+      index.escrows.map((escrow) => escrow.toString()),
+      [firstPda.toString(), secondPda.toString()]
+    );
+
+    const simulation = await program.methods
+      .getBeneficiaryIndex()
+      .accounts({ beneficiaryIndex: indexPda })
+      .simulate();
+    assert.isTrue(simulation.raw.some((log) => log.includes(firstPda.toString())));
+    assert.isTrue(simulation.raw.some((log) => log.includes(secondPda.toString())));
+    console.log("✓ Beneficiary index lists both escrows");
+  });
+
+  it("Returns the new escrow id as return data", async () => {
+    const counterAccount = await program.account.escrowCounter.fetch(counterPda);
+    const expectedId = counterAccount.count.add(new anchor.BN(1));
+    const escrowPda = await nextEscrowPda();
+
+    const signature = await program.methods
+      .createEscrow(
+        new anchor.BN(0.01 * LAMPORTS_PER_SOL),
+        beneficiary.publicKey,
+        approver1.publicKey,
+        approver2.publicKey,
+        null,
+        "Test return data",
+        escrowOptions()
+      )
+      .accounts({
+        escrow: escrowPda,
+        counter: counterPda,
+        creator: creator.publicKey,
+        systemProgram: SystemProgram.programId,
+        creatorStats: creatorStatsPda(creator.publicKey),
+        beneficiaryIndex: beneficiaryIndexPda(beneficiary.publicKey),
+      })
+      .signers([creator])
+      .rpc({ commitment: "confirmed" });
+
+    const tx = await provider.connection.getTransaction(signature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    const [returnData] = tx.meta.returnData.data;
+    const returnedId = new anchor.BN(Buffer.from(returnData, "base64"), "le");
+    assert.equal(tx.meta.returnData.programId, program.programId.toString());
+    assert.equal(returnedId.toString(), expectedId.toString());
+    console.log("✓ Escrow id read from return data");
+  });
+
+  it("Previews whether an approval would release", async () => {
+    const escrowPda = await createEscrow(
+      new anchor.BN(0.01 * LAMPORTS_PER_SOL),
+      "Test approval preview"
+    );
+
+    const preview = async (approver: PublicKey): Promise<string[]> => {
+      const simulation = await program.methods
+        .previewApproval(approver)
+        .accounts({ escrow: escrowPda })
+        .simulate();
+      return simulation.raw;
+    };
+
+    let logs = await preview(approver1.publicKey);
+    assert.isTrue(logs.some((log) => log.includes("Preview: would not release")));
+
+    await program.methods
+      .approveRelease()
+      .accounts({
+        escrow: escrowPda,
+        approver: approver1.publicKey,
+        beneficiary: beneficiary.publicKey,
+        systemProgram: SystemProgram.programId,
+        creatorStats: creatorStatsPda(creator.publicKey),
+      })
+      .signers([approver1])
+      .rpc();
+
+    logs = await preview(approver1.publicKey);
+    assert.isTrue(logs.some((log) => log.includes("already approved")));
+    assert.isTrue(logs.some((log) => log.includes("Preview: would not release")));
+
+    logs = await preview(approver2.publicKey);
+    assert.isTrue(logs.some((log) => log.includes("Preview: would release")));
+
+    // Preview never records an approval
+    let escrowAccount = await program.account.escrow.fetch(escrowPda);
+    assert.equal(escrowAccount.approvals.length, 1);
+
+    await program.methods
+      .approveRelease()
+      .accounts({
+        escrow: escrowPda,
+        approver: approver2.publicKey,
+        beneficiary: beneficiary.publicKey,
+        systemProgram: SystemProgram.programId,
+        creatorStats: creatorStatsPda(creator.publicKey),
+      })
+      .signers([approver2])
+      .rpc();
+
+    escrowAccount = await program.account.escrow.fetch(escrowPda);
+    assert.equal(escrowAccount.isCompleted, true);
+    console.log("✓ Preview predicted the releasing approval");
+  });
+
+  it("Replaces an approver with creator and arbiter co-signing", async () => {
+    const arbiter = Keypair.generate();
+    const newApprover = Keypair.generate();
+    const escrowPda = await createEscrow(new anchor.BN(0.1 * LAMPORTS_PER_SOL), "Test replace approver", {
+      terms: { arbiter: arbiter.publicKey },
+    });
+
+    const approve = (approver: Keypair) =>
+      program.methods
+        .approveRelease()
+        .accounts({
+          escrow: escrowPda,
+          approver: approver.publicKey,
+          beneficiary: beneficiary.publicKey,
+          systemProgram: SystemProgram.programId,
+          creatorStats: creatorStatsPda(creator.publicKey),
+        })
+        .signers([approver])
+        .rpc();
+
+    const replace = (slot: number, replacement: PublicKey) =>
+      program.methods
+        .replaceApprover(slot, replacement)
+        .accounts({ creator: creator.publicKey, arbiter: arbiter.publicKey, escrow: escrowPda })
+        .signers([creator, arbiter])
+        .rpc();
+
+    await approve(approver1);
+
+    // Swapping in a key that already holds a slot would break uniqueness
+    try {
+      await replace(1, approver1.publicKey);
+      assert.fail("Should have thrown error");
+    } catch (error) {
+      expect(error.message).to.include("InvalidApprover");
+    }
+
+    await replace(1, newApprover.publicKey);
+
+    let escrowAccount = await program.account.escrow.fetch(escrowPda);
+    assert.equal(escrowAccount.approver2.toString(), newApprover.publicKey.toString());
+    assert.equal(escrowAccount.approvals.length, 1);
+
+    try {
+      await approve(approver2);
+      assert.fail("Should have thrown error");
+    } catch (error) {
+      expect(error.message).to.include("Unauthorized");
+    }
+
+    await approve(newApprover);
+
+    escrowAccount = await program.account.escrow.fetch(escrowPda);
+    assert.equal(escrowAccount.isCompleted, true);
+    console.log("✓ Replacement approver completed the escrow");
+  });
+
+  it("Emits a release event with the approvals in order", async () => {
+    const escrowAmount = new anchor.BN(0.1 * LAMPORTS_PER_SOL);
+    const escrowPda = await createEscrow(escrowAmount, "Test release event", {
+      thirdApprover: approver3.publicKey,
+    });
+
+    const approve = (approver: Keypair) =>
+      program.methods
+        .approveRelease()
+        .accounts({
+          escrow: escrowPda,
+          approver: approver.publicKey,
+          beneficiary: beneficiary.publicKey,
+          systemProgram: SystemProgram.programId,
+          creatorStats: creatorStatsPda(creator.publicKey),
+        })
+        .signers([approver])
+        .rpc({ commitment: "confirmed" });
+
+    await approve(approver3);
+    const signature = await approve(approver1);
+
+    const tx = await provider.connection.getTransaction(signature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    const dataLog = tx.meta.logMessages.find(isEventLog);
+    const event = Buffer.from(dataLog.slice("Program data: ".length), "base64");
+
+    // Borsh layout of `ReleaseEvent`
+    const escrowId = new anchor.BN(event.subarray(0, 8), "le");
+    const eventBeneficiary = new PublicKey(event.subarray(8, 40));
+    const amount = new anchor.BN(event.subarray(40, 48), "le");
+    const approvalCount = event.readUInt32LE(48);
+    const approvers = [];
+    for (let i = 0; i < approvalCount; i++) {
+      const offset = 52 + i * 40;
+      approvers.push(new PublicKey(event.subarray(offset, offset + 32)).toString());
+    }
+    const completedOffset = 52 + approvalCount * 40;
+    const completedAt = new anchor.BN(event.subarray(completedOffset, completedOffset + 8), "le");
+    const released = new anchor.BN(event.subarray(completedOffset + 8), "le");
+
+    const escrowAccount = await program.account.escrow.fetch(escrowPda);
+    assert.equal(escrowId.toString(), escrowAccount.id.toString());
+    assert.equal(eventBeneficiary.toString(), beneficiary.publicKey.toString());
+    assert.equal(amount.toString(), escrowAmount.toString());
+    assert.deepEqual(approvers, [approver3.publicKey.toString(), approver1.publicKey.toString()]);
+    assert.equal(completedAt.toString(), escrowAccount.completedAt.toString());
+    assert.equal(released.toString(), escrowAmount.toString());
+    assert.equal(escrowAccount.released.toString(), escrowAmount.toString());
+    console.log("✓ Release event recorded the approving set");
+  });
+
+  it("Resizes an escrow to its content plus headroom", async () => {
+    const escrowPda = await createEscrow(
+      new anchor.BN(0.1 * LAMPORTS_PER_SOL),
+      "Test resize"
+    );
+
+    const resize = (headroom: number) =>
+      program.methods
+        .resizeEscrow(headroom)
+        .accounts({
+          creator: creator.publicKey,
+          escrow: escrowPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([creator])
+        .rpc();
+
+    const dataLen = async (): Promise<number> =>
+      (await provider.connection.getAccountInfo(escrowPda)).data.length;
+
+    // Shrink from MAX_SIZE down to exactly the current content
+    await resize(0);
+    const contentLen = await dataLen();
+
+    await resize(64);
+    assert.equal(await dataLen(), contentLen + 64);
+
+    await program.methods
+      .appendNote("shipment sent")
+      .accounts({ escrow: escrowPda, creator: creator.publicKey })
+      .signers([creator])
+      .rpc();
+
+    await resize(0);
+    // Four bytes of length prefix plus the note itself
+    assert.equal(await dataLen(), contentLen + 4 + "shipment sent".length);
+
+    const escrowAccount = await program.account.escrow.fetch(escrowPda);
+    assert.deepEqual(escrowAccount.notes, ["shipment sent"]);
+
+    const rentExempt = await provider.connection.getMinimumBalanceForRentExemption(await dataLen());
+    const lamports = await provider.connection.getBalance(escrowPda);
+    assert.equal(lamports, rentExempt + 0.1 * LAMPORTS_PER_SOL);
+    console.log("✓ Escrow resized up and back down");
+  });
+
+  it("Holds a delayed release until the timelock elapses", async () => {
+    const releaseBeneficiary = Keypair.generate();
+    const escrowAmount = new anchor.BN(0.1 * LAMPORTS_PER_SOL);
+    const escrowPda = await createEscrow(escrowAmount, "Test release delay", {
+      escrowBeneficiary: releaseBeneficiary.publicKey,
+      terms: { releaseDelaySecs: new anchor.BN(2) },
+    });
+
+    for (const approver of [approver1, approver2]) {
+      await program.methods
+        .approveRelease()
+        .accounts({
+          escrow: escrowPda,
+          approver: approver.publicKey,
+          beneficiary: releaseBeneficiary.publicKey,
+          systemProgram: SystemProgram.programId,
+          creatorStats: creatorStatsPda(creator.publicKey),
+        })
+        .signers([approver])
+        .rpc();
+    }
+
+    let escrowAccount = await program.account.escrow.fetch(escrowPda);
+    assert.equal(escrowAccount.isCompleted, false);
+    assert.isTrue(escrowAccount.releasableAt.gt(new anchor.BN(0)));
+    assert.equal(await provider.connection.getBalance(releaseBeneficiary.publicKey), 0);
+
+    const finalize = () =>
+      program.methods
+        .finalizeRelease()
+        .accounts({
+          escrow: escrowPda,
+          beneficiary: releaseBeneficiary.publicKey,
+          creatorStats: creatorStatsPda(creator.publicKey),
+        })
+        .rpc();
+
+    try {
+      await finalize();
+      assert.fail("Should have thrown error");
+    } catch (error) {
+      expect(error.message).to.include("EscrowNotReleasable");
+    }
+
+    await new Promise((resolve) => setTimeout(resolve, 4000));
+    await finalize();
+
+    escrowAccount = await program.account.escrow.fetch(escrowPda);
+    assert.equal(escrowAccount.isCompleted, true);
+    const balance = await provider.connection.getBalance(releaseBeneficiary.publicKey);
+    assert.equal(balance, escrowAmount.toNumber());
+    console.log("✓ Funds released after the delay");
+  });
+
+  it("Summarizes several escrows in one view", async () => {
+    const escrowPdas = [];
+    for (const amount of [0.01, 0.02, 0.03]) {
+      escrowPdas.push(
+        await createEscrow(new anchor.BN(amount * LAMPORTS_PER_SOL), "Test summaries")
+      );
+    }
+
+    await program.methods
+      .approveRelease()
+      .accounts({
+        escrow: escrowPdas[0],
+        approver: approver1.publicKey,
+        beneficiary: beneficiary.publicKey,
+        systemProgram: SystemProgram.programId,
+        creatorStats: creatorStatsPda(creator.publicKey),
+      })
+      .signers([approver1])
+      .rpc();
+
+    // The counter is program-owned but does not deserialize as an escrow
+    const simulation = await program.methods
+      .summarizeEscrows()
+      .remainingAccounts(
+        [...escrowPdas, counterPda].map((pubkey) => ({
+          pubkey,
+          isSigner: false,
+          isWritable: false,
+        }))
+      )
+      .simulate();
+
+    // Borsh layout of `EscrowSummary`
+    const summaries = simulation.raw
+      .filter((log) => log.startsWith("Program data: "))
+      .map((log) => {
+        const data = Buffer.from(log.slice("Program data: ".length), "base64");
+        return {
+          id: new anchor.BN(data.subarray(0, 8), "le"),
+          amount: new anchor.BN(data.subarray(8, 16), "le"),
+          status: data.readUInt8(16),
+          approvals: data.readUInt8(17),
+        };
+      });
+    assert.equal(summaries.length, 3);
+    assert.isTrue(simulation.raw.some((log) => log.includes("not an escrow account")));
+
+    for (const [i, escrowPda] of escrowPdas.entries()) {
+      const escrowAccount = await program.account.escrow.fetch(escrowPda);
+      assert.equal(summaries[i].id.toString(), escrowAccount.id.toString());
+      assert.equal(summaries[i].amount.toString(), escrowAccount.amount.toString());
+      assert.equal(summaries[i].status, 0);
+    }
+    assert.deepEqual(
+      summaries.map((summary) => summary.approvals),
+      [1, 0, 0]
+    );
+    console.log("✓ Summaries emitted for every escrow, garbage skipped");
+  });
+
+  it("Blocks release of an escrow younger than its minimum age", async () => {
+    const releaseBeneficiary = Keypair.generate();
+    const escrowAmount = new anchor.BN(0.1 * LAMPORTS_PER_SOL);
+    const escrowPda = await createEscrow(escrowAmount, "Test minimum age", {
+      escrowBeneficiary: releaseBeneficiary.publicKey,
+      terms: { minAgeSecs: new anchor.BN(3) },
+    });
+
+    // Quorum is reached immediately, but the funds stay put
+    for (const approver of [approver1, approver2]) {
+      await program.methods
+        .approveRelease()
+        .accounts({
+          escrow: escrowPda,
+          approver: approver.publicKey,
+          beneficiary: releaseBeneficiary.publicKey,
+          systemProgram: SystemProgram.programId,
+          creatorStats: creatorStatsPda(creator.publicKey),
+        })
+        .signers([approver])
+        .rpc();
+    }
+
+    let escrowAccount = await program.account.escrow.fetch(escrowPda);
+    assert.equal(escrowAccount.approvals.length, 2);
+    assert.equal(escrowAccount.isCompleted, false);
+
+    const finalize = () =>
+      program.methods
+        .finalizeRelease()
+        .accounts({
+          escrow: escrowPda,
+          beneficiary: releaseBeneficiary.publicKey,
+          creatorStats: creatorStatsPda(creator.publicKey),
+        })
+        .rpc();
+
+    try {
+      await finalize();
+      assert.fail("Should have thrown error");
+    } catch (error) {
+      expect(error.message).to.include("EscrowTooYoung");
+    }
+
+    await new Promise((resolve) => setTimeout(resolve, 5000));
+    await finalize();
+
+    escrowAccount = await program.account.escrow.fetch(escrowPda);
+    assert.equal(escrowAccount.isCompleted, true);
+    const balance = await provider.connection.getBalance(releaseBeneficiary.publicKey);
+    assert.equal(balance, escrowAmount.toNumber());
+    console.log("✓ Release waited out the minimum age");
+  });
+
+  it("Emits a release request with the remaining approvals", async () => {
+    const escrowPda = await createEscrow(new anchor.BN(0.01 * LAMPORTS_PER_SOL), "Test request release", {
+      thirdApprover: approver3.publicKey,
+    });
+
+    // Borsh layout of `ReleaseRequestedEvent`
+    const requestRelease = async () => {
+      const signature = await program.methods
+        .requestRelease()
+        .accounts({ creator: creator.publicKey, escrow: escrowPda })
+        .signers([creator])
+        .rpc({ commitment: "confirmed" });
+      const tx = await provider.connection.getTransaction(signature, {
+        commitment: "confirmed",
+        maxSupportedTransactionVersion: 0,
+      });
+      const dataLog = tx.meta.logMessages.find((log) => log.startsWith("Program data: "));
+      const event = Buffer.from(dataLog.slice("Program data: ".length), "base64");
+      return {
+        escrowId: new anchor.BN(event.subarray(0, 8), "le"),
+        approvals: event.readUInt8(8),
+        remaining: event.readUInt32LE(9),
+      };
+    };
+
+    let event = await requestRelease();
+    const escrowAccount = await program.account.escrow.fetch(escrowPda);
+    assert.equal(event.escrowId.toString(), escrowAccount.id.toString());
+    assert.equal(event.approvals, 0);
+    assert.equal(event.remaining, 2);
+
+    await program.methods
+      .approveRelease()
+      .accounts({
+        escrow: escrowPda,
+        approver: approver2.publicKey,
+        beneficiary: beneficiary.publicKey,
+        systemProgram: SystemProgram.programId,
+        creatorStats: creatorStatsPda(creator.publicKey),
+      })
+      .signers([approver2])
+      .rpc();
+
+    event = await requestRelease();
+    assert.equal(event.approvals, 1);
+    assert.equal(event.remaining, 1);
+    console.log("✓ Release request reported the remaining approvals");
+  });
+
+  it("Stores the escrow bump and signs the release with it", async () => {
+    const counterAccount = await program.account.escrowCounter.fetch(counterPda);
+    const nextEscrowId = counterAccount.count.add(new anchor.BN(1));
+    const [escrowPda, derivedBump] = PublicKey.findProgramAddressSync(
+      [Buffer.from("escrow"), nextEscrowId.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+    const [wsolPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("wsol"), escrowPda.toBuffer()],
+      program.programId
+    );
+
+    await program.methods
+      .createEscrow(
+        new anchor.BN(0.05 * LAMPORTS_PER_SOL),
+        beneficiary.publicKey,
+        approver1.publicKey,
+        approver2.publicKey,
+        null,
+        "Test stored bump",
+        escrowOptions({ wrapSol: true })
+      )
+      .accounts({
+        escrow: escrowPda,
+        counter: counterPda,
+        creator: creator.publicKey,
+        systemProgram: SystemProgram.programId,
+        creatorStats: creatorStatsPda(creator.publicKey),
+        beneficiaryIndex: beneficiaryIndexPda(beneficiary.publicKey),
+        wsolAccount: wsolPda,
+        nativeMint: NATIVE_MINT,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([creator])
+      .rpc();
+
+    let escrowAccount = await program.account.escrow.fetch(escrowPda);
+    assert.equal(escrowAccount.bump, derivedBump);
+
+    // Unwrapping on release is a CPI signed with the stored bump
+    for (const approver of [approver1, approver2]) {
+      await program.methods
+        .approveRelease()
+        .accounts({
+          escrow: escrowPda,
+          approver: approver.publicKey,
+          beneficiary: beneficiary.publicKey,
+          systemProgram: SystemProgram.programId,
+          creatorStats: creatorStatsPda(creator.publicKey),
+          wsolAccount: wsolPda,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([approver])
+        .rpc();
+    }
+
+    escrowAccount = await program.account.escrow.fetch(escrowPda);
+    assert.equal(escrowAccount.isCompleted, true);
+    console.log("✓ Release signed with the stored bump");
+  });
+
+  it("Clones an escrow's terms into a fresh escrow", async () => {
+    const arbiter = Keypair.generate();
+    const sourcePda = await createEscrow(new anchor.BN(0.02 * LAMPORTS_PER_SOL), "Test clone escrow", {
+      thirdApprover: approver3.publicKey,
+      terms: { weights: [2, 1, 1], arbiter: arbiter.publicKey },
+    });
+
+    await program.methods
+      .approveRelease()
+      .accounts({
+        escrow: sourcePda,
+        approver: approver2.publicKey,
+        beneficiary: beneficiary.publicKey,
+        systemProgram: SystemProgram.programId,
+        creatorStats: creatorStatsPda(creator.publicKey),
+      })
+      .signers([approver2])
+      .rpc();
+
+    const sourceBefore = await provider.connection.getAccountInfo(sourcePda);
+    const clonePda = await nextEscrowPda();
+
+    await program.methods
+      .cloneEscrow(new anchor.BN(0.03 * LAMPORTS_PER_SOL))
+      .accounts({
+        creator: creator.publicKey,
+        sourceEscrow: sourcePda,
+        newEscrow: clonePda,
+        counter: counterPda,
+        systemProgram: SystemProgram.programId,
+        creatorStats: creatorStatsPda(creator.publicKey),
+        beneficiaryIndex: beneficiaryIndexPda(beneficiary.publicKey),
+      })
+      .signers([creator])
+      .rpc();
+
+    // The source is left byte-for-byte untouched
+    const sourceAfter = await provider.connection.getAccountInfo(sourcePda);
+    assert.isTrue(sourceAfter.data.equals(sourceBefore.data));
+    assert.equal(sourceAfter.lamports, sourceBefore.lamports);
+
+    const source = await program.account.escrow.fetch(sourcePda);
+    const clone = await program.account.escrow.fetch(clonePda);
+    assert.notEqual(clone.id.toString(), source.id.toString());
+    assert.equal(clone.amount.toString(), (0.03 * LAMPORTS_PER_SOL).toString());
+    assert.equal(clone.beneficiary.toString(), source.beneficiary.toString());
+    assert.equal(clone.approver1.toString(), source.approver1.toString());
+    assert.equal(clone.approver2.toString(), source.approver2.toString());
+    assert.equal(clone.approver3.toString(), source.approver3.toString());
+    assert.deepEqual(clone.weights, source.weights);
+    assert.equal(clone.arbiter.toString(), arbiter.publicKey.toString());
+    assert.equal(clone.approvals.length, 0);
+    assert.equal(clone.isCompleted, false);
+    assert.isTrue(clone.createdAt.gte(source.createdAt));
+    console.log("✓ Escrow cloned with fresh state");
+  });
+
+  it("Moves no funds when the completed escrow cannot be committed", async () => {
+    const releaseBeneficiary = Keypair.generate();
+    const escrowAmount = new anchor.BN(0.1 * LAMPORTS_PER_SOL);
+    const escrowPda = await createEscrow(escrowAmount, "Test release commit", {
+      escrowBeneficiary: releaseBeneficiary.publicKey,
+    });
+
+    const approve = (approver: Keypair) =>
+      program.methods
+        .approveRelease()
+        .accounts({
+          escrow: escrowPda,
+          approver: approver.publicKey,
+          beneficiary: releaseBeneficiary.publicKey,
+          systemProgram: SystemProgram.programId,
+          creatorStats: creatorStatsPda(creator.publicKey),
+        })
+        .signers([approver])
+        .rpc();
+
+    const resize = (headroom: number) =>
+      program.methods
+        .resizeEscrow(headroom)
+        .accounts({
+          creator: creator.publicKey,
+          escrow: escrowPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([creator])
+        .rpc();
+
+    await approve(approver1);
+
+    // With no headroom the releasing approval no longer fits in the account
+    await resize(0);
+    try {
+      await approve(approver2);
+      assert.fail("Should have thrown error");
+    } catch (error) {
+      expect(error.message).to.include("Failed to serialize or deserialize account data");
+    }
+
+    let escrowAccount = await program.account.escrow.fetch(escrowPda);
+    assert.equal(escrowAccount.isCompleted, false);
+    assert.equal(escrowAccount.approvals.length, 1);
+    assert.equal(await provider.connection.getBalance(releaseBeneficiary.publicKey), 0);
+
+    await resize(64);
+    await approve(approver2);
+
+    escrowAccount = await program.account.escrow.fetch(escrowPda);
+    assert.equal(escrowAccount.isCompleted, true);
+    const balance = await provider.connection.getBalance(releaseBeneficiary.publicKey);
+    assert.equal(balance, escrowAmount.toNumber());
+    console.log("✓ Funds only moved once the completed escrow was committed");
+  });
+
+  it("Counts a delegate's approval toward quorum", async () => {
+    const delegate = Keypair.generate();
+    const chained = Keypair.generate();
+    const escrowPda = await createEscrow(new anchor.BN(0.05 * LAMPORTS_PER_SOL), "Test delegation");
+
+    const delegateApproval = (approver: Keypair, delegateKey: PublicKey) =>
+      program.methods
+        .delegateApproval(delegateKey)
+        .accounts({ approver: approver.publicKey, escrow: escrowPda })
+        .signers([approver])
+        .rpc();
+
+    const approve = (signer: Keypair) =>
+      program.methods
+        .approveRelease()
+        .accounts({
+          escrow: escrowPda,
+          approver: signer.publicKey,
+          beneficiary: beneficiary.publicKey,
+          systemProgram: SystemProgram.programId,
+          creatorStats: creatorStatsPda(creator.publicKey),
+        })
+        .signers([signer])
+        .rpc();
+
+    await delegateApproval(approver2, delegate.publicKey);
+
+    // A delegate cannot pass the role on, nor serve a second approver
+    try {
+      await delegateApproval(delegate, chained.publicKey);
+      assert.fail("Should have thrown error");
+    } catch (error) {
+      expect(error.message).to.include("Unauthorized");
+    }
+    try {
+      await delegateApproval(approver1, delegate.publicKey);
+      assert.fail("Should have thrown error");
+    } catch (error) {
+      expect(error.message).to.include("InvalidDelegate");
+    }
+
+    await approve(approver1);
+    await approve(delegate);
+
+    const escrowAccount = await program.account.escrow.fetch(escrowPda);
+    assert.equal(escrowAccount.isCompleted, true);
+    assert.deepEqual(
+      escrowAccount.approvals.map((approval) => approval.approver.toString()),
+      [approver1.publicKey.toString(), approver2.publicKey.toString()]
+    );
+    console.log("✓ Delegate approved on behalf of its approver");
+  });
+
+  it("Rejects an unrelated account passed as the counter", async () => {
+    const escrowPda = await nextEscrowPda();
+    // A program-owned account that is not the counter PDA
+    const bogusCounter = creatorStatsPda(creator.publicKey);
+
+    try {
+      await program.methods
+        .createEscrow(
+          new anchor.BN(0.01 * LAMPORTS_PER_SOL),
+          beneficiary.publicKey,
+          approver1.publicKey,
+          approver2.publicKey,
+          null,
+          "Test bogus counter",
+          escrowOptions()
+        )
+        .accounts({
+          escrow: escrowPda,
+          counter: bogusCounter,
+          creator: creator.publicKey,
+          systemProgram: SystemProgram.programId,
+          creatorStats: creatorStatsPda(creator.publicKey),
+          beneficiaryIndex: beneficiaryIndexPda(beneficiary.publicKey),
+        })
+        .signers([creator])
+        .rpc();
+      assert.fail("Should have thrown error");
+    } catch (error) {
+      expect(error.message).to.include("InvalidCounterAccount");
+    }
+    console.log("✓ Correctly rejected a bogus counter account");
+  });
+
+  it("Expires stale approvals before they reach quorum", async () => {
+    const escrowPda = await createEscrow(new anchor.BN(0.05 * LAMPORTS_PER_SOL), "Test approval ttl", {
+      terms: { approvalTtlSecs: new anchor.BN(2) },
+    });
+
+    const approve = (approver: Keypair) =>
+      program.methods
+        .approveRelease()
+        .accounts({
+          escrow: escrowPda,
+          approver: approver.publicKey,
+          beneficiary: beneficiary.publicKey,
+          systemProgram: SystemProgram.programId,
+          creatorStats: creatorStatsPda(creator.publicKey),
+        })
+        .signers([approver])
+        .rpc();
+
+    await approve(approver1);
+    await new Promise((resolve) => setTimeout(resolve, 4000));
+    await approve(approver2);
+
+    // The first approval lapsed, so the second alone cannot release
+    let escrowAccount = await program.account.escrow.fetch(escrowPda);
+    assert.equal(escrowAccount.isCompleted, false);
+    assert.deepEqual(
+      escrowAccount.approvals.map((approval) => approval.approver.toString()),
+      [approver2.publicKey.toString()]
+    );
+
+    await approve(approver1);
+
+    escrowAccount = await program.account.escrow.fetch(escrowPda);
+    assert.equal(escrowAccount.isCompleted, true);
+    console.log("✓ Expired approval had to be re-submitted");
+  });
+
+  it("Sweeps lamports sent to an escrow beyond its amount and rent", async () => {
+    const escrowAmount = new anchor.BN(0.05 * LAMPORTS_PER_SOL);
+    const escrowPda = await createEscrow(escrowAmount, "Test sweep excess");
+    const lamportsFunded = await provider.connection.getBalance(escrowPda);
+
+    // Over-fund the escrow PDA with a plain transfer
+    const excess = 0.01 * LAMPORTS_PER_SOL;
+    await provider.sendAndConfirm(
+      new anchor.web3.Transaction().add(
+        SystemProgram.transfer({
+          fromPubkey: creator.publicKey,
+          toPubkey: escrowPda,
+          lamports: excess,
+        })
+      ),
+      [creator]
+    );
+    assert.equal(await provider.connection.getBalance(escrowPda), lamportsFunded + excess);
+
+    await program.methods
+      .sweepExcess()
+      .accounts({ creator: creator.publicKey, escrow: escrowPda })
+      .signers([creator])
+      .rpc();
+
+    const escrowInfo = await provider.connection.getAccountInfo(escrowPda);
+    const rentExempt = await provider.connection.getMinimumBalanceForRentExemption(
+      escrowInfo.data.length
+    );
+    assert.equal(escrowInfo.lamports, lamportsFunded);
+    assert.equal(escrowInfo.lamports, rentExempt + escrowAmount.toNumber());
+
+    const escrowAccount = await program.account.escrow.fetch(escrowPda);
+    assert.equal(escrowAccount.amount.toString(), escrowAmount.toString());
+    console.log("✓ Excess swept, escrowed amount intact");
+  });
+
+  it("Requires approvals from each required category", async () => {
+    // Approvers 1 and 2 are buyer-side (0), approver 3 is seller-side (1)
+    const escrowPda = await createEscrow(new anchor.BN(0.05 * LAMPORTS_PER_SOL), "Test categories", {
+      thirdApprover: approver3.publicKey,
+      terms: { categories: [0, 0, 1], requiredCategories: 0b11 },
+    });
+
+    const approve = (approver: Keypair) =>
+      program.methods
+        .approveRelease()
+        .accounts({
+          escrow: escrowPda,
+          approver: approver.publicKey,
+          beneficiary: beneficiary.publicKey,
+          systemProgram: SystemProgram.programId,
+          creatorStats: creatorStatsPda(creator.publicKey),
+        })
+        .signers([approver])
+        .rpc();
+
+    await approve(approver1);
+    await approve(approver2);
+
+    // Enough approvals, but both from the buyer side
+    let escrowAccount = await program.account.escrow.fetch(escrowPda);
+    assert.equal(escrowAccount.approvals.length, 2);
+    assert.equal(escrowAccount.isCompleted, false);
+
+    await approve(approver3);
+
+    escrowAccount = await program.account.escrow.fetch(escrowPda);
+    assert.equal(escrowAccount.isCompleted, true);
+    console.log("✓ Release waited for a seller-side approval");
+  });
+
+  it("Refuses release to a missing beneficiary when required", async () => {
+    const missingBeneficiary = Keypair.generate();
+    const escrowPda = await createEscrow(new anchor.BN(0.05 * LAMPORTS_PER_SOL), "Test missing beneficiary", {
+      escrowBeneficiary: missingBeneficiary.publicKey,
+      terms: { requireExistingBeneficiary: true },
+    });
+
+    const approve = (approver: Keypair) =>
+      program.methods
+        .approveRelease()
+        .accounts({
+          escrow: escrowPda,
+          approver: approver.publicKey,
+          beneficiary: missingBeneficiary.publicKey,
+          systemProgram: SystemProgram.programId,
+          creatorStats: creatorStatsPda(creator.publicKey),
+        })
+        .signers([approver])
+        .rpc();
+
+    await approve(approver1);
+    try {
+      await approve(approver2);
+      assert.fail("Should have thrown error");
+    } catch (error) {
+      expect(error.message).to.include("BeneficiaryNotFound");
+    }
+
+    const escrowAccount = await program.account.escrow.fetch(escrowPda);
+    assert.equal(escrowAccount.isCompleted, false);
+    assert.isNull(await provider.connection.getAccountInfo(missingBeneficiary.publicKey));
+    console.log("✓ Release blocked for a beneficiary that does not exist");
+  });
+
+  it("Merges two escrows with identical terms", async () => {
+    const escrowPda = await createEscrow(new anchor.BN(50), "Test merge escrows");
+    const mergedPda = await createEscrow(new anchor.BN(50), "Test merge escrows");
+    const otherPda = await createEscrow(new anchor.BN(50), "Test merge escrows", {
+      thirdApprover: approver3.publicKey,
+    });
+
+    const merge = (merged: PublicKey) =>
+      program.methods
+        .mergeEscrows()
+        .accounts({
+          creator: creator.publicKey,
+          escrow: escrowPda,
+          mergedEscrow: merged,
+          creatorStats: creatorStatsPda(creator.publicKey),
+        })
+        .signers([creator])
+        .rpc();
+
+    try {
+      await merge(otherPda);
+      assert.fail("Should have thrown error");
+    } catch (error) {
+      expect(error.message).to.include("IncompatibleEscrows");
+    }
+
+    const keptLamportsBefore = await provider.connection.getBalance(escrowPda);
+    await merge(mergedPda);
+
+    const escrowAccount = await program.account.escrow.fetch(escrowPda);
+    assert.equal(escrowAccount.amount.toString(), "100");
+    assert.equal(escrowAccount.approvals.length, 0);
+    assert.equal(await provider.connection.getBalance(escrowPda), keptLamportsBefore + 50);
+    assert.isNull(await provider.connection.getAccountInfo(mergedPda));
+    console.log("✓ Two 50-lamport escrows merged into one");
+  });
+
+  it("Enforces the configured description limit", async () => {
+    const setLimit = (maxDescriptionLen: number) =>
+      program.methods
+        .updateConfig(null, maxDescriptionLen, null, null, null, null)
+        .accounts({ authority: provider.wallet.publicKey, counter: counterPda })
+        .rpc();
+
+    // Nothing above the absolute ceiling can be configured
+    try {
+      await setLimit(201);
+      assert.fail("Should have thrown error");
+    } catch (error) {
+      expect(error.message).to.include("invalid instruction data");
+    }
+
+    const allocations = [];
+    for (const limit of [10, 50]) {
+      await setLimit(limit);
+
+      const escrowPda = await createEscrow(new anchor.BN(0.01 * LAMPORTS_PER_SOL), "x".repeat(limit));
+      const escrowAccount = await program.account.escrow.fetch(escrowPda);
+      assert.equal(escrowAccount.description.length, limit);
+      allocations.push((await provider.connection.getAccountInfo(escrowPda)).data.length);
+
+      try {
+        await createEscrow(new anchor.BN(0.01 * LAMPORTS_PER_SOL), "x".repeat(limit + 1));
+        assert.fail("Should have thrown error");
+      } catch (error) {
+        expect(error.message).to.include("invalid instruction data");
+      }
+    }
+
+    // Accounts are sized for the configured limit rather than the ceiling
+    assert.equal(allocations[1] - allocations[0], 40);
+
+    await setLimit(200);
+    console.log("✓ Description limit enforced at two configured boundaries");
+  });
+
+  it("Requires every consenter in a batched cancel to sign", async () => {
+    const escrowPda = await createEscrow(new anchor.BN(0.05 * LAMPORTS_PER_SOL), "Test batched consent");
+
+    await program.methods
+      .approveRelease()
+      .accounts({
+        escrow: escrowPda,
+        approver: approver1.publicKey,
+        beneficiary: beneficiary.publicKey,
+        systemProgram: SystemProgram.programId,
+        creatorStats: creatorStatsPda(creator.publicKey),
+      })
+      .signers([approver1])
+      .rpc();
+
+    const consentCancel = (approverSigns: boolean) =>
+      program.methods
+        .consentCancel(1)
+        .accounts({
+          consenter: creator.publicKey,
+          escrow: escrowPda,
+          refundAccount: creator.publicKey,
+          creatorStats: creatorStatsPda(creator.publicKey),
+        })
+        .remainingAccounts([
+          { pubkey: approver1.publicKey, isSigner: approverSigns, isWritable: false },
+        ])
+        .signers(approverSigns ? [creator, approver1] : [creator])
+        .rpc();
+
+    // Listing the approver without its signature must not count as consent
+    try {
+      await consentCancel(false);
+      assert.fail("Should have thrown error");
+    } catch (error) {
+      expect(error.message).to.include("missing required signature");
+    }
+
+    let escrowAccount = await program.account.escrow.fetch(escrowPda);
+    assert.equal(escrowAccount.cancelApprovals.length, 0);
+
+    await consentCancel(true);
+
+    escrowAccount = await program.account.escrow.fetch(escrowPda);
+    assert.equal(escrowAccount.isCompleted, true);
+    console.log("✓ Unsigned consenter rejected, signed batch cancelled");
+  });
+
+  it("Attaches delivery proofs from the beneficiary", async () => {
+    const escrowPda = await createEscrow(new anchor.BN(0.01 * LAMPORTS_PER_SOL), "Test delivery proofs");
+
+    // Borsh layout of `ProofAttachedEvent`
+    const attachProof = async (proofHash: number[]) => {
+      const signature = await program.methods
+        .attachProof(proofHash)
+        .accounts({ beneficiary: beneficiary.publicKey, escrow: escrowPda })
+        .signers([beneficiary])
+        .rpc({ commitment: "confirmed" });
+      const tx = await provider.connection.getTransaction(signature, {
+        commitment: "confirmed",
+        maxSupportedTransactionVersion: 0,
+      });
+      const dataLog = tx.meta.logMessages.find((log) => log.startsWith("Program data: "));
+      const event = Buffer.from(dataLog.slice("Program data: ".length), "base64");
+      return {
+        escrowId: new anchor.BN(event.subarray(0, 8), "le"),
+        proofHash: Array.from(event.subarray(8, 40)),
+        proofs: event.readUInt8(40),
+      };
+    };
+
+    const first = Array.from({ length: 32 }, (_, i) => i);
+    const second = Array.from({ length: 32 }, (_, i) => 255 - i);
+
+    let event = await attachProof(first);
+    assert.deepEqual(event.proofHash, first);
+    assert.equal(event.proofs, 1);
+
+    event = await attachProof(second);
+    assert.deepEqual(event.proofHash, second);
+    assert.equal(event.proofs, 2);
+
+    const escrowAccount = await program.account.escrow.fetch(escrowPda);
+    assert.equal(event.escrowId.toString(), escrowAccount.id.toString());
+    assert.deepEqual(escrowAccount.proofs, [first, second]);
+
+    // Only the beneficiary may attach evidence
+    try {
+      await program.methods
+        .attachProof(first)
+        .accounts({ beneficiary: creator.publicKey, escrow: escrowPda })
+        .signers([creator])
+        .rpc();
+      assert.fail("Should have thrown error");
+    } catch (error) {
+      expect(error.message).to.include("Unauthorized");
+    }
+
+    console.log("✓ Two proofs attached and read back in order");
+  });
+
+  it("Creates escrows with client nonces without touching the counter", async () => {
+    const randomNonce = () => Array.from({ length: 8 }, () => Math.floor(Math.random() * 256));
+
+    const createWithNonce = (nonce: number[], description: string) => {
+      const [escrowPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("escrow"), creator.publicKey.toBuffer(), Buffer.from(nonce)],
+        program.programId
+      );
+      const tx = program.methods
+        .createEscrowWithNonce(
+          nonce,
+          new anchor.BN(0.01 * LAMPORTS_PER_SOL),
+          beneficiary.publicKey,
+          approver1.publicKey,
+          approver2.publicKey,
+          null,
+          description,
+          escrowOptions()
+        )
+        .accounts({
+          escrow: escrowPda,
+          counter: counterPda,
+          creator: creator.publicKey,
+          systemProgram: SystemProgram.programId,
+          creatorStats: creatorStatsPda(creator.publicKey),
+          beneficiaryIndex: beneficiaryIndexPda(beneficiary.publicKey),
+        })
+        .signers([creator])
+        .rpc();
+      return { escrowPda, tx };
+    };
+
+    const counterBefore = await program.account.escrowCounter.fetch(counterPda);
+
+    // Neither create writes the counter, so both land without serializing on it
+    const nonceA = randomNonce();
+    const nonceB = randomNonce();
+    const a = createWithNonce(nonceA, "Test client nonce A");
+    const b = createWithNonce(nonceB, "Test client nonce B");
+    await Promise.all([a.tx, b.tx]);
+
+    const accountA = await program.account.escrow.fetch(a.escrowPda);
+    const accountB = await program.account.escrow.fetch(b.escrowPda);
+    assert.equal(accountA.id.toString(), new anchor.BN(nonceA, "le").toString());
+    assert.equal(accountB.id.toString(), new anchor.BN(nonceB, "le").toString());
+    assert.equal(accountA.creatorScoped, true);
+
+    const counterAfter = await program.account.escrowCounter.fetch(counterPda);
+    assert.equal(counterAfter.count.toString(), counterBefore.count.toString());
+
+    // A reused nonce collides with the existing escrow account
+    try {
+      await createWithNonce(nonceA, "Test client nonce reuse").tx;
+      assert.fail("Should have thrown error");
+    } catch (error) {
+      // System program `AccountAlreadyInUse`
+      expect(error.message).to.include("custom program error: 0x0");
+    }
+    const reused = await program.account.escrow.fetch(a.escrowPda);
+    assert.equal(reused.description, "Test client nonce A");
+
+    console.log("✓ Concurrent client-nonce escrows created without the counter");
+  });
+
+  it("Stores an approver's comment with their approval", async () => {
+    const escrowPda = await createEscrow(new anchor.BN(0.01 * LAMPORTS_PER_SOL), "Test approval comment", {
+      thirdApprover: approver3.publicKey,
+    });
+
+    const signature = await program.methods
+      .approveWithComment("approved pending invoice")
+      .accounts({
+        escrow: escrowPda,
+        approver: approver1.publicKey,
+        beneficiary: beneficiary.publicKey,
+        systemProgram: SystemProgram.programId,
+        creatorStats: creatorStatsPda(creator.publicKey),
+      })
+      .signers([approver1])
+      .rpc({ commitment: "confirmed" });
+
+    const escrowAccount = await program.account.escrow.fetch(escrowPda);
+    assert.equal(escrowAccount.approvals.length, 1);
+    assert.equal(escrowAccount.approvalComments.length, 1);
+    assert.equal(
+      escrowAccount.approvalComments[0].approver.toString(),
+      escrowAccount.approvals[0].approver.toString()
+    );
+    assert.equal(escrowAccount.approvalComments[0].comment, "approved pending invoice");
+
+    // Borsh layout of `ApprovalEvent`
+    const tx = await provider.connection.getTransaction(signature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    const dataLog = tx.meta.logMessages.find((log) => log.startsWith("Program data: "));
+    const event = Buffer.from(dataLog.slice("Program data: ".length), "base64");
+    assert.equal(new PublicKey(event.subarray(8, 40)).toString(), approver1.publicKey.toString());
+    const commentLen = event.readUInt32LE(48);
+    assert.equal(event.subarray(52, 52 + commentLen).toString(), "approved pending invoice");
+
+    // Comments are capped
+    try {
+      await program.methods
+        .approveWithComment("x".repeat(65))
+        .accounts({
+          escrow: escrowPda,
+          approver: approver2.publicKey,
+          beneficiary: beneficiary.publicKey,
+          systemProgram: SystemProgram.programId,
+          creatorStats: creatorStatsPda(creator.publicKey),
+        })
+        .signers([approver2])
+        .rpc();
+      assert.fail("Should have thrown error");
+    } catch (error) {
+      expect(error.message).to.include("invalid instruction data");
+    }
+
+    console.log("✓ Approval comment stored and emitted");
+  });
+
+  it("Lets only the counter authority force-refund an escrow", async () => {
+    const escrowAmount = new anchor.BN(0.05 * LAMPORTS_PER_SOL);
+    const escrowPda = await createEscrow(escrowAmount, "Test emergency recovery", {
+      thirdApprover: approver3.publicKey,
+    });
+
+    await program.methods
+      .approveRelease()
+      .accounts({
+        escrow: escrowPda,
+        approver: approver1.publicKey,
+        beneficiary: beneficiary.publicKey,
+        systemProgram: SystemProgram.programId,
+        creatorStats: creatorStatsPda(creator.publicKey),
+      })
+      .signers([approver1])
+      .rpc();
+
+    const recover = (authority: PublicKey) =>
+      program.methods.emergencyRecover().accounts({
+        authority,
+        counter: counterPda,
+        escrow: escrowPda,
+        creator: creator.publicKey,
+        creatorStats: creatorStatsPda(creator.publicKey),
+      });
+
+    // The creator is not the counter authority
+    try {
+      await recover(creator.publicKey).signers([creator]).rpc();
+      assert.fail("Should have thrown error");
+    } catch (error) {
+      expect(error.message).to.include("Unauthorized");
+    }
+
+    const balanceBefore = await provider.connection.getBalance(creator.publicKey);
+    const signature = await recover(provider.wallet.publicKey).rpc({ commitment: "confirmed" });
+    const balanceAfter = await provider.connection.getBalance(creator.publicKey);
+    assert.equal(balanceAfter - balanceBefore, escrowAmount.toNumber());
+
+    const escrowAccount = await program.account.escrow.fetch(escrowPda);
+    assert.equal(escrowAccount.isCompleted, true);
+
+    // Borsh layout of `EmergencyRecoveryEvent`
+    const tx = await provider.connection.getTransaction(signature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    assert.isTrue(tx.meta.logMessages.some((log) => log.includes("ADMIN: escrow")));
+    const dataLog = tx.meta.logMessages.find(isEventLog);
+    const event = Buffer.from(dataLog.slice("Program data: ".length), "base64");
+    assert.equal(new anchor.BN(event.subarray(0, 8), "le").toString(), escrowAccount.id.toString());
+    assert.equal(new PublicKey(event.subarray(8, 40)).toString(), provider.wallet.publicKey.toString());
+    assert.equal(new PublicKey(event.subarray(40, 72)).toString(), creator.publicKey.toString());
+    assert.equal(new anchor.BN(event.subarray(72, 80), "le").toString(), escrowAmount.toString());
+    assert.equal(event.readUInt8(80), 1);
+
+    console.log("✓ Emergency recovery restricted to the authority and logged");
+  });
+
+  it("Funds a new escrow with its amount plus rent", async () => {
+    const escrowAmount = new anchor.BN(0.02 * LAMPORTS_PER_SOL);
+    const escrowPda = await createEscrow(escrowAmount, "Test funding invariant");
+
+    const accountInfo = await provider.connection.getAccountInfo(escrowPda);
+    const rent = await provider.connection.getMinimumBalanceForRentExemption(accountInfo.data.length);
+    assert.isAtLeast(accountInfo.lamports, escrowAmount.toNumber() + rent);
+    console.log("✓ Escrow balance covers amount and rent");
+  });
+
+  it("Reports a release blocked by the minimum age", async () => {
+    const escrowPda = await createEscrow(new anchor.BN(0.01 * LAMPORTS_PER_SOL), "Test release status", {
+      terms: { minAgeSecs: new anchor.BN(3600) },
+    });
+
+    // Borsh layout of `ReleaseStatus`
+    const releaseStatus = async () => {
+      const simulation = await program.methods
+        .escrowReleaseStatus()
+        .accounts({ escrow: escrowPda })
+        .simulate();
+      const dataLog = simulation.raw.find((log) => log.startsWith("Program data: "));
+      const status = Buffer.from(dataLog.slice("Program data: ".length), "base64");
+      return {
+        releasableNow: status.readUInt8(8) === 1,
+        remainingWeight: status.readUInt32LE(9),
+        blockedByMinAge: status.readUInt8(13) === 1,
+        blockedByDelay: status.readUInt8(14) === 1,
+      };
+    };
+
+    let status = await releaseStatus();
+    assert.equal(status.releasableNow, false);
+    assert.equal(status.remainingWeight, 2);
+    assert.equal(status.blockedByMinAge, true);
+
+    for (const approver of [approver1, approver2]) {
+      await program.methods
+        .approveRelease()
+        .accounts({
+          escrow: escrowPda,
+          approver: approver.publicKey,
+          beneficiary: beneficiary.publicKey,
+          systemProgram: SystemProgram.programId,
+          creatorStats: creatorStatsPda(creator.publicKey),
+        })
+        .signers([approver])
+        .rpc();
+    }
+
+    // Quorum is in, yet the minimum age still holds the funds
+    status = await releaseStatus();
+    assert.equal(status.releasableNow, false);
+    assert.equal(status.remainingWeight, 0);
+    assert.equal(status.blockedByMinAge, true);
+    assert.equal(status.blockedByDelay, false);
+    console.log("✓ Release status reports the minimum age block");
+  });
+
+  it("Splits a consent cancel between penalty and refund", async () => {
+    const escrowAmount = new anchor.BN(0.04 * LAMPORTS_PER_SOL);
+
+    // More than 100% is rejected
+    try {
+      await createEscrow(escrowAmount, "Test invalid penalty", {
+        terms: { cancelPenaltyBps: 10001 },
+      });
+      assert.fail("Should have thrown error");
+    } catch (error) {
+      expect(error.message).to.include("custom program error");
+    }
+
+    const escrowPda = await createEscrow(escrowAmount, "Test cancel penalty", {
+      thirdApprover: approver3.publicKey,
+      terms: { cancelPenaltyBps: 2500 },
+    });
+
+    await program.methods
+      .approveRelease()
+      .accounts({
+        escrow: escrowPda,
+        approver: approver1.publicKey,
+        beneficiary: beneficiary.publicKey,
+        systemProgram: SystemProgram.programId,
+        creatorStats: creatorStatsPda(creator.publicKey),
+      })
+      .signers([approver1])
+      .rpc();
+
+    const creatorBefore = await provider.connection.getBalance(creator.publicKey);
+    const beneficiaryBefore = await provider.connection.getBalance(beneficiary.publicKey);
+
+    await program.methods
+      .consentCancel(1)
+      .accounts({
+        consenter: approver1.publicKey,
+        escrow: escrowPda,
+        refundAccount: creator.publicKey,
+        creatorStats: creatorStatsPda(creator.publicKey),
+      })
+      .remainingAccounts([
+        { pubkey: creator.publicKey, isSigner: true, isWritable: true },
+        { pubkey: beneficiary.publicKey, isSigner: false, isWritable: true },
+      ])
+      .signers([approver1, creator])
+      .rpc();
+
+    const penalty = escrowAmount.toNumber() / 4;
+    const creatorAfter = await provider.connection.getBalance(creator.publicKey);
+    const beneficiaryAfter = await provider.connection.getBalance(beneficiary.publicKey);
+    assert.equal(beneficiaryAfter - beneficiaryBefore, penalty);
+    assert.equal(creatorAfter - creatorBefore, escrowAmount.toNumber() - penalty);
+
+    const escrowAccount = await program.account.escrow.fetch(escrowPda);
+    assert.equal(escrowAccount.isCompleted, true);
+    console.log("✓ Cancel penalty paid to the beneficiary, remainder refunded");
+  });
+
+  it("Lists approvers with their approval state and the quorum", async () => {
+    const escrowPda = await createEscrow(new anchor.BN(0.01 * LAMPORTS_PER_SOL), "Test approver matrix", {
+      thirdApprover: approver3.publicKey,
+    });
+
+    await program.methods
+      .approveRelease()
+      .accounts({
+        escrow: escrowPda,
+        approver: approver2.publicKey,
+        beneficiary: beneficiary.publicKey,
+        systemProgram: SystemProgram.programId,
+        creatorStats: creatorStatsPda(creator.publicKey),
+      })
+      .signers([approver2])
+      .rpc();
+
+    const simulation = await program.methods.listApprovers().accounts({ escrow: escrowPda }).simulate();
+    const dataLog = simulation.raw.find((log) => log.startsWith("Program data: "));
+    const matrix = Buffer.from(dataLog.slice("Program data: ".length), "base64");
+
+    // Borsh layout of `ApproverMatrix`
+    const count = matrix.readUInt32LE(8);
+    const entries = [];
+    for (let i = 0; i < count; i++) {
+      const offset = 12 + i * 36;
+      entries.push({
+        approver: new PublicKey(matrix.subarray(offset, offset + 32)).toString(),
+        approved: matrix.readUInt8(offset + 32) === 1,
+        weight: matrix.readUInt16LE(offset + 33),
+        category: matrix.readUInt8(offset + 35),
+      });
+    }
+    const requiredWeight = matrix.readUInt32LE(12 + count * 36);
+    const approvedWeight = matrix.readUInt32LE(16 + count * 36);
+
+    assert.deepEqual(
+      entries.map((entry) => [entry.approver, entry.approved, entry.weight]),
+      [
+        [approver1.publicKey.toString(), false, 1],
+        [approver2.publicKey.toString(), true, 1],
+        [approver3.publicKey.toString(), false, 1],
+      ]
+    );
+    assert.equal(requiredWeight, 2);
+    assert.equal(approvedWeight, 1);
+    console.log("✓ Approver matrix matches the partially-approved escrow");
+  });
+
+  it("Force-finalizes a stuck escrow to the beneficiary under legacy rules", async () => {
+    const arbiter = Keypair.generate();
+    const escrowAmount = new anchor.BN(0.03 * LAMPORTS_PER_SOL);
+    // A year-long release delay is a term the escrow will not meet in time
+    const escrowPda = await createEscrow(escrowAmount, "Test force finalize", {
+      terms: { arbiter: arbiter.publicKey, releaseDelaySecs: new anchor.BN(365 * 24 * 3600) },
+    });
+
+    for (const approver of [approver1, approver2]) {
+      await program.methods
+        .approveRelease()
+        .accounts({
+          escrow: escrowPda,
+          approver: approver.publicKey,
+          beneficiary: beneficiary.publicKey,
+          systemProgram: SystemProgram.programId,
+          creatorStats: creatorStatsPda(creator.publicKey),
+        })
+        .signers([approver])
+        .rpc();
+    }
+
+    let escrowAccount = await program.account.escrow.fetch(escrowPda);
+    assert.equal(escrowAccount.isCompleted, false);
+
+    const forceFinalize = (signers: Keypair[]) =>
+      program.methods
+        .forceFinalize()
+        .accounts({
+          creator: creator.publicKey,
+          arbiter: arbiter.publicKey,
+          escrow: escrowPda,
+          beneficiary: beneficiary.publicKey,
+          refundAccount: creator.publicKey,
+          creatorStats: creatorStatsPda(creator.publicKey),
+        })
+        .signers(signers)
+        .rpc();
+
+    // The creator alone cannot force an outcome
+    try {
+      await forceFinalize([creator]);
+      assert.fail("Should have thrown error");
+    } catch (error) {
+      expect(error.message).to.include("Signature verification failed");
+    }
+
+    const beneficiaryBefore = await provider.connection.getBalance(beneficiary.publicKey);
+    await forceFinalize([creator, arbiter]);
+    const beneficiaryAfter = await provider.connection.getBalance(beneficiary.publicKey);
+    assert.equal(beneficiaryAfter - beneficiaryBefore, escrowAmount.toNumber());
+
+    escrowAccount = await program.account.escrow.fetch(escrowPda);
+    assert.equal(escrowAccount.isCompleted, true);
+    console.log("✓ Stuck escrow force-finalized to the beneficiary");
+  });
+
+  it("Raises the approval threshold with unanimous consent", async () => {
+    const escrowPda = await createEscrow(new anchor.BN(0.02 * LAMPORTS_PER_SOL), "Test change threshold", {
+      thirdApprover: approver3.publicKey,
+    });
+
+    const changeThreshold = (approvers: Keypair[]) =>
+      program.methods
+        .changeThreshold(3)
+        .accounts({ creator: creator.publicKey, escrow: escrowPda })
+        .remainingAccounts(
+          approvers.map((approver) => ({ pubkey: approver.publicKey, isSigner: true, isWritable: false }))
+        )
+        .signers([creator, ...approvers])
+        .rpc();
+
+    // Every approver must sign
+    try {
+      await changeThreshold([approver1, approver2]);
+      assert.fail("Should have thrown error");
+    } catch (error) {
+      expect(error.message).to.include("Unauthorized");
+    }
+
+    await changeThreshold([approver1, approver2, approver3]);
+
+    const approve = (approver: Keypair) =>
+      program.methods
+        .approveRelease()
+        .accounts({
+          escrow: escrowPda,
+          approver: approver.publicKey,
+          beneficiary: beneficiary.publicKey,
+          systemProgram: SystemProgram.programId,
+          creatorStats: creatorStatsPda(creator.publicKey),
+        })
+        .signers([approver])
+        .rpc();
+
+    // Two approvals used to be enough
+    await approve(approver1);
+    await approve(approver2);
+    let escrowAccount = await program.account.escrow.fetch(escrowPda);
+    assert.equal(escrowAccount.threshold, 3);
+    assert.equal(escrowAccount.isCompleted, false);
+
+    await approve(approver3);
+    escrowAccount = await program.account.escrow.fetch(escrowPda);
+    assert.equal(escrowAccount.isCompleted, true);
+    console.log("✓ Raised threshold required all three approvals");
+  });
+
+  it("Blocks cancellation until the cancel lock elapses", async () => {
+    const escrowPda = await createEscrow(new anchor.BN(0.01 * LAMPORTS_PER_SOL), "Test cancel lock", {
+      terms: { cancelLockSecs: new anchor.BN(3) },
+    });
+
+    const cancel = () =>
+      program.methods
+        .cancelEscrow()
+        .accounts({
+          escrow: escrowPda,
+          creator: creator.publicKey,
+          systemProgram: SystemProgram.programId,
+          creatorStats: creatorStatsPda(creator.publicKey),
+        })
+        .signers([creator])
+        .rpc();
+
+    try {
+      await cancel();
+      assert.fail("Should have thrown error");
+    } catch (error) {
+      expect(error.message).to.include("CancelLocked");
+    }
+
+    await new Promise((resolve) => setTimeout(resolve, 4000));
+    await cancel();
+
+    const escrowAccount = await program.account.escrow.fetch(escrowPda);
+    assert.equal(escrowAccount.isCompleted, true);
+    console.log("✓ Cancel rejected inside the lock and accepted after it");
+  });
+
+  it("Holds release until the beneficiary acknowledges", async () => {
+    const escrowAmount = new anchor.BN(0.02 * LAMPORTS_PER_SOL);
+    const escrowPda = await createEscrow(escrowAmount, "Test beneficiary acknowledgment", {
+      terms: { requireAck: true },
+    });
+
+    for (const approver of [approver1, approver2]) {
+      await program.methods
+        .approveRelease()
+        .accounts({
+          escrow: escrowPda,
+          approver: approver.publicKey,
+          beneficiary: beneficiary.publicKey,
+          systemProgram: SystemProgram.programId,
+          creatorStats: creatorStatsPda(creator.publicKey),
+        })
+        .signers([approver])
+        .rpc();
+    }
+
+    // Quorum is in, but nothing moves without the acknowledgment
+    let escrowAccount = await program.account.escrow.fetch(escrowPda);
+    assert.equal(escrowAccount.approvals.length, 2);
+    assert.equal(escrowAccount.isCompleted, false);
+    assert.equal(escrowAccount.releasableAt.toNumber(), 0);
+
+    await program.methods
+      .acknowledgeEscrow()
+      .accounts({ beneficiary: beneficiary.publicKey, escrow: escrowPda })
+      .signers([beneficiary])
+      .rpc();
+
+    const beneficiaryBefore = await provider.connection.getBalance(beneficiary.publicKey);
+    await program.methods
+      .finalizeRelease()
+      .accounts({
+        escrow: escrowPda,
+        beneficiary: beneficiary.publicKey,
+        creatorStats: creatorStatsPda(creator.publicKey),
+      })
+      .rpc();
+    const beneficiaryAfter = await provider.connection.getBalance(beneficiary.publicKey);
+    assert.equal(beneficiaryAfter - beneficiaryBefore, escrowAmount.toNumber());
+
+    escrowAccount = await program.account.escrow.fetch(escrowPda);
+    assert.equal(escrowAccount.beneficiaryAcknowledged, true);
+    assert.equal(escrowAccount.isCompleted, true);
+    console.log("✓ Release held until the beneficiary acknowledged");
+  });
+
+  it("Returns the counter's current count", async () => {
+    const getCount = async (): Promise<anchor.BN> => {
+      const signature = await program.methods
+        .getCounter()
+        .accounts({ counter: counterPda })
+        .rpc({ commitment: "confirmed" });
+      const tx = await provider.connection.getTransaction(signature, {
+        commitment: "confirmed",
+        maxSupportedTransactionVersion: 0,
+      });
+      const [returnData] = tx.meta.returnData.data;
+      // Borsh layout of `EscrowCounter`, `count` first
+      return new anchor.BN(Buffer.from(returnData, "base64").subarray(0, 8), "le");
+    };
+
+    const before = await getCount();
+    for (let i = 0; i < 3; i++) {
+      await createEscrow(new anchor.BN(0.01 * LAMPORTS_PER_SOL), `Test get counter ${i}`);
+    }
+    const after = await getCount();
+
+    const counterAccount = await program.account.escrowCounter.fetch(counterPda);
+    assert.equal(after.sub(before).toNumber(), 3);
+    assert.equal(after.toString(), counterAccount.count.toString());
+    console.log("✓ Counter count read from return data");
+  });
+
+  it("Releases a 5-of-7 escrow on the fifth approval", async () => {
+    const approver3 = Keypair.generate();
+    const extraApprovers = Array.from({ length: 4 }, () => Keypair.generate());
+    const escrowAmount = new anchor.BN(0.01 * LAMPORTS_PER_SOL);
+    const escrowPda = await createEscrow(escrowAmount, "Test five of seven", {
+      thirdApprover: approver3.publicKey,
+      terms: { extraApprovers: extraApprovers.map((a) => a.publicKey), threshold: 5 },
+    });
+
+    const approve = (approver: Keypair) =>
+      program.methods
+        .approveRelease()
+        .accounts({
+          escrow: escrowPda,
+          approver: approver.publicKey,
+          beneficiary: beneficiary.publicKey,
+          systemProgram: SystemProgram.programId,
+          creatorStats: creatorStatsPda(creator.publicKey),
+        })
+        .signers([approver])
+        .rpc();
+
+    for (const approver of [approver1, approver2, ...extraApprovers.slice(0, 2)]) {
+      await approve(approver);
+    }
+    let escrowAccount = await program.account.escrow.fetch(escrowPda);
+    assert.equal(escrowAccount.extraApprovers.length, 4);
+    assert.equal(escrowAccount.approvals.length, 4);
+    assert.equal(escrowAccount.isCompleted, false);
+
+    const beneficiaryBefore = await provider.connection.getBalance(beneficiary.publicKey);
+    await approve(extraApprovers[3]);
+    const beneficiaryAfter = await provider.connection.getBalance(beneficiary.publicKey);
+    assert.equal(beneficiaryAfter - beneficiaryBefore, escrowAmount.toNumber());
+
+    escrowAccount = await program.account.escrow.fetch(escrowPda);
+    assert.equal(escrowAccount.isCompleted, true);
+    console.log("✓ Fifth of seven approvers released the escrow");
+  });
+
+  it("Returns the reason code from every cancellation path", async () => {
+    // Borsh layout of `CancellationReceipt`: escrow id, reason, refunded lamports
+    const receiptOf = async (signature: string) => {
+      const tx = await provider.connection.getTransaction(signature, {
+        commitment: "confirmed",
+        maxSupportedTransactionVersion: 0,
+      });
+      const data = Buffer.from(tx.meta.returnData.data[0], "base64");
+      return {
+        escrowId: new anchor.BN(data.subarray(0, 8), "le"),
+        reason: data.readUInt8(8),
+        refunded: new anchor.BN(data.subarray(9, 17), "le"),
+      };
+    };
+    const escrowAmount = new anchor.BN(0.01 * LAMPORTS_PER_SOL);
+    const cancel = (escrowPda: PublicKey) =>
+      program.methods
+        .cancelEscrow()
+        .accounts({
+          escrow: escrowPda,
+          creator: creator.publicKey,
+          systemProgram: SystemProgram.programId,
+          creatorStats: creatorStatsPda(creator.publicKey),
+        })
+        .signers([creator])
+        .rpc({ commitment: "confirmed" });
+    const approve = (escrowPda: PublicKey, approver: Keypair) =>
+      program.methods
+        .approveRelease()
+        .accounts({
+          escrow: escrowPda,
+          approver: approver.publicKey,
+          beneficiary: beneficiary.publicKey,
+          systemProgram: SystemProgram.programId,
+          creatorStats: creatorStatsPda(creator.publicKey),
+        })
+        .signers([approver])
+        .rpc();
+
+    // 0: creator cancel
+    let escrowPda = await createEscrow(escrowAmount, "Test reason creator");
+    let receipt = await receiptOf(await cancel(escrowPda));
+    let escrowAccount = await program.account.escrow.fetch(escrowPda);
+    assert.equal(receipt.escrowId.toString(), escrowAccount.id.toString());
+    assert.equal(receipt.reason, 0);
+    assert.equal(receipt.refunded.toString(), escrowAmount.toString());
+
+    // 1: cancel after the beneficiary declined, even with an approval standing
+    escrowPda = await createEscrow(escrowAmount, "Test reason declined");
+    await approve(escrowPda, approver1);
+    await program.methods
+      .declineEscrow()
+      .accounts({ escrow: escrowPda, beneficiary: beneficiary.publicKey })
+      .signers([beneficiary])
+      .rpc();
+    receipt = await receiptOf(await cancel(escrowPda));
+    assert.equal(receipt.reason, 1);
+
+    // 2: consent of the creator and the approver that approved
+    escrowPda = await createEscrow(escrowAmount, "Test reason consent", {
+      thirdApprover: approver3.publicKey,
+    });
+    await approve(escrowPda, approver1);
+    const signature = await program.methods
+      .consentCancel(1)
+      .accounts({
+        consenter: creator.publicKey,
+        escrow: escrowPda,
+        refundAccount: creator.publicKey,
+        creatorStats: creatorStatsPda(creator.publicKey),
+      })
+      .remainingAccounts([{ pubkey: approver1.publicKey, isSigner: true, isWritable: false }])
+      .signers([creator, approver1])
+      .rpc({ commitment: "confirmed" });
+    receipt = await receiptOf(signature);
+    assert.equal(receipt.reason, 2);
+
+    // 3: arbiter-backed force finalize short of quorum refunds as a dispute
+    const arbiter = Keypair.generate();
+    escrowPda = await createEscrow(escrowAmount, "Test reason dispute", {
+      terms: { arbiter: arbiter.publicKey },
+    });
+    receipt = await receiptOf(
+      await program.methods
+        .forceFinalize()
+        .accounts({
+          creator: creator.publicKey,
+          arbiter: arbiter.publicKey,
+          escrow: escrowPda,
+          beneficiary: beneficiary.publicKey,
+          refundAccount: creator.publicKey,
+          creatorStats: creatorStatsPda(creator.publicKey),
+        })
+        .signers([creator, arbiter])
+        .rpc({ commitment: "confirmed" })
+    );
+    assert.equal(receipt.reason, 3);
+
+    // 4: emergency recovery by the counter authority
+    escrowPda = await createEscrow(escrowAmount, "Test reason emergency");
+    receipt = await receiptOf(
+      await program.methods
+        .emergencyRecover()
+        .accounts({
+          authority: provider.wallet.publicKey,
+          counter: counterPda,
+          escrow: escrowPda,
+          creator: creator.publicKey,
+          creatorStats: creatorStatsPda(creator.publicKey),
+        })
+        .rpc({ commitment: "confirmed" })
+    );
+    assert.equal(receipt.reason, 4);
+    console.log("✓ Each cancellation path returned its reason code");
+  });
+
+  it("Refuses to reclaim an escrow that still holds its funds", async () => {
+    const escrowAmount = new anchor.BN(0.01 * LAMPORTS_PER_SOL);
+    const escrowPda = await createEscrow(escrowAmount, "Test reclaim funded");
+    const escrowAccount = await program.account.escrow.fetch(escrowPda);
+
+    try {
+      await program.methods
+        .reclaimFailedCreate(escrowAccount.id)
+        .accounts({
+          creator: creator.publicKey,
+          escrow: escrowPda,
+        })
+        .signers([creator])
+        .rpc();
+      assert.fail("Should have thrown error");
+    } catch (error) {
+      expect(error.message).to.include("Invalid escrow account");
+    }
+
+    const balance = await provider.connection.getBalance(escrowPda);
+    assert.isAtLeast(balance, escrowAmount.toNumber());
+    console.log("✓ Funded escrow cannot be reclaimed as a failed create");
+  });
+
+  it("Reclaims a forfeited escrow revived in the same transaction", async () => {
+    const escrowCreator = Keypair.generate();
+    await provider.connection.requestAirdrop(escrowCreator.publicKey, LAMPORTS_PER_SOL);
+    await new Promise((resolve) => setTimeout(resolve, 1000));
+
+    const escrowId = new anchor.BN(1);
+    const [escrowPda] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("escrow"),
+        escrowCreator.publicKey.toBuffer(),
+        escrowId.toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    );
+    await program.methods
+      .createEscrowV2(
+        new anchor.BN(0.01 * LAMPORTS_PER_SOL),
+        beneficiary.publicKey,
+        approver1.publicKey,
+        approver2.publicKey,
+        null,
+        "Test reclaim revived",
+        escrowOptions()
+      )
+      .accounts({
+        escrow: escrowPda,
+        counter: counterPda,
+        creator: escrowCreator.publicKey,
+        systemProgram: SystemProgram.programId,
+        creatorStats: creatorStatsPda(escrowCreator.publicKey),
+        beneficiaryIndex: beneficiaryIndexPda(beneficiary.publicKey),
+      })
+      .signers([escrowCreator])
+      .rpc();
+
+    // Forfeiting empties the account, and a transfer later in the same transaction
+    // keeps it alive: still owned by the program, with only zeroes for data
+    const revival = 0.005 * LAMPORTS_PER_SOL;
+    await program.methods
+      .forfeitEscrow()
+      .accounts({
+        creator: escrowCreator.publicKey,
+        beneficiary: beneficiary.publicKey,
+        escrow: escrowPda,
+        incinerator: INCINERATOR,
+        creatorStats: creatorStatsPda(escrowCreator.publicKey),
+      })
+      .postInstructions([
+        SystemProgram.transfer({
+          fromPubkey: escrowCreator.publicKey,
+          toPubkey: escrowPda,
+          lamports: revival,
+        }),
+      ])
+      .signers([escrowCreator, beneficiary])
+      .rpc();
+    const revived = await provider.connection.getAccountInfo(escrowPda);
+    assert.isTrue(revived.owner.equals(program.programId));
+    assert.isTrue(revived.data.every((b) => b === 0));
+
+    const creatorBefore = await provider.connection.getBalance(escrowCreator.publicKey);
+    await program.methods
+      .reclaimFailedCreate(escrowId)
+      .accounts({ creator: escrowCreator.publicKey, escrow: escrowPda })
+      .signers([escrowCreator])
+      .rpc();
+
+    assert.isNull(await provider.connection.getAccountInfo(escrowPda));
+    const creatorAfter = await provider.connection.getBalance(escrowCreator.publicKey);
+    // The creator pays the transaction fee out of the reclaimed lamports
+    assert.isAbove(creatorAfter, creatorBefore + revival - 10_000);
+    console.log("✓ Revived escrow account returned to its creator");
+  });
+
+  it("Reports which approvers can still approve", async () => {
+    const escrowAmount = new anchor.BN(0.01 * LAMPORTS_PER_SOL);
+    const escrowPda = await createEscrow(escrowAmount, "Test can approve", {
+      thirdApprover: approver3.publicKey,
+    });
+    await program.methods
+      .approveRelease()
+      .accounts({
+        escrow: escrowPda,
+        approver: approver1.publicKey,
+        beneficiary: beneficiary.publicKey,
+        systemProgram: SystemProgram.programId,
+        creatorStats: creatorStatsPda(creator.publicKey),
+      })
+      .signers([approver1])
+      .rpc();
+
+    const canApprove = async (approver: PublicKey): Promise<boolean> => {
+      const signature = await program.methods
+        .canApprove(approver)
+        .accounts({ escrow: escrowPda })
+        .rpc({ commitment: "confirmed" });
+      const tx = await provider.connection.getTransaction(signature, {
+        commitment: "confirmed",
+        maxSupportedTransactionVersion: 0,
+      });
+      return Buffer.from(tx.meta.returnData.data[0], "base64").readUInt8(0) === 1;
+    };
+
+    assert.equal(await canApprove(approver1.publicKey), false);
+    assert.equal(await canApprove(approver2.publicKey), true);
+    assert.equal(await canApprove(beneficiary.publicKey), false);
+    console.log("✓ Only pending approvers can approve");
+  });
+
+  it("Requires the executor's signature to release", async () => {
+    const executor = Keypair.generate();
+    const escrowAmount = new anchor.BN(0.01 * LAMPORTS_PER_SOL);
+    const escrowPda = await createEscrow(escrowAmount, "Test executor", {
+      terms: { executor: executor.publicKey },
+    });
+
+    for (const approver of [approver1, approver2]) {
+      await program.methods
+        .approveRelease()
+        .accounts({
+          escrow: escrowPda,
+          approver: approver.publicKey,
+          beneficiary: beneficiary.publicKey,
+          systemProgram: SystemProgram.programId,
+          creatorStats: creatorStatsPda(creator.publicKey),
+        })
+        .signers([approver])
+        .rpc();
+    }
+
+    // Quorum alone does not pay out an escrow with an executor
+    let escrowAccount = await program.account.escrow.fetch(escrowPda);
+    assert.equal(escrowAccount.isCompleted, false);
+
+    const finalize = (executorSigns: boolean) =>
+      program.methods
+        .finalizeRelease()
+        .accounts({
+          escrow: escrowPda,
+          beneficiary: beneficiary.publicKey,
+          creatorStats: creatorStatsPda(creator.publicKey),
+        })
+        .remainingAccounts([
+          { pubkey: executor.publicKey, isSigner: executorSigns, isWritable: false },
+        ])
+        .signers(executorSigns ? [executor] : [])
+        .rpc();
+
+    try {
+      await finalize(false);
+      assert.fail("Should have thrown error");
+    } catch (error) {
+      expect(error.message).to.include("missing required signature");
+    }
+
+    const beneficiaryBefore = await provider.connection.getBalance(beneficiary.publicKey);
+    await finalize(true);
+    const beneficiaryAfter = await provider.connection.getBalance(beneficiary.publicKey);
+    assert.equal(beneficiaryAfter - beneficiaryBefore, escrowAmount.toNumber());
+
+    escrowAccount = await program.account.escrow.fetch(escrowPda);
+    assert.equal(escrowAccount.isCompleted, true);
+    console.log("✓ Release waited for the executor's signature");
+  });
+
+  it("Sweeps expired escrows and skips the one still running", async () => {
+    const escrowAmount = new anchor.BN(0.01 * LAMPORTS_PER_SOL);
+    const expired = [
+      await createEscrow(escrowAmount, "Test sweep expired 1", {
+        terms: { expirySecs: new anchor.BN(1) },
+      }),
+      await createEscrow(escrowAmount, "Test sweep expired 2", {
+        terms: { expirySecs: new anchor.BN(1) },
+      }),
+    ];
+    const running = await createEscrow(escrowAmount, "Test sweep running", {
+      terms: { expirySecs: new anchor.BN(3600) },
+    });
+    await new Promise((resolve) => setTimeout(resolve, 3000));
+
+    const creatorBefore = await provider.connection.getBalance(creator.publicKey);
+    const signature = await program.methods
+      .sweepExpired()
+      .remainingAccounts([
+        { pubkey: counterPda, isSigner: false, isWritable: false },
+        { pubkey: provider.wallet.publicKey, isSigner: false, isWritable: true },
+        ...[...expired, running].flatMap((escrow) => [
+          { pubkey: escrow, isSigner: false, isWritable: true },
+          { pubkey: creator.publicKey, isSigner: false, isWritable: true },
+          { pubkey: creator.publicKey, isSigner: false, isWritable: true },
+          { pubkey: creatorStatsPda(creator.publicKey), isSigner: false, isWritable: true },
+        ]),
+      ])
+      .rpc({ commitment: "confirmed" });
+    const creatorAfter = await provider.connection.getBalance(creator.publicKey);
+    assert.isAtLeast(creatorAfter - creatorBefore, 2 * escrowAmount.toNumber());
+
+    for (const escrow of expired) {
+      assert.isNull(await provider.connection.getAccountInfo(escrow));
+    }
+    const runningAccount = await program.account.escrow.fetch(running);
+    assert.equal(runningAccount.isCompleted, false);
+
+    const tx = await provider.connection.getTransaction(signature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    assert.isTrue(tx.meta.logMessages.some((log) => log.includes("not expired")));
+    assert.isTrue(tx.meta.logMessages.some((log) => log.includes("Swept 2 of 3 escrows")));
+    console.log("✓ Expired escrows swept, running escrow left alone");
+  });
+
+  it("Leaves an expired escrow that is pending release to its beneficiary", async () => {
+    const escrowPda = await createEscrow(new anchor.BN(0.01 * LAMPORTS_PER_SOL), "Test sweep hold", {
+      terms: { expirySecs: new anchor.BN(1), releaseDelaySecs: new anchor.BN(3600) },
+    });
+    for (const approver of [approver1, approver2]) {
+      await program.methods
+        .approveRelease()
+        .accounts({
+          escrow: escrowPda,
+          approver: approver.publicKey,
+          beneficiary: beneficiary.publicKey,
+          systemProgram: SystemProgram.programId,
+          creatorStats: creatorStatsPda(creator.publicKey),
+        })
+        .signers([approver])
+        .rpc();
+    }
+    await new Promise((resolve) => setTimeout(resolve, 3000));
+
+    const signature = await program.methods
+      .sweepExpired()
+      .remainingAccounts([
+        { pubkey: counterPda, isSigner: false, isWritable: false },
+        { pubkey: provider.wallet.publicKey, isSigner: false, isWritable: true },
+        { pubkey: escrowPda, isSigner: false, isWritable: true },
+        { pubkey: creator.publicKey, isSigner: false, isWritable: true },
+        { pubkey: creator.publicKey, isSigner: false, isWritable: true },
+        { pubkey: creatorStatsPda(creator.publicKey), isSigner: false, isWritable: true },
+      ])
+      .rpc({ commitment: "confirmed" });
+
+    const tx = await provider.connection.getTransaction(signature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    assert.isTrue(tx.meta.logMessages.some((log) => log.includes("pending release")));
+    const escrowAccount = await program.account.escrow.fetch(escrowPda);
+    assert.equal(escrowAccount.isCompleted, false);
+    assert.notEqual(escrowAccount.releasableAt.toNumber(), 0);
+    console.log("✓ Approved escrow kept for its beneficiary");
+  });
+
+  it("Sweeps an expired escrow to its refund_to account", async () => {
+    const escrowAmount = new anchor.BN(0.01 * LAMPORTS_PER_SOL);
+    const refundTo = Keypair.generate().publicKey;
+    const escrowPda = await createEscrow(escrowAmount, "Test sweep refund_to", {
+      terms: { refundTo, expirySecs: new anchor.BN(1) },
+    });
+    await new Promise((resolve) => setTimeout(resolve, 3000));
+
+    const sweep = (refundAccount: PublicKey) =>
+      program.methods
+        .sweepExpired()
+        .remainingAccounts([
+          { pubkey: counterPda, isSigner: false, isWritable: false },
+          { pubkey: provider.wallet.publicKey, isSigner: false, isWritable: true },
+          { pubkey: escrowPda, isSigner: false, isWritable: true },
+          { pubkey: creator.publicKey, isSigner: false, isWritable: true },
+          { pubkey: refundAccount, isSigner: false, isWritable: true },
+          { pubkey: creatorStatsPda(creator.publicKey), isSigner: false, isWritable: true },
+        ])
+        .rpc({ commitment: "confirmed" });
+
+    // The creator is not the refund recipient here, so the escrow is skipped
+    await sweep(creator.publicKey);
+    assert.isNotNull(await provider.connection.getAccountInfo(escrowPda));
+
+    const escrowBalance = await provider.connection.getBalance(escrowPda);
+    const creatorBefore = await provider.connection.getBalance(creator.publicKey);
+    const signature = await sweep(refundTo);
+    const creatorAfter = await provider.connection.getBalance(creator.publicKey, "confirmed");
+
+    // Borsh layout of `CancellationReceipt`, logged per swept escrow: 5 is `Expiry`
+    const tx = await provider.connection.getTransaction(signature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    const receipt = Buffer.from(
+      tx.meta.logMessages.find(isEventLog).slice("Program data: ".length),
+      "base64"
+    );
+    assert.equal(receipt.length, 17);
+    assert.equal(receipt.readUInt8(8), 5);
+    assert.equal(new anchor.BN(receipt.subarray(9, 17), "le").toNumber(), escrowAmount.toNumber());
+
+    // The deposit goes to refund_to, the rent back to the creator
+    assert.equal(
+      await provider.connection.getBalance(refundTo, "confirmed"),
+      escrowAmount.toNumber()
+    );
+    assert.equal(creatorAfter - creatorBefore, escrowBalance - escrowAmount.toNumber());
+    assert.isNull(await provider.connection.getAccountInfo(escrowPda));
+    console.log("✓ Expiry refund paid to refund_to");
+  });
+
+  it("Rejects an arbiter that holds another role", async () => {
+    const overlaps: [string, PublicKey][] = [
+      ["creator", creator.publicKey],
+      ["beneficiary", beneficiary.publicKey],
+      ["approver", approver2.publicKey],
+      ["third approver", approver3.publicKey],
+    ];
+    for (const [role, arbiter] of overlaps) {
+      try {
+        await createEscrow(new anchor.BN(0.01 * LAMPORTS_PER_SOL), `Test arbiter as ${role}`, {
+          thirdApprover: approver3.publicKey,
+          terms: { arbiter },
+        });
+        assert.fail(`Arbiter as ${role} should have thrown error`);
+      } catch (error) {
+        expect(error.message).to.include("RoleConflict");
+      }
+    }
+
+    const arbiter = Keypair.generate();
+    const escrowPda = await createEscrow(new anchor.BN(0.01 * LAMPORTS_PER_SOL), "Test arbiter", {
+      thirdApprover: approver3.publicKey,
+      terms: { arbiter: arbiter.publicKey },
+    });
+    const escrowAccount = await program.account.escrow.fetch(escrowPda);
+    assert.equal(escrowAccount.arbiter.toString(), arbiter.publicKey.toString());
+    console.log("✓ Arbiter kept distinct from every other role");
+  });
+
+  it("Logs the escrow state right before release and cancel", async () => {
+    const snapshotOf = async (signature: string): Promise<Buffer> => {
+      const tx = await provider.connection.getTransaction(signature, {
+        commitment: "confirmed",
+        maxSupportedTransactionVersion: 0,
+      });
+      const snapshotLog = tx.meta.logMessages.find((log) => log.startsWith(SNAPSHOT_LOG));
+      return Buffer.from(snapshotLog.slice(SNAPSHOT_LOG.length), "base64");
+    };
+    // The account is allocated with headroom, so the Borsh state is a prefix of its data
+    const assertSnapshot = async (escrowPda: PublicKey, snapshot: Buffer, before: Buffer) => {
+      assert.isTrue(snapshot.equals(before.subarray(0, snapshot.length)));
+      const after = (await provider.connection.getAccountInfo(escrowPda)).data;
+      assert.isFalse(snapshot.equals(after.subarray(0, snapshot.length)));
+    };
+
+    // Release: with an executor, FinalizeRelease settles an escrow left untouched since quorum
+    const executor = Keypair.generate();
+    const escrowAmount = new anchor.BN(0.01 * LAMPORTS_PER_SOL);
+    let escrowPda = await createEscrow(escrowAmount, "Test release snapshot", {
+      terms: { executor: executor.publicKey },
+    });
+    for (const approver of [approver1, approver2]) {
+      await program.methods
+        .approveRelease()
+        .accounts({
+          escrow: escrowPda,
+          approver: approver.publicKey,
+          beneficiary: beneficiary.publicKey,
+          systemProgram: SystemProgram.programId,
+          creatorStats: creatorStatsPda(creator.publicKey),
+        })
+        .signers([approver])
+        .rpc();
+    }
+    let before = (await provider.connection.getAccountInfo(escrowPda)).data;
+    let signature = await program.methods
+      .finalizeRelease()
+      .accounts({
+        escrow: escrowPda,
+        beneficiary: beneficiary.publicKey,
+        creatorStats: creatorStatsPda(creator.publicKey),
+      })
+      .remainingAccounts([{ pubkey: executor.publicKey, isSigner: true, isWritable: false }])
+      .signers([executor])
+      .rpc({ commitment: "confirmed" });
+    await assertSnapshot(escrowPda, await snapshotOf(signature), before);
+
+    // Cancel
+    escrowPda = await createEscrow(escrowAmount, "Test cancel snapshot");
+    before = (await provider.connection.getAccountInfo(escrowPda)).data;
+    signature = await program.methods
+      .cancelEscrow()
+      .accounts({
+        escrow: escrowPda,
+        creator: creator.publicKey,
+        systemProgram: SystemProgram.programId,
+        creatorStats: creatorStatsPda(creator.publicKey),
+      })
+      .signers([creator])
+      .rpc({ commitment: "confirmed" });
+    await assertSnapshot(escrowPda, await snapshotOf(signature), before);
+    console.log("✓ Settlement snapshots match the escrow before completion");
+  });
+
+  it("Rejects approvals with too few or extra accounts distinctly", async () => {
+    const escrowAmount = new anchor.BN(0.01 * LAMPORTS_PER_SOL);
+    const escrowPda = await createEscrow(escrowAmount, "Test approve accounts");
+    const approve = () =>
+      program.methods.approveRelease().accounts({
+        escrow: escrowPda,
+        approver: approver1.publicKey,
+        beneficiary: beneficiary.publicKey,
+        systemProgram: SystemProgram.programId,
+        creatorStats: creatorStatsPda(creator.publicKey),
+      });
+
+    // Only the approver, escrow and beneficiary
+    const ix = await approve().instruction();
+    try {
+      await provider.sendAndConfirm(
+        new anchor.web3.Transaction().add(
+          new anchor.web3.TransactionInstruction({
+            programId: program.programId,
+            keys: ix.keys.slice(0, 3),
+            data: ix.data,
+          })
+        ),
+        [approver1]
+      );
+      assert.fail("Should have thrown error");
+    } catch (error) {
+      expect(error.message).to.include("insufficient account keys");
+    }
+
+    try {
+      await approve()
+        .remainingAccounts([{ pubkey: approver2.publicKey, isSigner: false, isWritable: false }])
+        .signers([approver1])
+        .rpc();
+      assert.fail("Should have thrown error");
+    } catch (error) {
+      expect(error.message).to.include("UnexpectedAccounts");
+    }
+
+    const escrowAccount = await program.account.escrow.fetch(escrowPda);
+    assert.equal(escrowAccount.approvals.length, 0);
+    console.log("✓ Missing and extra accounts rejected with distinct errors");
+  });
+
+  it("Groups escrows under a shared parent agreement", async () => {
+    const groupId = Array.from(Keypair.generate().publicKey.toBytes());
+    const escrowAmount = new anchor.BN(0.01 * LAMPORTS_PER_SOL);
+    const bundled = [
+      await createEscrow(escrowAmount, "Test group 1", { terms: { groupId } }),
+      await createEscrow(escrowAmount, "Test group 2", { terms: { groupId } }),
+    ];
+    const loose = await createEscrow(escrowAmount, "Test no group");
+
+    for (const escrowPda of bundled) {
+      const escrowAccount = await program.account.escrow.fetch(escrowPda);
+      assert.deepEqual(Array.from(escrowAccount.groupId), groupId);
+    }
+    const looseAccount = await program.account.escrow.fetch(loose);
+    assert.isNull(looseAccount.groupId);
+    console.log("✓ Both escrows recorded the same group id");
+  });
+
+  it("Releases an escrow straight into a chained escrow", async () => {
+    const escrowAmount = new anchor.BN(0.02 * LAMPORTS_PER_SOL);
+    // The beneficiary as executor holds the payout until it chooses where it goes
+    const escrowPda = await createEscrow(escrowAmount, "Test release into", {
+      terms: { executor: beneficiary.publicKey },
+    });
+    for (const approver of [approver1, approver2]) {
+      await program.methods
+        .approveRelease()
+        .accounts({
+          escrow: escrowPda,
+          approver: approver.publicKey,
+          beneficiary: beneficiary.publicKey,
+          systemProgram: SystemProgram.programId,
+          creatorStats: creatorStatsPda(creator.publicKey),
+        })
+        .signers([approver])
+        .rpc();
+    }
+
+    const nextBeneficiary = Keypair.generate().publicKey;
+    const chainedPda = await nextEscrowPda();
+    await program.methods
+      .releaseInto(
+        nextBeneficiary,
+        approver1.publicKey,
+        approver2.publicKey,
+        null,
+        "Test chained stage",
+        escrowOptions()
+      )
+      .accounts({
+        escrow: escrowPda,
+        creatorStats: creatorStatsPda(creator.publicKey),
+        beneficiary: beneficiary.publicKey,
+        newEscrow: chainedPda,
+        counter: counterPda,
+        systemProgram: SystemProgram.programId,
+        beneficiaryStats: creatorStatsPda(beneficiary.publicKey),
+        beneficiaryIndex: beneficiaryIndexPda(nextBeneficiary),
+      })
+      .remainingAccounts([{ pubkey: beneficiary.publicKey, isSigner: true, isWritable: false }])
+      .signers([beneficiary])
+      .rpc();
+
+    const released = await program.account.escrow.fetch(escrowPda);
+    assert.equal(released.isCompleted, true);
+    const chained = await program.account.escrow.fetch(chainedPda);
+    assert.equal(chained.amount.toString(), escrowAmount.toString());
+    assert.equal(chained.creator.toString(), beneficiary.publicKey.toString());
+    assert.equal(chained.beneficiary.toString(), nextBeneficiary.toString());
+    assert.equal(chained.isCompleted, false);
+
+    // Each account keeps exactly its rent, plus the escrowed amount on the chained one
+    for (const [pda, held] of [
+      [escrowPda, 0],
+      [chainedPda, escrowAmount.toNumber()],
+    ] as [PublicKey, number][]) {
+      const info = await provider.connection.getAccountInfo(pda);
+      const rent = await provider.connection.getMinimumBalanceForRentExemption(info.data.length);
+      assert.equal(info.lamports, rent + held);
+    }
+    console.log("✓ Released funds seeded the chained escrow without leaking lamports");
+  });
+
+  it("Rejects control characters in descriptions under strict mode", async () => {
+    const setStrict = (strict: boolean) =>
+      program.methods
+        .updateConfig(null, null, strict, null, null, null)
+        .accounts({ authority: provider.wallet.publicKey, counter: counterPda })
+        .rpc();
+    const escrowAmount = new anchor.BN(0.01 * LAMPORTS_PER_SOL);
+
+    // Off by default
+    const escrowPda = await createEscrow(escrowAmount, "Line one\nline two");
+    const escrowAccount = await program.account.escrow.fetch(escrowPda);
+    assert.equal(escrowAccount.description, "Line one\nline two");
+
+    await setStrict(true);
+    try {
+      for (const description of ["Line one\nline two", "Null\u0000byte"]) {
+        try {
+          await createEscrow(escrowAmount, description);
+          assert.fail("Should have thrown error");
+        } catch (error) {
+          expect(error.message).to.include("invalid instruction data");
+        }
+      }
+      await createEscrow(escrowAmount, "Plain description");
+    } finally {
+      await setStrict(false);
+    }
+    console.log("✓ Strict mode rejected newline and NUL descriptions");
+  });
+}
+  it("Rejects an escrow paying out to its own PDA", async () => {
+    const escrowPda = await nextEscrowPda();
+    try {
+      await createEscrow(new anchor.BN(0.01 * LAMPORTS_PER_SOL), "Test self beneficiary", {
+        escrowBeneficiary: escrowPda,
+      });
+      assert.fail("Should have thrown error");
+    } catch (error) {
+      expect(error.message).to.include("InvalidBeneficiary");
+    }
+    assert.isNull(await provider.connection.getAccountInfo(escrowPda));
+    console.log("✓ Escrow PDA refused as its own beneficiary");
+  });
+
+  it("Derives escrow addresses by id", async () => {
+    for (const id of [0, 1, 42, 1_000_000]) {
+      const escrowId = new anchor.BN(id);
+      const signature = await program.methods
+        .deriveEscrowAddress(escrowId)
+        .accounts({})
+        .rpc({ commitment: "confirmed" });
+      const tx = await provider.connection.getTransaction(signature, {
+        commitment: "confirmed",
+        maxSupportedTransactionVersion: 0,
+      });
+      const returnData = Buffer.from(tx.meta.returnData.data[0], "base64");
+
+      const [expectedPda, expectedBump] = PublicKey.findProgramAddressSync(
+        [Buffer.from("escrow"), escrowId.toArrayLike(Buffer, "le", 8)],
+        program.programId
+      );
+      assert.equal(returnData.length, 33);
+      assert.equal(new PublicKey(returnData.subarray(0, 32)).toString(), expectedPda.toString());
+      assert.equal(returnData.readUInt8(32), expectedBump);
+    }
+    console.log("✓ Derived escrow addresses match findProgramAddressSync");
+  });
+
+  it("Enforces approval order when requested", async () => {
+    const escrowPda = await createEscrow(new anchor.BN(0.01 * LAMPORTS_PER_SOL), "Test ordered", {
+      terms: { approvalOrder: true },
+    });
+    const approve = (approver: Keypair) =>
+      program.methods
+        .approveRelease()
+        .accounts({
+          escrow: escrowPda,
+          approver: approver.publicKey,
+          beneficiary: beneficiary.publicKey,
+          systemProgram: SystemProgram.programId,
+          creatorStats: creatorStatsPda(creator.publicKey),
+        })
+        .signers([approver])
+        .rpc();
+
+    try {
+      await approve(approver2);
+      assert.fail("Should have thrown error");
+    } catch (error) {
+      expect(error.message).to.include("OutOfOrderApproval");
+    }
+
+    await approve(approver1);
+    await approve(approver2);
+    const escrowAccount = await program.account.escrow.fetch(escrowPda);
+    assert.equal(escrowAccount.isCompleted, true);
+    console.log("✓ approver2 waited for approver1");
+  });
+
+  it("Forfeits an escrow to the incinerator with both parties signing", async () => {
+    const escrowAmount = new anchor.BN(0.01 * LAMPORTS_PER_SOL);
+    const escrowPda = await createEscrow(escrowAmount, "Test forfeit");
+    const forfeit = (sink: PublicKey, signers: Keypair[]) =>
+      program.methods
+        .forfeitEscrow()
+        .accounts({
+          creator: creator.publicKey,
+          beneficiary: beneficiary.publicKey,
+          escrow: escrowPda,
+          incinerator: sink,
+          creatorStats: creatorStatsPda(creator.publicKey),
+        })
+        .signers(signers)
+        .rpc();
+
+    try {
+      await forfeit(INCINERATOR, [creator]);
+      assert.fail("Should have thrown error");
+    } catch (error) {
+      expect(error.message).to.include("Signature verification failed");
+    }
+    try {
+      await forfeit(creator.publicKey, [creator, beneficiary]);
+      assert.fail("Should have thrown error");
+    } catch (error) {
+      expect(error.message).to.include("InvalidIncinerator");
+    }
+
+    const escrowInfo = await provider.connection.getAccountInfo(escrowPda);
+    const rent = escrowInfo.lamports - escrowAmount.toNumber();
+    const creatorBefore = await provider.connection.getBalance(creator.publicKey);
+    const beneficiaryBefore = await provider.connection.getBalance(beneficiary.publicKey);
+    await forfeit(INCINERATOR, [creator, beneficiary]);
+
+    assert.isNull(await provider.connection.getAccountInfo(escrowPda));
+    // The creator pays the fee, so it gets back at most the rent and never the amount
+    const creatorAfter = await provider.connection.getBalance(creator.publicKey);
+    assert.isAtMost(creatorAfter - creatorBefore, rent);
+    assert.isAtMost(
+      await provider.connection.getBalance(beneficiary.publicKey),
+      beneficiaryBefore
+    );
+    console.log("✓ Forfeited lamports left both parties");
+  });
+
+  it("Logs why an unauthorized approval failed", async () => {
+    const escrowPda = await createEscrow(
+      new anchor.BN(0.01 * LAMPORTS_PER_SOL),
+      "Test diagnostics"
+    );
+    const outsider = Keypair.generate();
+    try {
+      await program.methods
+        .approveRelease()
+        .accounts({
+          escrow: escrowPda,
+          approver: outsider.publicKey,
+          beneficiary: beneficiary.publicKey,
+          systemProgram: SystemProgram.programId,
+          creatorStats: creatorStatsPda(creator.publicKey),
+        })
+        .signers([outsider])
+        .rpc();
+      assert.fail("Should have thrown error");
+    } catch (error) {
+      expect(error.message).to.include("Unauthorized");
+      const expected = `Approver not authorized: ${outsider.publicKey.toString()}`;
+      assert.isTrue(error.logs.some((log: string) => log.includes(expected)));
+    }
+    console.log("✓ Failed approval logged the unauthorized signer");
+  });
+
+  it("Bounds escrow expiry by the configured horizon", async () => {
+    const escrowAmount = new anchor.BN(0.01 * LAMPORTS_PER_SOL);
+    const setHorizon = (maxExpirySecs: anchor.BN) =>
+      program.methods
+        .updateConfig(null, null, null, maxExpirySecs, null, null)
+        .accounts({ authority: provider.wallet.publicKey, counter: counterPda })
+        .rpc();
+    const expectRejected = async (expirySecs: anchor.BN) => {
+      try {
+        await createEscrow(escrowAmount, "Test deadline", { terms: { expirySecs } });
+        assert.fail("Should have thrown error");
+      } catch (error) {
+        expect(error.message).to.include("InvalidDeadline");
+      }
+    };
+
+    // A deadline before creation
+    await expectRejected(new anchor.BN(-1));
+
+    await setHorizon(new anchor.BN(3600));
+    try {
+      await expectRejected(new anchor.BN(3601));
+      await createEscrow(escrowAmount, "Test deadline", {
+        terms: { expirySecs: new anchor.BN(3600) },
+      });
+    } finally {
+      await setHorizon(new anchor.BN(365 * 24 * 60 * 60));
+    }
+    console.log("✓ Past and over-horizon deadlines rejected");
+  });
+
+  it("Returns the existing escrow for a repeated idempotency key", async () => {
+    const idempotencyKey = Array.from(anchor.utils.bytes.utf8.encode("retry-key-000001"));
+    const [markerPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("idempotency"), creator.publicKey.toBuffer(), Buffer.from(idempotencyKey)],
+      program.programId
+    );
+    const create = async (): Promise<anchor.BN> => {
+      const signature = await program.methods
+        .createEscrow(
+          new anchor.BN(0.01 * LAMPORTS_PER_SOL),
+          beneficiary.publicKey,
+          approver1.publicKey,
+          approver2.publicKey,
+          null,
+          "Test idempotent create",
+          escrowOptions({ idempotencyKey })
+        )
+        .accounts({
+          escrow: await nextEscrowPda(),
+          counter: counterPda,
+          creator: creator.publicKey,
+          systemProgram: SystemProgram.programId,
+          creatorStats: creatorStatsPda(creator.publicKey),
+          beneficiaryIndex: beneficiaryIndexPda(beneficiary.publicKey),
+        })
+        .remainingAccounts([{ pubkey: markerPda, isSigner: false, isWritable: true }])
+        .signers([creator])
+        .rpc({ commitment: "confirmed" });
+      const tx = await provider.connection.getTransaction(signature, {
+        commitment: "confirmed",
+        maxSupportedTransactionVersion: 0,
+      });
+      return new anchor.BN(Buffer.from(tx.meta.returnData.data[0], "base64"), "le");
+    };
+
+    const first = await create();
+    const countAfterFirst = (await program.account.escrowCounter.fetch(counterPda)).count;
+    const second = await create();
+    const countAfterSecond = (await program.account.escrowCounter.fetch(counterPda)).count;
+
+    assert.equal(second.toString(), first.toString());
+    assert.equal(countAfterSecond.toString(), countAfterFirst.toString());
+    console.log("✓ Retried create returned escrow", first.toString());
+  });
+
+  it("Leaves a new escrow account rent-exempt", async () => {
+    const escrowAmount = new anchor.BN(0.01 * LAMPORTS_PER_SOL);
+    const escrowPda = await createEscrow(escrowAmount, "Test rent exemption");
+
+    const info = await provider.connection.getAccountInfo(escrowPda);
+    const rent = await provider.connection.getMinimumBalanceForRentExemption(info.data.length);
+    assert.isAtLeast(info.lamports - escrowAmount.toNumber(), rent);
+    console.log("✓ Escrow account holds its amount plus the rent-exempt minimum");
+  });
+
+  it("Fails a cancel bundled after an in-transaction release", async () => {
+    const escrowAmount = new anchor.BN(0.01 * LAMPORTS_PER_SOL);
+    const escrowPda = await createEscrow(escrowAmount, "Test release then cancel");
+    const approve = (approver: Keypair) =>
+      program.methods
+        .approveRelease()
+        .accounts({
+          escrow: escrowPda,
+          approver: approver.publicKey,
+          beneficiary: beneficiary.publicKey,
+          systemProgram: SystemProgram.programId,
+          creatorStats: creatorStatsPda(creator.publicKey),
+        })
+        .instruction();
+    const cancel = program.methods
+      .cancelEscrow()
+      .accounts({
+        creator: creator.publicKey,
+        escrow: escrowPda,
+        systemProgram: SystemProgram.programId,
+        creatorStats: creatorStatsPda(creator.publicKey),
+      })
+      .instruction();
+
+    const beneficiaryBefore = await provider.connection.getBalance(beneficiary.publicKey);
+    try {
+      await provider.sendAndConfirm(
+        new anchor.web3.Transaction().add(
+          await approve(approver1),
+          await approve(approver2),
+          await cancel
+        ),
+        [approver1, approver2, creator]
+      );
+      assert.fail("Should have thrown error");
+    } catch (error) {
+      // EscrowCompleted
+      expect(error.message).to.include("custom program error: 0x5");
+    }
+
+    // The whole transaction rolled back, release included
+    const escrowAccount = await program.account.escrow.fetch(escrowPda);
+    assert.equal(escrowAccount.isCompleted, false);
+    assert.equal(escrowAccount.approvals.length, 0);
+    assert.equal(await provider.connection.getBalance(beneficiary.publicKey), beneficiaryBefore);
+    console.log("✓ Cancel after an in-transaction release failed cleanly");
+  });
+
+  it("Classifies active and terminal escrows", async () => {
+    const activePda = await createEscrow(new anchor.BN(0.01 * LAMPORTS_PER_SOL), "Test active");
+    const cancelledPda = await createEscrow(
+      new anchor.BN(0.01 * LAMPORTS_PER_SOL),
+      "Test cancelled"
+    );
+    await program.methods
+      .cancelEscrow()
+      .accounts({
+        escrow: cancelledPda,
+        creator: creator.publicKey,
+        systemProgram: SystemProgram.programId,
+        creatorStats: creatorStatsPda(creator.publicKey),
+      })
+      .signers([creator])
+      .rpc();
+
+    const signature = await program.methods
+      .classifyEscrows()
+      .remainingAccounts(
+        [activePda, cancelledPda, counterPda].map((pubkey) => ({
+          pubkey,
+          isSigner: false,
+          isWritable: false,
+        }))
+      )
+      .rpc({ commitment: "confirmed" });
+    const tx = await provider.connection.getTransaction(signature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    const returnData = Buffer.from(tx.meta.returnData.data[0], "base64");
+
+    // Borsh `Vec<EscrowClass>`: u32 length, then NotEscrow = 0, Active = 1, Terminal = 2
+    assert.equal(returnData.readUInt32LE(0), 3);
+    assert.deepEqual([...returnData.subarray(4)], [1, 2, 0]);
+    console.log("✓ Escrows classified as active, terminal and not an escrow");
+  });
+
+  it("Rate limits re-approvals by slot", async () => {
+    const escrowPda = await createEscrow(new anchor.BN(0.01 * LAMPORTS_PER_SOL), "Test rate", {
+      terms: { approvalTtlSecs: new anchor.BN(1), approvalRateLimitSlots: new anchor.BN(1000) },
+    });
+    const approve = () =>
+      program.methods
+        .approveRelease()
+        .accounts({
+          escrow: escrowPda,
+          approver: approver1.publicKey,
+          beneficiary: beneficiary.publicKey,
+          systemProgram: SystemProgram.programId,
+          creatorStats: creatorStatsPda(creator.publicKey),
+        })
+        .signers([approver1])
+        .rpc();
+
+    await approve();
+    // The approval expires after a second, well inside the 1000-slot window
+    await new Promise((resolve) => setTimeout(resolve, 2500));
+    try {
+      await approve();
+      assert.fail("Should have thrown error");
+    } catch (error) {
+      expect(error.message).to.include("ApprovalRateLimited");
+    }
+
+    const escrowAccount = await program.account.escrow.fetch(escrowPda);
+    assert.equal(escrowAccount.approvalSlots.length, 1);
+    assert.equal(
+      escrowAccount.approvalSlots[0].approver.toString(),
+      approver1.publicKey.toString()
+    );
+    console.log("✓ Re-approval inside the slot window rejected");
+  });
+
+  it("Takes the expiry fee for the treasury on a sweep refund", async () => {
+    const setFee = (expiryFeeBps: number) =>
+      program.methods
+        .updateConfig(null, null, null, null, expiryFeeBps, null)
+        .accounts({ authority: provider.wallet.publicKey, counter: counterPda })
+        .rpc();
+    const escrowAmount = new anchor.BN(0.1 * LAMPORTS_PER_SOL);
+    const escrowPda = await createEscrow(escrowAmount, "Test expiry fee", {
+      terms: { expirySecs: new anchor.BN(1) },
+    });
+    await new Promise((resolve) => setTimeout(resolve, 3000));
+
+    await setFee(250);
+    let signature: string;
+    const escrowBalance = await provider.connection.getBalance(escrowPda);
+    const creatorBefore = await provider.connection.getBalance(creator.publicKey);
+    try {
+      signature = await program.methods
+        .sweepExpired()
+        .remainingAccounts([
+          { pubkey: counterPda, isSigner: false, isWritable: false },
+          { pubkey: provider.wallet.publicKey, isSigner: false, isWritable: true },
+          { pubkey: escrowPda, isSigner: false, isWritable: true },
+          { pubkey: creator.publicKey, isSigner: false, isWritable: true },
+          { pubkey: creator.publicKey, isSigner: false, isWritable: true },
+          { pubkey: creatorStatsPda(creator.publicKey), isSigner: false, isWritable: true },
+        ])
+        .rpc({ commitment: "confirmed" });
+    } finally {
+      await setFee(0);
+    }
+    const tx = await provider.connection.getTransaction(signature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    const creatorAfter = await provider.connection.getBalance(creator.publicKey, "confirmed");
+    // The treasury also paid the transaction fee, at account index 0
+    const treasuryGain = tx.meta.postBalances[0] - tx.meta.preBalances[0] + tx.meta.fee;
+
+    // 2.5% of the deposit; the creator keeps the rest of the deposit and all of the rent
+    const fee = (escrowAmount.toNumber() * 250) / 10_000;
+    assert.equal(treasuryGain, fee);
+    assert.equal(creatorAfter - creatorBefore, escrowBalance - fee);
+    assert.isNull(await provider.connection.getAccountInfo(escrowPda));
+    console.log("✓ Expiry refund split between creator and treasury");
+  });
+
+  it("Refuses to split an approved escrow into a cancellable one", async () => {
+    const escrowPda = await createEscrow(new anchor.BN(100), "Test split approved");
+    await program.methods
+      .approveRelease()
+      .accounts({
+        escrow: escrowPda,
+        approver: approver1.publicKey,
+        beneficiary: beneficiary.publicKey,
+        systemProgram: SystemProgram.programId,
+        creatorStats: creatorStatsPda(creator.publicKey),
+      })
+      .signers([approver1])
+      .rpc();
+
+    const newEscrowPda = await nextEscrowPda();
+    try {
+      await program.methods
+        .splitEscrow(new anchor.BN(99))
+        .accounts({
+          creator: creator.publicKey,
+          escrow: escrowPda,
+          newEscrow: newEscrowPda,
+          counter: counterPda,
+          systemProgram: SystemProgram.programId,
+          creatorStats: creatorStatsPda(creator.publicKey),
+          beneficiaryIndex: beneficiaryIndexPda(beneficiary.publicKey),
+        })
+        .signers([creator])
+        .rpc();
+      assert.fail("Should have thrown error");
+    } catch (error) {
+      expect(error.message).to.include("CannotCancelAfterApprovals");
+    }
+
+    // No split-off escrow exists to cancel, and the approved original stays locked
+    assert.isNull(await provider.connection.getAccountInfo(newEscrowPda));
+    try {
+      await program.methods
+        .cancelEscrow()
+        .accounts({
+          escrow: newEscrowPda,
+          creator: creator.publicKey,
+          systemProgram: SystemProgram.programId,
+          creatorStats: creatorStatsPda(creator.publicKey),
+        })
+        .signers([creator])
+        .rpc();
+      assert.fail("Should have thrown error");
+    } catch (error) {
+      expect(error.message).to.not.include("Should have thrown error");
+    }
+    const original = await program.account.escrow.fetch(escrowPda);
+    assert.equal(original.amount.toString(), "100");
+    assert.equal(original.approvals.length, 1);
+    console.log("✓ Approved escrow could not be split and cancelled");
+  });
+
+  it("Refuses to release to anyone but the escrow's beneficiary", async () => {
+    const escrowAmount = new anchor.BN(0.01 * LAMPORTS_PER_SOL);
+    const escrowPda = await createEscrow(escrowAmount, "Test payee");
+    const approve = (approver: Keypair, payee: PublicKey) =>
+      program.methods
+        .approveRelease()
+        .accounts({
+          escrow: escrowPda,
+          approver: approver.publicKey,
+          beneficiary: payee,
+          systemProgram: SystemProgram.programId,
+          creatorStats: creatorStatsPda(creator.publicKey),
+        })
+        .signers([approver])
+        .rpc();
+
+    await approve(approver1, beneficiary.publicKey);
+    // The final approval triggers the release, so the payee is checked there
+    for (const payee of [Keypair.generate().publicKey, escrowPda]) {
+      try {
+        await approve(approver2, payee);
+        assert.fail("Should have thrown error");
+      } catch (error) {
+        expect(error.message).to.include("InvalidBeneficiary");
+      }
+    }
+
+    let escrowAccount = await program.account.escrow.fetch(escrowPda);
+    assert.equal(escrowAccount.isCompleted, false);
+    await approve(approver2, beneficiary.publicKey);
+    escrowAccount = await program.account.escrow.fetch(escrowPda);
+    assert.equal(escrowAccount.isCompleted, true);
+    console.log("✓ Release paid only the recorded beneficiary");
+  });
+
+  it("Gives a clone of an escrow near expiry its own deadline", async () => {
+    const sourcePda = await createEscrow(new anchor.BN(0.01 * LAMPORTS_PER_SOL), "Test expiry", {
+      terms: { expirySecs: new anchor.BN(3) },
+    });
+    await new Promise((resolve) => setTimeout(resolve, 2000));
+
+    const clonePda = await nextEscrowPda();
+    await program.methods
+      .cloneEscrow(new anchor.BN(0.01 * LAMPORTS_PER_SOL))
+      .accounts({
+        creator: creator.publicKey,
+        sourceEscrow: sourcePda,
+        newEscrow: clonePda,
+        counter: counterPda,
+        systemProgram: SystemProgram.programId,
+        creatorStats: creatorStatsPda(creator.publicKey),
+        beneficiaryIndex: beneficiaryIndexPda(beneficiary.publicKey),
+      })
+      .signers([creator])
+      .rpc();
+
+    const source = await program.account.escrow.fetch(sourcePda);
+    const clone = await program.account.escrow.fetch(clonePda);
+    assert.equal(clone.expiresAt.sub(clone.createdAt).toNumber(), 3);
+    assert.isTrue(clone.expiresAt.gt(source.expiresAt));
+    console.log("✓ Clone expires three seconds after its own creation");
+  });
+});